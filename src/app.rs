@@ -0,0 +1,451 @@
+use clap::Parser;
+use crate::{
+    apply_rules, batch_replace_files, config_schema, load_rules, write_completion, write_man_page, Cli, CodeParser,
+    Commands, CompareTool, Config, json_output_schema, posix_grep, ripgrep_args, time_external, translate_rg_args,
+    write_comparison, DoctorReport, FileWalker, OutputFormatter, PosixGrepOptions, SearchEngine, LANGUAGE_EXAMPLES,
+};
+use std::process;
+
+/// Entry point of the `cg` and `cg-rg` binaries; ripgrep-style arguments are translated first
+pub fn main_with_args(args: Vec<String>) {
+    let args = if args.iter().any(|arg| arg == "--rg-compat") {
+        translate_rg_args(args)
+    } else {
+        args
+    };
+    let cli = Cli::parse_from(args);
+    
+    // Files are searched on rayon's global pool, so it gets the thread count the walker uses
+    if let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(cli.effective_threads()).build_global() {
+        eprintln!("Warning: could not size the search thread pool: {}", e);
+    }
+    
+    // Exit status: 0 when something matched, 1 when nothing did, 2 on errors
+    match run(cli) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+/// Returns whether the search found anything; other modes report `true` on success
+fn run(mut cli: Cli) -> anyhow::Result<bool> {
+    // Handle subcommands
+    if let Some(command) = &cli.command {
+        return handle_subcommand(command, &cli);
+    }
+    
+    // Handle regex checking
+    if let Some(ref pattern) = cli.check_regex {
+        check_regex_pattern(pattern)?;
+        return Ok(true);
+    }
+    
+    // Print the schema of `--output json` without searching
+    if cli.print_json_schema {
+        println!("{}", serde_json::to_string_pretty(&json_output_schema())?);
+        return Ok(true);
+    }
+    
+    // Lint the config file; any issue makes the exit status 1
+    if cli.validate_config {
+        return validate_config(cli.config.as_deref());
+    }
+    
+    // Load configuration
+    let config = Config::find_and_load().unwrap_or_default();
+    
+    // List the files a search would cover; no pattern is taken, so a positional is a path
+    if cli.list_files {
+        if let Some(path) = cli.pattern.take() {
+            cli.paths.insert(0, path.into());
+        }
+        let walker = FileWalker::with_config(&cli, &config);
+        for path in walker.included_files(&cli)? {
+            println!("{}", path.display());
+        }
+        return Ok(true);
+    }
+    
+    cli.apply_saved_pattern(&config)?;
+    
+    // --todos brings its own pattern, so a positional is a path
+    if cli.todos {
+        if let Some(path) = cli.pattern.take() {
+            cli.paths.insert(0, path.into());
+        }
+    }
+    
+    // Main search functionality
+    if cli.pattern.is_none() && cli.and.is_empty() && cli.or.is_empty() && !cli.todos {
+        eprintln!("Error: No search pattern provided");
+        process::exit(2);
+    }
+    
+    if cli.dump_config {
+        let dump = cli.effective_config(&config, Config::find_config_path().as_deref());
+        eprintln!("{}", serde_json::to_string_pretty(&dump)?);
+    }
+    
+    if cli.verbose > 0 {
+        match Config::find_config_path() {
+            Some(path) => eprintln!("Config: {}", path.display()),
+            None => eprintln!("Config: none (using defaults)"),
+        }
+        let (threads, source) = cli.thread_count();
+        eprintln!("Using {} threads (from {})", threads, source);
+        if cli.paths.is_empty() {
+            eprintln!("Searching: .");
+        }
+        for path in &cli.paths {
+            eprintln!("Searching: {}", path.display());
+        }
+    }
+    
+    // Create search engine
+    let engine = SearchEngine::with_config(cli.clone(), &config)?;
+    
+    // Large result sets can be spilled to disk and streamed back for plain output
+    if let Some(threshold) = cli.spill_threshold {
+        if !cli.has_replacement() && !cli.split_results_by_file {
+            let (spool, stats) = engine.search_spooled(threshold)?;
+            engine.write_debug_matcher_log()?;
+            let mut formatter = OutputFormatter::open(cli)?;
+            formatter.print_spooled_results(spool, &stats)?;
+            return Ok(stats.files_with_matches > 0);
+        }
+    }
+    
+    // Perform search
+    let (file_matches, stats) = engine.search()?;
+    engine.write_debug_matcher_log()?;
+    
+    // Handle replacement if requested
+    if cli.has_replacement() {
+        // With --replace-cmd the replacement text comes from the command instead
+        let replacement = cli.replace.clone().unwrap_or_default();
+        let pattern_matcher = crate::PatternMatcher::new(&cli)?;
+        
+        let _replacement_results = batch_replace_files(
+            &file_matches,
+            &pattern_matcher,
+            &replacement,
+            &cli,
+        )?;
+        
+        if !cli.preview && !cli.interactive && !cli.writes_in_place() && !cli.count_replacements && !cli.output_as_patch {
+            println!("Note: Use --preview, --interactive, or --write to apply replacements");
+        }
+    } else {
+        // Regular search output
+        let mut formatter = OutputFormatter::open(cli.clone())?;
+        match cli.output_file {
+            Some(ref dir) if cli.split_results_by_file => {
+                formatter.write_split_results(dir, &file_matches, &stats)?;
+            }
+            _ => formatter.print_results(&file_matches, &stats)?,
+        }
+    }
+    
+    Ok(stats.files_with_matches > 0)
+}
+
+/// Runs a subcommand; `grep` (nothing matched) and `detect-language` (unknown language) can report `false`
+fn handle_subcommand(command: &Commands, cli: &Cli) -> anyhow::Result<bool> {
+    match command {
+        Commands::Config { path, default, write } => {
+            let config = if *default {
+                Config::default()
+            } else if let Some(config_path) = path {
+                Config::load_from_file(config_path)?
+            } else {
+                Config::find_and_load().unwrap_or_default()
+            };
+            if let Some(target) = write {
+                config.save_to_file(target)?;
+                println!("Wrote config to {}", target.display());
+            } else {
+                println!("{}", serde_yaml::to_string(&config)?);
+            }
+        }
+        Commands::ConfigSchema => {
+            println!("{}", serde_json::to_string_pretty(&config_schema()?)?);
+        }
+        Commands::Benchmark { pattern, path, iterations, compare } => {
+            run_benchmark(pattern, path.as_ref(), *iterations, *compare, cli)?;
+        }
+        Commands::Profile { pattern, path, output_profile } => {
+            run_profile(pattern, path.as_ref(), output_profile, cli)?;
+        }
+        Commands::Parse { file } => {
+            let parsed = CodeParser::new().parse_file(file)?;
+            println!("{}", serde_json::to_string_pretty(&parsed)?);
+        }
+        Commands::DetectLanguage { path } => {
+            let candidates = CodeParser::new().detect_language(path);
+            if candidates.is_empty() {
+                println!("{}: unknown", path.display());
+                return Ok(false);
+            }
+            for (language, confidence) in candidates {
+                println!("{}\t{:.2}", language, confidence);
+            }
+        }
+        Commands::ApplyRules { file, paths } => {
+            let rules = load_rules(file)?;
+            let outcomes = apply_rules(&rules, paths, cli)?;
+            for (index, outcome) in outcomes.iter().enumerate() {
+                println!(
+                    "Rule {} ({} -> {}): {} replacements in {} files",
+                    index + 1,
+                    outcome.pattern,
+                    outcome.replace,
+                    outcome.replacements,
+                    outcome.files
+                );
+            }
+            if !cli.preview && !cli.interactive && !cli.writes_in_place() && !cli.count_replacements && !cli.output_as_patch {
+                println!("Note: Use --preview, --interactive, or --write to apply replacements");
+            }
+        }
+        Commands::LangHelp { language } => {
+            show_language_help(language.as_deref());
+        }
+        Commands::Grep { pattern, files, count, line_number, invert_match, quiet, recursive } => {
+            let options = PosixGrepOptions {
+                pattern: pattern.clone(),
+                files: files.clone(),
+                count: *count,
+                line_number: *line_number,
+                invert_match: *invert_match,
+                quiet: *quiet,
+                recursive: *recursive,
+            };
+            let mut stdout = std::io::stdout().lock();
+            return Ok(posix_grep(&options, &mut std::io::stdin(), &mut stdout, &mut std::io::stderr())?);
+        }
+        Commands::Doctor => {
+            let config = Config::find_and_load().unwrap_or_default();
+            println!("{}", DoctorReport::collect(cli, &config));
+        }
+        Commands::Completion { shell } => {
+            write_completion(*shell, &mut std::io::stdout());
+        }
+        Commands::Man => {
+            write_man_page(&mut std::io::stdout())?;
+        }
+    }
+    Ok(true)
+}
+
+fn validate_config(path: Option<&std::path::Path>) -> anyhow::Result<bool> {
+    let Some(path) = path.map(|p| p.to_path_buf()).or_else(Config::find_config_path) else {
+        println!("No config file found");
+        return Ok(true);
+    };
+    
+    let issues = Config::parse_file(&path)?.validate();
+    for issue in &issues {
+        println!("{}: {}", path.display(), issue);
+    }
+    if issues.is_empty() {
+        println!("{}: OK", path.display());
+    }
+    Ok(issues.is_empty())
+}
+
+fn check_regex_pattern(pattern: &str) -> anyhow::Result<()> {
+    // Test with basic regex
+    match regex::Regex::new(pattern) {
+        Ok(_) => println!("✓ Valid basic regex pattern"),
+        Err(e) => println!("✗ Invalid basic regex: {}", e),
+    }
+    
+    // Test with fancy regex
+    match fancy_regex::Regex::new(pattern) {
+        Ok(_) => println!("✓ Valid fancy regex pattern"),
+        Err(e) => println!("✗ Invalid fancy regex: {}", e),
+    }
+    
+    Ok(())
+}
+
+fn run_benchmark(
+    pattern: &str,
+    path: Option<&std::path::PathBuf>,
+    iterations: usize,
+    compare: Option<CompareTool>,
+    cli: &Cli,
+) -> anyhow::Result<()> {
+    use std::time::Instant;
+    
+    let search_path = path.cloned().unwrap_or_else(|| ".".into());
+    
+    let benchmark_cli = Cli {
+        pattern: Some(pattern.to_string()),
+        paths: vec![search_path.clone()],
+        ..cli.clone()
+    };
+    
+    println!("Running benchmark: pattern='{}', iterations={}", pattern, iterations);
+    
+    let mut total_time = std::time::Duration::ZERO;
+    let mut total_files = 0;
+    let mut total_matches = 0;
+    let mut total_bytes = 0;
+    let mut total_lines = 0;
+    
+    for i in 1..=iterations {
+        let start = Instant::now();
+        let engine = SearchEngine::new(benchmark_cli.clone())?;
+        let (_file_matches, stats) = engine.search()?;
+        let elapsed = start.elapsed();
+        
+        total_time += elapsed;
+        total_files += stats.files_searched;
+        total_matches += stats.total_matches;
+        total_bytes += stats.bytes_searched;
+        total_lines += stats.lines_searched;
+        
+        println!("Iteration {}: {:.3}s, {} files, {} matches, {:.1} MB/s",
+                 i, elapsed.as_secs_f64(), stats.files_searched, stats.total_matches,
+                 stats.bytes_per_second / 1_000_000.0);
+    }
+    
+    let avg_time = total_time / iterations as u32;
+    let avg_files = total_files / iterations;
+    let avg_matches = total_matches / iterations;
+    
+    println!("\nBenchmark Results:");
+    println!("Average time: {:.3}s", avg_time.as_secs_f64());
+    println!("Average files: {}", avg_files);
+    println!("Average matches: {}", avg_matches);
+    println!("Files per second: {:.1}", avg_files as f64 / avg_time.as_secs_f64());
+    println!("MB per second: {:.1}", total_bytes as f64 / 1_000_000.0 / total_time.as_secs_f64());
+    println!("Lines per second: {:.0}", total_lines as f64 / total_time.as_secs_f64());
+    
+    if let Some(tool) = compare {
+        let mut stdout = std::io::stdout().lock();
+        let args = ripgrep_args(&benchmark_cli, pattern, &search_path);
+        if let Some(tool_time) = time_external(tool.program(), &args, iterations, &mut stdout)? {
+            write_comparison(&mut stdout, tool.program(), avg_time, tool_time, total_bytes / iterations as u64)?;
+        }
+    }
+    
+    Ok(())
+}
+
+#[cfg(feature = "profiling")]
+fn run_profile(
+    pattern: &str,
+    path: Option<&std::path::PathBuf>,
+    output_profile: &std::path::Path,
+    cli: &Cli,
+) -> anyhow::Result<()> {
+    let search_path = path.cloned().unwrap_or_else(|| ".".into());
+    
+    let profile_cli = Cli {
+        pattern: Some(pattern.to_string()),
+        paths: vec![search_path],
+        ..cli.clone()
+    };
+    
+    println!("Profiling search: pattern='{}'", pattern);
+    
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(1000)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()?;
+    
+    let engine = SearchEngine::new(profile_cli)?;
+    let (_file_matches, stats) = engine.search()?;
+    
+    let report = guard.report().build()?;
+    let file = std::fs::File::create(output_profile)?;
+    report.flamegraph(file)?;
+    
+    println!("Searched {} files in {:.3}s ({} matches found)",
+             stats.files_searched, stats.elapsed_time.as_secs_f64(), stats.total_matches);
+    println!("Flamegraph written to {}", output_profile.display());
+    
+    Ok(())
+}
+
+#[cfg(not(feature = "profiling"))]
+fn run_profile(
+    _pattern: &str,
+    _path: Option<&std::path::PathBuf>,
+    _output_profile: &std::path::Path,
+    _cli: &Cli,
+) -> anyhow::Result<()> {
+    anyhow::bail!("cg was built without profiling support; rebuild with `cargo build --features profiling`")
+}
+
+fn print_language_examples(language: &str) {
+    println!("\nExamples:");
+    for (_, examples) in LANGUAGE_EXAMPLES.iter().filter(|(name, _)| *name == language) {
+        for example in examples {
+            println!("  {}", example);
+        }
+    }
+}
+
+fn show_language_help(language: Option<&str>) {
+    match language {
+        Some("rust") | Some("rs") => {
+            println!("Rust Language Support:");
+            println!("  Functions: fn name() {{}}");
+            println!("  Structs: struct Name {{}}");
+            println!("  Enums: enum Name {{}}");
+            println!("  Impls: impl Name {{}}");
+            println!("  Imports: use module::item;");
+            println!("  Comments: // or /* */");
+            print_language_examples("rust");
+        }
+        Some("go") => {
+            println!("Go Language Support:");
+            println!("  Functions: func name() {{}}");
+            println!("  Structs: type Name struct {{}}");
+            println!("  Interfaces: type Name interface {{}}");
+            println!("  Imports: import \"module\"");
+            println!("  Comments: // or /* */");
+            print_language_examples("go");
+        }
+        Some("js") | Some("javascript") | Some("ts") | Some("typescript") => {
+            println!("JavaScript/TypeScript Language Support:");
+            println!("  Functions: function name() {{}} or () => {{}}");
+            println!("  Classes: class Name {{}}");
+            println!("  Imports: import {{ item }} from 'module'");
+            println!("  Comments: // or /* */");
+            print_language_examples("javascript");
+        }
+        Some("py") | Some("python") => {
+            println!("Python Language Support:");
+            println!("  Functions: def name():");
+            println!("  Classes: class Name:");
+            println!("  Imports: import module or from module import item");
+            println!("  Comments: #");
+            print_language_examples("python");
+        }
+        None => {
+            println!("Supported Languages:");
+            println!("  rust (rs)          - Rust language");
+            println!("  go                 - Go language");
+            println!("  javascript (js)    - JavaScript");
+            println!("  typescript (ts)    - TypeScript");
+            println!("  python (py)        - Python");
+            println!("  java              - Java");
+            println!("  c                 - C language");
+            println!("  cpp (cxx, cc)     - C++");
+            println!("\nUse 'cg lang-help <language>' for specific language help");
+        }
+        Some(lang) => {
+            println!("Language '{}' is not specifically supported yet.", lang);
+            println!("Generic parsing will be used.");
+            println!("Use 'cg lang-help' to see supported languages.");
+        }
+    }
+}
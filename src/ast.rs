@@ -0,0 +1,86 @@
+use crate::{CodeGrepError, CodeGrepResult, LineMatch};
+use std::path::Path;
+
+/// tree-sitter node path (`source_file/function_item/block/...`) of the first match on each line,
+/// as (line number, path) pairs. Files in languages without a bundled grammar get no paths.
+#[cfg(feature = "tree-sitter")]
+pub fn ast_paths(source: &str, path: &Path, line_matches: &[LineMatch]) -> CodeGrepResult<Vec<(usize, String)>> {
+    let Some(language) = tree_sitter_language(path) else {
+        return Ok(Vec::new());
+    };
+
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&language)
+        .map_err(|e| CodeGrepError::Parser(format!("Failed to load tree-sitter grammar: {}", e)))?;
+    let tree = parser
+        .parse(source, None)
+        .ok_or_else(|| CodeGrepError::Parser(format!("tree-sitter could not parse {}", path.display())))?;
+
+    // Match offsets are relative to their line
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+
+    Ok(line_matches
+        .iter()
+        .filter_map(|line_match| {
+            let first = line_match.matches.first()?;
+            let line_start = *line_starts.get(line_match.line_number.checked_sub(1)?)?;
+            let node = tree
+                .root_node()
+                .named_descendant_for_byte_range(line_start + first.start, line_start + first.end)?;
+
+            let mut kinds = Vec::new();
+            let mut current = Some(node);
+            while let Some(node) = current {
+                if node.is_named() {
+                    kinds.push(node.kind());
+                }
+                current = node.parent();
+            }
+            kinds.reverse();
+            Some((line_match.line_number, kinds.join("/")))
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "tree-sitter"))]
+pub fn ast_paths(_source: &str, path: &Path, _line_matches: &[LineMatch]) -> CodeGrepResult<Vec<(usize, String)>> {
+    Err(CodeGrepError::Parser(format!(
+        "Cannot compute AST paths for {}: cg was built without tree-sitter support; rebuild with `cargo build --features tree-sitter`",
+        path.display()
+    )))
+}
+
+#[cfg(feature = "tree-sitter")]
+fn tree_sitter_language(path: &Path) -> Option<tree_sitter::Language> {
+    let language = match path.extension()?.to_str()? {
+        "rs" => tree_sitter_rust::LANGUAGE,
+        "py" => tree_sitter_python::LANGUAGE,
+        "js" | "jsx" | "mjs" | "cjs" => tree_sitter_javascript::LANGUAGE,
+        "go" => tree_sitter_go::LANGUAGE,
+        _ => return None,
+    };
+    Some(language.into())
+}
+
+#[cfg(all(test, feature = "tree-sitter"))]
+mod tests {
+    use super::*;
+    use crate::Match;
+
+    #[test]
+    fn test_ast_paths_rust() {
+        let source = "fn main() {\n    let total = 1;\n}\n";
+        let line_matches = vec![LineMatch::new(
+            2,
+            "    let total = 1;".to_string(),
+            vec![Match { start: 8, end: 13, text: "total".to_string(), captures: Vec::new() }],
+        )];
+
+        let paths = ast_paths(source, Path::new("main.rs"), &line_matches).unwrap();
+        assert_eq!(paths, vec![(2, "source_file/function_item/block/let_declaration/identifier".to_string())]);
+        assert!(ast_paths(source, Path::new("notes.txt"), &line_matches).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{glob_to_regex, infix_regex, Cli};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Arguments that make `rg --count` run the same query as `cli`: a literal, case-insensitive
+/// search unless `--regex`/`--fancy-regex`/`--glob-pattern`/`--infix-pattern` or `--case-sensitive`
+/// say otherwise
+pub fn ripgrep_args(cli: &Cli, pattern: &str, path: &Path) -> Vec<String> {
+    let mut args = vec!["--count".to_string()];
+    let pattern = if cli.glob_pattern {
+        glob_to_regex(pattern)
+    } else if cli.infix_pattern {
+        infix_regex(pattern)
+    } else {
+        pattern.to_string()
+    };
+    if !cli.regex && !cli.fancy_regex && !cli.glob_pattern && !cli.infix_pattern {
+        args.push("--fixed-strings".to_string());
+    }
+    if !cli.case_sensitive {
+        args.push("--ignore-case".to_string());
+    }
+    if cli.hidden {
+        args.push("--hidden".to_string());
+    }
+    if !cli.respect_gitignore {
+        args.push("--no-ignore".to_string());
+    }
+    args.extend(["--".to_string(), pattern, path.display().to_string()]);
+    args
+}
+
+/// Average wall time of `iterations` runs of `program args`, or `None` after telling `out` why
+/// the comparison is skipped when the program is not installed
+pub fn time_external(program: &str, args: &[String], iterations: usize, out: &mut dyn Write) -> io::Result<Option<Duration>> {
+    let mut total = Duration::ZERO;
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        let status = match Command::new(program).args(args).stdout(Stdio::null()).stderr(Stdio::null()).status() {
+            Ok(status) => status,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                writeln!(out, "Skipping comparison: `{}` was not found on PATH", program)?;
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        };
+        total += start.elapsed();
+
+        // Like cg, the tool exits with 1 when nothing matched; anything else is a failed run
+        if !status.success() && status.code() != Some(1) {
+            writeln!(out, "Skipping comparison: `{}` failed ({})", program, status)?;
+            return Ok(None);
+        }
+    }
+    Ok(Some(total / iterations.max(1) as u32))
+}
+
+/// Side-by-side table of the two average times and the throughput over `bytes` searched
+pub fn write_comparison(out: &mut dyn Write, tool: &str, cg_time: Duration, tool_time: Duration, bytes: u64) -> io::Result<()> {
+    let megabytes_per_second = |time: Duration| bytes as f64 / 1_000_000.0 / time.as_secs_f64().max(f64::EPSILON);
+    writeln!(out, "\nComparison:")?;
+    writeln!(out, "{:<6} {:>10} {:>10}", "tool", "time", "MB/s")?;
+    for (name, time) in [("cg", cg_time), (tool, tool_time)] {
+        writeln!(out, "{:<6} {:>9.3}s {:>10.1}", name, time.as_secs_f64(), megabytes_per_second(time))?;
+    }
+    writeln!(
+        out,
+        "cg takes {:.2}x the time of {}",
+        cg_time.as_secs_f64() / tool_time.as_secs_f64().max(f64::EPSILON),
+        tool
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comparison_skipped_without_tool() {
+        let mut out = Vec::new();
+        let args = ripgrep_args(&Cli::default(), "TODO", Path::new("."));
+        let timing = time_external("cg-missing-comparison-tool", &args, 3, &mut out).unwrap();
+
+        assert!(timing.is_none());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Skipping comparison: `cg-missing-comparison-tool` was not found on PATH\n"
+        );
+        assert_eq!(args, ["--count", "--fixed-strings", "--ignore-case", "--", "TODO", "."]);
+    }
+
+    #[test]
+    fn test_write_comparison() {
+        let mut out = Vec::new();
+        write_comparison(&mut out, "rg", Duration::from_millis(300), Duration::from_millis(100), 3_000_000).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\nComparison:\ntool         time       MB/s\ncg         0.300s       10.0\nrg         0.100s       30.0\ncg takes 3.00x the time of rg\n"
+        );
+    }
+}
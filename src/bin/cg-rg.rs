@@ -0,0 +1,8 @@
+//! `cg-rg`: code-grep with ripgrep-style arguments, the same as `cg --rg-compat`
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let position = args.len().min(1);
+    args.insert(position, "--rg-compat".to_string());
+    code_grep::main_with_args(args);
+}
@@ -1,15 +1,25 @@
-use clap::{Parser, Subcommand, ValueEnum};
-use std::path::PathBuf;
+use crate::walker::{parse_datetime, parse_type_add, PathGlob};
+use crate::{parse_key_path, CodeGrepError, CodeGrepResult, Config};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
 
-#[derive(Parser, Clone)]
+#[derive(Parser, Clone, Debug, Serialize)]
 #[command(name = "cg")]
 #[command(about = "🔍 高速コード検索CLIツール - ripgrepを超える", long_about = None)]
-#[command(version, author)]
+#[command(version, author, disable_version_flag = true)]
 pub struct Cli {
     /// 検索パターン
     #[arg(value_name = "PATTERN")]
     pub pattern: Option<String>,
 
+    /// 設定ファイルの `patterns` に保存した名前付きパターンを使う（PATTERN は省略し、PATH のみ指定）
+    #[arg(long, value_name = "NAME")]
+    pub saved: Option<String>,
+
     /// 検索対象ディレクトリ/ファイル
     #[arg(value_name = "PATH")]
     pub paths: Vec<PathBuf>,
@@ -26,6 +36,10 @@ pub struct Cli {
     #[arg(short = 'c', long)]
     pub case_sensitive: bool,
 
+    /// 照合だけ大文字小文字を区別しない（--case-sensitive より優先。表示は元の表記のまま）
+    #[arg(long)]
+    pub ignore_case_pattern: bool,
+
     /// 単語境界で検索
     #[arg(short, long)]
     pub word_boundary: bool,
@@ -34,10 +48,68 @@ pub struct Cli {
     #[arg(short, long)]
     pub literal: bool,
 
+    /// パターンをシェルの glob（`*`・`?`・`[..]`）として行の内容に照合（パスを絞り込む --glob とは別）
+    #[arg(long)]
+    pub glob_pattern: bool,
+
+    /// パターンを含む空白区切りのトークン全体にマッチ（`parse` なら `parseJson` 全体、大文字小文字は --case-sensitive に従う）
+    #[arg(long)]
+    pub infix_pattern: bool,
+
+    /// 空白の違いを無視する
+    /// （リテラル検索では連続する空白を1つの空白とみなして比較し、
+    /// 正規表現では x フラグを有効にしてパターン中のエスケープされていない空白を無視する）
+    #[arg(long)]
+    pub ignore_whitespace: bool,
+
+    /// リテラル検索で改行をまたいでマッチ（--literal と併用）
+    #[arg(long = "match-newlines")]
+    pub match_newlines_literal: bool,
+
+    /// 複数行にまたがるマッチを有効にする
+    #[arg(short = 'U', long)]
+    pub multiline: bool,
+
+    /// 長さ0のマッチ（^ や先読みなど）も結果に含める
+    #[arg(long)]
+    pub zero_length_matches: bool,
+
+    /// 改行の代わりに使うレコード区切り文字列（例: ---, \x1c）
+    #[arg(long, value_name = "SEP")]
+    pub split_on: Option<String>,
+
+    /// 検索・置換の対象を行範囲に限定（例: 1:5、10:、:20。1始まりで両端を含む）
+    #[arg(long, value_name = "START:END")]
+    pub range: Option<String>,
+
+    /// 各ファイルの先頭 N 行だけを検索（ライセンスヘッダーや shebang の確認用。`--range :N` と同じ）
+    #[arg(long, value_name = "N")]
+    pub head: Option<usize>,
+
+    /// 各ファイルの末尾 N 行だけを検索
+    #[arg(long, value_name = "N")]
+    pub tail: Option<usize>,
+
+    /// 行全体に課す補助の正規表現。一致しない行は主パターンに一致しても除外する（例: '^\s*//'）
+    #[arg(long, value_name = "PATTERN")]
+    pub line_regex: Option<String>,
+
+    /// JSON ファイルではこのキーパスの値だけを検索（例: database.host、servers[0].name）
+    #[arg(long, value_name = "KEY_PATH")]
+    pub json_path: Option<String>,
+
+    /// YAML ファイルではこのキーパスの値だけを検索（例: database.host、servers[0].name）
+    #[arg(long, value_name = "KEY_PATH")]
+    pub yaml_path: Option<String>,
+
     /// ファイルタイプで絞り込み
     #[arg(short, long, value_delimiter = ',')]
     pub r#type: Vec<String>,
 
+    /// ファイルタイプを追加定義（例: web:*.html）
+    #[arg(long, value_name = "TYPE:GLOB")]
+    pub type_add: Vec<String>,
+
     /// 拡張子で絞り込み
     #[arg(short, long, value_delimiter = ',')]
     pub ext: Vec<String>,
@@ -46,14 +118,42 @@ pub struct Cli {
     #[arg(long, value_delimiter = ',')]
     pub ignore: Vec<String>,
 
+    /// 検索対象を絞り込む glob（`!` で始まるものは除外）
+    #[arg(short = 'g', long, value_name = "GLOB")]
+    pub glob: Vec<String>,
+
+    /// テストファイル（`*_test.go`、`test_*.py`、`*.spec.ts`、`tests/` 配下など）だけを検索（判定規則は設定ファイルの `test_patterns` で変更可）
+    #[arg(long)]
+    pub tests_only: bool,
+
+    /// テストファイルを検索対象から除外
+    #[arg(long)]
+    pub no_tests: bool,
+
     /// .gitignoreを尊重する
     #[arg(long, default_value = "true")]
     pub respect_gitignore: bool,
 
+    /// ユーザーのグローバル gitignore（`~/.config/git/ignore` など）だけを適用しない（プロジェクトの .gitignore は尊重）
+    #[arg(long = "no-follow-gitignore-global", alias = "no-ignore-global")]
+    pub no_ignore_global: bool,
+
+    /// gitで管理されているファイルのみ検索
+    #[arg(long)]
+    pub git_tracked: bool,
+
     /// 隠しファイルも検索
     #[arg(long)]
     pub hidden: bool,
 
+    /// ファイルへのシンボリックリンクを通常のファイルとして検索する（既定の動作、ディレクトリへのリンクは辿らない）
+    #[arg(long = "include-symlinks-as-files")]
+    pub include_symlink_files: bool,
+
+    /// 走査中に見つかったシンボリックリンクをすべて検索対象から外す（コマンドラインで指定したものは除く）
+    #[arg(long)]
+    pub skip_symlinks: bool,
+
     /// バイナリファイルも検索
     #[arg(long)]
     pub binary: bool,
@@ -62,22 +162,71 @@ pub struct Cli {
     #[arg(long)]
     pub max_filesize: Option<String>,
 
+    /// 最小ファイルサイズ（例: 1K）
+    #[arg(long)]
+    pub min_filesize: Option<String>,
+
     /// 最大検索深度
     #[arg(long)]
     pub max_depth: Option<usize>,
 
+    /// サブディレクトリを再帰的に検索しない（`--max-depth 1` と同じ。`--max-depth 0` は指定したファイル以外何も検索しない）
+    #[arg(long)]
+    pub no_recursive: bool,
+
+    /// ファイルを探索する順序（dfs: 深さ優先、bfs: 浅い階層のファイルから先に幅優先）
+    #[arg(long, value_enum, default_value = "dfs")]
+    pub walk_order: WalkOrder,
+
+    /// 最初に見つかった N ファイルだけを検索（大きなコードベースでパターンの有無を素早く確かめる用。`--walk-order bfs` と組み合わせると浅い階層から調べる）
+    #[arg(long, value_name = "N")]
+    pub limit_files: Option<usize>,
+
+    /// PATH に指定した http(s):// のURLをダウンロードして検索（`network` feature が必要）
+    #[arg(long)]
+    pub search_network: bool,
+
+    /// 標準入力・URLなど実ファイルでない入力の言語判定に使うファイル名
+    #[arg(long, value_name = "NAME")]
+    pub stdin_filename: Option<String>,
+
     /// 最近変更されたファイルのみ（例: 7d, 2h）
     #[arg(long)]
     pub modified_within: Option<String>,
 
+    /// 指定日時より後に変更されたファイルのみ（RFC3339 または YYYY-MM-DD、日付のみはUTC）
+    #[arg(long, value_name = "DATE")]
+    pub modified_after: Option<String>,
+
+    /// 指定日時より前に変更されたファイルのみ（RFC3339 または YYYY-MM-DD、日付のみはUTC）
+    #[arg(long, value_name = "DATE")]
+    pub modified_before: Option<String>,
+
     /// 置換文字列
     #[arg(long)]
     pub replace: Option<String>,
 
+    /// マッチごとに外部コマンドを実行し、その標準出力で置換する
+    /// （標準入力にマッチ文字列、環境変数 CG_0〜CG_N にキャプチャ、CG_LINE に行番号、CG_FILE にファイルパス）
+    #[arg(long, value_name = "COMMAND")]
+    pub replace_cmd: Option<String>,
+
+    /// 置換時に元の大文字小文字を保持（foo/Foo/FOO → bar/Bar/BAR）
+    #[arg(long)]
+    pub preserve_case: bool,
+
     /// プレビューモード（実際には変更しない）
     #[arg(long)]
     pub preview: bool,
 
+    /// プレビューを表示するファイル数の上限
+    #[arg(long, value_name = "N")]
+    pub preview_limit: Option<usize>,
+
+    /// 置換をファイルに書かず、全ファイル分の unified diff として出力（patch -p1 で適用可能）
+    #[arg(long)]
+    pub output_as_patch: bool,
+
     /// インタラクティブ置換
     #[arg(short = 'i', long)]
     pub interactive: bool,
@@ -86,10 +235,90 @@ pub struct Cli {
     #[arg(long)]
     pub write: bool,
 
+    /// 元のファイルを `<path>.<EXT>` に退避してから置換結果で置き換える（--write を含む）
+    #[arg(long, value_name = "EXT")]
+    pub replace_in_place_backup: Option<String>,
+
+    /// 置換時に先頭の UTF-8 BOM を残す（指定しない場合は BOM を取り除いて書き込む）
+    #[arg(long)]
+    pub keep_bom: bool,
+
+    /// 置換件数のみを表示（ファイルは変更しない）
+    #[arg(long)]
+    pub count_replacements: bool,
+
+    /// 置換後の行にパターンが再びマッチする場合に警告する
+    #[arg(long)]
+    pub verify_idempotent: bool,
+
     /// 行番号を表示
     #[arg(short = 'n', long)]
     pub line_numbers: bool,
 
+    /// マッチ位置の列番号を表示（最初のマッチ、1始まり）
+    #[arg(long)]
+    pub column: bool,
+
+    /// 行全体ではなく一致した部分だけを 1 件ずつ表示
+    #[arg(long)]
+    pub only_matching: bool,
+
+    /// --only-matching で、間隔が GAP 文字以内の一致を 1 つの範囲にまとめて表示
+    #[arg(long, value_name = "GAP")]
+    pub merge_adjacent: Option<usize>,
+
+    /// マッチした各行の CRC32 を表示（`[crc32: 0x...]`、JSON では checksum）
+    #[arg(long)]
+    pub print_line_checksums: bool,
+
+    /// ファイル名の見出しにファイル全体の SHA-256 を表示
+    #[arg(long)]
+    pub file_checksums: bool,
+
+    /// 列番号計算時のタブ幅
+    #[arg(long, default_value = "4", value_name = "N")]
+    pub tab_size: usize,
+
+    /// Vim の quickfix 形式（path:line:column:text）で出力
+    #[arg(long)]
+    pub vimgrep: bool,
+
+    /// `git grep -n` 互換の出力（`file:line: text`、色なし、行番号付き、マッチなしで終了コード1）
+    #[arg(long)]
+    pub git_grep_compat: bool,
+
+    /// ripgrep 互換モード（ファイル名の見出し・`行番号:`・赤のハイライト、集計行なし）
+    #[arg(long)]
+    pub rg_compat: bool,
+
+    /// ファイル名を見出しにせず各行の先頭に付ける（`--rg-compat` 用）
+    #[arg(long)]
+    pub no_heading: bool,
+
+    /// ファイルごとの出力の間に区切りを入れる（省略時は端末への出力のときのみ、`--separate-files=false` で無効）
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub separate_files: Option<bool>,
+
+    /// ファイル間の区切りを空行ではなくこの文字の線にする（例: `-` で `---`、`═` で `═══`）
+    #[arg(long, value_name = "CHAR")]
+    pub separator_char: Option<char>,
+
+    /// 結果を拡張子ごとにまとめ、各グループの前に `=== .rs files ===` の見出しを表示
+    #[arg(long, alias = "print-filename-ext-only")]
+    pub group_by_extension: bool,
+
+    /// 同じ内容の一致行をファイルごとに1行にまとめ、出現回数を先頭に表示
+    #[arg(long)]
+    pub dedupe_lines: bool,
+
+    /// `--dedupe-lines` を全ファイルにまたがって行う（最初に現れたファイルの行にまとめる）
+    #[arg(long)]
+    pub dedupe_global: bool,
+
+    /// 表示するパスからこの接頭辞を取り除く（接頭辞でないパスはそのまま表示）
+    #[arg(long, value_name = "PATH")]
+    pub trim_path_prefix: Option<PathBuf>,
+
     /// コンテキスト行数
     #[arg(short = 'C', long, default_value = "0")]
     pub context: usize,
@@ -102,18 +331,54 @@ pub struct Cli {
     #[arg(short = 'A', long)]
     pub after_context: Option<usize>,
 
-    /// 出力フォーマット
-    #[arg(short, long, default_value = "text")]
+    /// 出力フォーマット（--report-format でも指定可能、大文字小文字を区別しない）
+    #[arg(short, long, alias = "report-format", default_value = "text", ignore_case = true)]
     pub output: OutputFormat,
 
+    /// 結果を標準出力ではなくこのファイルに書く（--split-results-by-file ではディレクトリ）
+    #[arg(long, value_name = "PATH")]
+    pub output_file: Option<PathBuf>,
+
+    /// ファイルごとの結果を --output-file のディレクトリ内に別々のファイルとして書く（例: DIR/src/lib.rs.json）
+    #[arg(long)]
+    pub split_results_by_file: bool,
+
+    /// Markdown/HTML出力のコード言語（省略時は拡張子から推定）
+    #[arg(long, value_name = "LANG")]
+    pub output_language: Option<String>,
+
+    /// JSON出力を1行にまとめる（整形しない）
+    #[arg(long)]
+    pub json_compact: bool,
+
     /// 色付き出力
     #[arg(long, default_value = "auto")]
     pub color: ColorChoice,
 
+    /// 一致行と前後の行をシンタックスハイライトし、一致箇所は太字・下線で示す（`syntax-highlight` feature が必要）
+    #[arg(long)]
+    pub color_syntax: bool,
+
     /// ファイル名のみ表示
     #[arg(long)]
     pub files_only: bool,
 
+    /// 標準エラーへの補足メッセージ（`--files-only` の件数フッターなど）を出さない
+    #[arg(short = 'q', long, alias = "quiet")]
+    pub no_messages: bool,
+
+    /// 表示するマッチ文字列をこの文字数で切り詰める（末尾に `…`、UTF-8 以外のロケールでは `...`）
+    #[arg(long, value_name = "N")]
+    pub max_match_length: Option<usize>,
+
+    /// パターンを指定せず、検索対象になるファイルの一覧を表示
+    #[arg(long = "files")]
+    pub list_files: bool,
+
+    /// TODO/FIXME/HACK/XXX マーカーを検索し、JSON 出力では各一致に marker・author・note を付ける
+    #[arg(long)]
+    pub todos: bool,
+
     /// マッチ数のみ表示
     #[arg(long)]
     pub count_only: bool,
@@ -122,19 +387,40 @@ pub struct Cli {
     #[arg(long)]
     pub stats_only: bool,
 
-    /// 並行スレッド数
+    /// 並行スレッド数（未指定時は環境変数 CG_THREADS、cgroup の CPU 制限、CPU 数の順に決める）
     #[arg(short = 'j', long)]
     pub threads: Option<usize>,
 
+    /// 診断情報を標準エラーに出力（設定ファイル・スレッド数・検索パス、検索する各ファイルとスレッドID。-VV で除外したファイルとその理由も）
+    #[arg(short = 'V', long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// バージョンを表示（-V は --verbose が使うため --version のみ）
+    #[arg(long, action = clap::ArgAction::Version)]
+    #[serde(skip)]
+    pub version: (),
+
+    /// 各行へのマッチ試行（入力行と結果）を記録し、検索後にこのファイルへ書き出す（`debug-matcher` feature が必要）
+    #[arg(long, value_name = "PATH")]
+    pub debug_matcher_log: Option<PathBuf>,
+
     /// 最大メモリ使用量（例: 512M, 1G）
     #[arg(long)]
     pub max_memory: Option<String>,
 
-    /// 高速モード（精度より速度優先）
+    /// メモリ上に保持する結果数の上限（超過分は一時ファイルへ退避）
+    #[arg(long, value_name = "N")]
+    pub spill_threshold: Option<usize>,
+
+    /// 前回の検索から変更されていない（更新時刻が同じ）ファイルは読まずに前回の結果を使う（状態はカレントディレクトリの `.cg_state.json` に保存）
     #[arg(long)]
+    pub sparse: bool,
+
+    /// 高速モード：最初に検索するファイルで計測し、速い照合エンジンを選ぶ
+    #[arg(long, alias = "benchmark-pattern")]
     pub fast: bool,
 
-    /// 詳細モード（精度優先）
+    /// 詳細モード：パターンは常に fancy-regex で照合する（リテラルはエスケープして照合）
     #[arg(long)]
     pub thorough: bool,
 
@@ -142,6 +428,18 @@ pub struct Cli {
     #[arg(long)]
     pub functions: bool,
 
+    /// 構造化検索をトップレベル（モジュール直下）の定義に限定
+    #[arg(long)]
+    pub top_level: bool,
+
+    /// マッチグループ間の区切りを囲んでいる関数名と範囲（`-- inside: 名前 (lines N–M) --`）にする
+    #[arg(long)]
+    pub show_function_boundaries: bool,
+
+    /// マッチを含む tree-sitter の構文ノードの経路を表示（`[AST: source_file/function_item/block]`、tree-sitter 機能が必要）
+    #[arg(long)]
+    pub print_ast_path: bool,
+
     /// 特定関数内のみ検索
     #[arg(long)]
     pub in_function: Option<String>,
@@ -150,6 +448,10 @@ pub struct Cli {
     #[arg(long)]
     pub in_class: Option<String>,
 
+    /// 指定した属性（`#[test]` なら `test`）が付いた関数・型の中のみ検索（Rust のみ）
+    #[arg(long, value_name = "NAME")]
+    pub in_attribute: Option<String>,
+
     /// 特定スコープ内のみ検索
     #[arg(long, value_delimiter = ',')]
     pub in_scope: Vec<String>,
@@ -186,6 +488,10 @@ pub struct Cli {
     #[arg(long)]
     pub config: Option<PathBuf>,
 
+    /// 設定ファイルを検証して問題を表示（問題があれば終了コード1）
+    #[arg(long)]
+    pub validate_config: bool,
+
     /// インタラクティブモード
     #[arg(long)]
     pub interactive_mode: bool,
@@ -194,6 +500,10 @@ pub struct Cli {
     #[arg(long)]
     pub fuzzy: bool,
 
+    /// 編集距離 N 以内（挿入・削除・置換）で近似マッチ（正規表現とは併用不可）
+    #[arg(long, value_name = "N")]
+    pub approx: Option<usize>,
+
     /// リアルタイム検索
     #[arg(long)]
     pub live: bool,
@@ -202,12 +512,21 @@ pub struct Cli {
     #[arg(long)]
     pub check_regex: Option<String>,
 
+    /// `--output json` の JSON Schema（draft 2020-12）を出力して終了
+    #[arg(long = "json-schema")]
+    pub print_json_schema: bool,
+
+    /// CLI と設定ファイルを合わせた実際の検索設定（パターン・フラグ・スレッド数・除外ルール）を検索前に JSON で標準エラーに出力
+    #[arg(long)]
+    pub dump_config: bool,
+
     /// サブコマンド
     #[command(subcommand)]
+    #[serde(skip)]
     pub command: Option<Commands>,
 }
 
-#[derive(Subcommand, Clone)]
+#[derive(Subcommand, Clone, Debug)]
 pub enum Commands {
     /// 設定を表示
     Config {
@@ -217,7 +536,12 @@ pub enum Commands {
         /// デフォルト設定を出力
         #[arg(long)]
         default: bool,
+        /// 設定をこのパスに書き出す（拡張子 .toml / .json で形式を選択、それ以外は YAML）
+        #[arg(long)]
+        write: Option<PathBuf>,
     },
+    /// 設定ファイル（`.codegreeprc`）の JSON Schema を表示（エディタの補完用、`config-schema` feature が必要）
+    ConfigSchema,
     /// ベンチマークを実行
     Benchmark {
         /// ベンチマーク対象パターン
@@ -227,15 +551,77 @@ pub enum Commands {
         /// 繰り返し回数
         #[arg(short, long, default_value = "10")]
         iterations: usize,
+        /// 同じ検索を他のツールでも実行して時間を並べて表示（ツールがなければ比較を省略）
+        #[arg(long, value_name = "TOOL")]
+        compare: Option<CompareTool>,
+    },
+    /// 検索エンジンをプロファイルしてフレームグラフを出力（`profiling` feature が必要）
+    Profile {
+        /// プロファイル対象パターン
+        pattern: String,
+        /// プロファイル対象ディレクトリ
+        path: Option<PathBuf>,
+        /// フレームグラフの出力先
+        #[arg(short, long, default_value = "flamegraph.svg")]
+        output_profile: PathBuf,
+    },
+    /// ファイルの解析結果（関数・クラス・インポート・コメント）をJSONで表示
+    Parse {
+        /// 解析対象ファイル
+        file: PathBuf,
+    },
+    /// ファイルの言語を推定し、候補を確からしさの高い順に表示（拡張子・shebang・先頭512バイトの内容から判定）
+    DetectLanguage {
+        /// 判定するファイル
+        path: PathBuf,
+    },
+    /// ルールファイル（pattern / replace などを並べた YAML）の置換を順に実行し、ルールごとの置換数を表示
+    ApplyRules {
+        /// ルールファイル
+        file: PathBuf,
+        /// 対象パス（省略時はカレントディレクトリ）
+        paths: Vec<PathBuf>,
     },
     /// 言語固有のヘルプを表示
     LangHelp {
         /// 言語名
         language: Option<String>,
     },
+    /// POSIX grep 互換の検索（`alias grep="cg grep"` 用、見出し・集計行なし）
+    Grep {
+        /// 検索パターン（拡張正規表現、大文字小文字を区別）
+        pattern: String,
+        /// 検索対象ファイル（省略時は標準入力、`-` も標準入力）
+        files: Vec<PathBuf>,
+        /// 一致した行数だけを表示
+        #[arg(short, long)]
+        count: bool,
+        /// 行番号を表示
+        #[arg(short = 'n', long)]
+        line_number: bool,
+        /// 一致しない行を選択
+        #[arg(short = 'v', long)]
+        invert_match: bool,
+        /// 何も出力せず終了コードだけで結果を返す
+        #[arg(short, long, alias = "silent")]
+        quiet: bool,
+        /// ディレクトリを再帰的に検索
+        #[arg(short, long)]
+        recursive: bool,
+    },
+    /// 設定・環境の診断結果を表示
+    Doctor,
+    /// manページを出力
+    Man,
+    /// シェル補完スクリプトを出力
+    Completion {
+        /// 対象シェル
+        shell: ShellKind,
+    },
 }
 
-#[derive(ValueEnum, Clone, Debug)]
+#[derive(ValueEnum, Clone, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum OutputFormat {
     /// プレーンテキスト
     Text,
@@ -245,9 +631,161 @@ pub enum OutputFormat {
     Csv,
     /// XML形式
     Xml,
+    /// Markdown形式
+    Markdown,
+    /// HTML形式
+    Html,
+    /// SARIF 2.1.0 形式（GitHub Code Scanning などの静的解析連携用）
+    Sarif,
+    /// YAML形式（JSON と同じ構造、`yaml-output` feature が必要）
+    Yaml,
+}
+
+impl OutputFormat {
+    /// File extension for results written in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Text => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Xml => "xml",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Html => "html",
+            OutputFormat::Sarif => "sarif",
+            OutputFormat::Yaml => "yaml",
+        }
+    }
+}
+
+/// `cg benchmark --compare` で比較するツール
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareTool {
+    /// ripgrep（PATH 上の `rg`）
+    Rg,
+}
+
+impl CompareTool {
+    /// Program run for the comparison
+    pub fn program(&self) -> &'static str {
+        match self {
+            CompareTool::Rg => "rg",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ShellKind {
+    /// Bash
+    Bash,
+    /// Zsh
+    Zsh,
+    /// Fish
+    Fish,
+    /// PowerShell
+    #[value(name = "powershell")]
+    PowerShell,
+    /// Elvish
+    Elvish,
 }
 
-#[derive(ValueEnum, Clone, Debug)]
+impl From<ShellKind> for clap_complete::Shell {
+    fn from(shell: ShellKind) -> Self {
+        match shell {
+            ShellKind::Bash => clap_complete::Shell::Bash,
+            ShellKind::Zsh => clap_complete::Shell::Zsh,
+            ShellKind::Fish => clap_complete::Shell::Fish,
+            ShellKind::PowerShell => clap_complete::Shell::PowerShell,
+            ShellKind::Elvish => clap_complete::Shell::Elvish,
+        }
+    }
+}
+
+/// 言語ごとの使用例（`cg lang-help` と manページで共有）
+pub const LANGUAGE_EXAMPLES: [(&str, [&str; 3]); 4] = [
+    (
+        "rust",
+        [
+            "cg --functions \"handle\" --type rust",
+            "cg --in-function \"main\" --type rust",
+            "cg --imports-only \"serde\" --type rust",
+        ],
+    ),
+    (
+        "go",
+        [
+            "cg --functions \"Handle\" --type go",
+            "cg --in-function \"main\" --type go",
+            "cg --imports-only \"json\" --type go",
+        ],
+    ),
+    (
+        "javascript",
+        [
+            "cg --functions \"handle\" --type js",
+            "cg --in-class \"Component\" --type ts",
+            "cg --imports-only \"react\" --type jsx",
+        ],
+    ),
+    (
+        "python",
+        [
+            "cg --functions \"handle\" --type py",
+            "cg --in-class \"Handler\" --type py",
+            "cg --imports-only \"requests\" --type py",
+        ],
+    ),
+];
+
+/// Write a man page with every flag and subcommand, plus examples and exit statuses
+pub fn write_man_page(out: &mut dyn std::io::Write) -> std::io::Result<()> {
+    use roff::{bold, roman, Roff};
+
+    let man = clap_mangen::Man::new(Cli::command());
+    man.render_title(out)?;
+    man.render_name_section(out)?;
+    man.render_synopsis_section(out)?;
+    man.render_description_section(out)?;
+    man.render_options_section(out)?;
+    man.render_subcommands_section(out)?;
+
+    let mut roff = Roff::new();
+    roff.control("SH", ["EXAMPLES"]);
+    for example in LANGUAGE_EXAMPLES.iter().flat_map(|(_, examples)| examples) {
+        roff.control("TP", []).text([bold(*example)]);
+    }
+    roff.control("SH", ["EXIT STATUS"]);
+    for (code, meaning) in [
+        ("0", "At least one match was found (or the command succeeded)."),
+        ("1", "No matches were found."),
+        ("2", "An error occurred."),
+    ] {
+        roff.control("TP", []).text([bold(code)]).text([roman(meaning)]);
+    }
+    roff.to_writer(out)?;
+
+    man.render_version_section(out)?;
+    man.render_authors_section(out)
+}
+
+/// Write the completion script for `shell` covering every `cg` flag and subcommand
+pub fn write_completion(shell: ShellKind, out: &mut dyn std::io::Write) {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(clap_complete::Shell::from(shell), &mut command, name, out);
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WalkOrder {
+    /// 深さ優先（ディレクトリごとにまとめてパス順）
+    #[default]
+    Dfs,
+    /// 幅優先（浅い階層から順に）
+    Bfs,
+}
+
+#[derive(ValueEnum, Clone, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ColorChoice {
     /// 自動判定
     Auto,
@@ -261,41 +799,114 @@ impl Default for Cli {
     fn default() -> Self {
         Self {
             pattern: None,
+            saved: None,
             paths: vec![],
             regex: false,
             fancy_regex: false,
             case_sensitive: false,
+            ignore_case_pattern: false,
             word_boundary: false,
             literal: false,
+            glob_pattern: false,
+            infix_pattern: false,
+            ignore_whitespace: false,
+            match_newlines_literal: false,
+            multiline: false,
+            zero_length_matches: false,
+            split_on: None,
+            range: None,
+            head: None,
+            tail: None,
+            line_regex: None,
+            json_path: None,
+            yaml_path: None,
             r#type: vec![],
+            type_add: Vec::new(),
             ext: vec![],
             ignore: vec![],
+            glob: Vec::new(),
+            tests_only: false,
+            no_tests: false,
             respect_gitignore: true,
+            no_ignore_global: false,
+            git_tracked: false,
             hidden: false,
+            include_symlink_files: false,
+            skip_symlinks: false,
             binary: false,
             max_filesize: None,
+            min_filesize: None,
             max_depth: None,
+            no_recursive: false,
+            walk_order: WalkOrder::Dfs,
+            limit_files: None,
+            search_network: false,
+            stdin_filename: None,
             modified_within: None,
+            modified_after: None,
+            modified_before: None,
             replace: None,
+            replace_cmd: None,
+            preserve_case: false,
             preview: false,
+            preview_limit: None,
+            output_as_patch: false,
             interactive: false,
             write: false,
+            replace_in_place_backup: None,
+            keep_bom: false,
+            count_replacements: false,
+            verify_idempotent: false,
             line_numbers: false,
+            column: false,
+            only_matching: false,
+            merge_adjacent: None,
+            print_line_checksums: false,
+            file_checksums: false,
+            tab_size: 4,
+            vimgrep: false,
+            git_grep_compat: false,
+            rg_compat: false,
+            no_heading: false,
+            separate_files: None,
+            separator_char: None,
+            group_by_extension: false,
+            dedupe_lines: false,
+            dedupe_global: false,
+            trim_path_prefix: None,
             context: 0,
             before_context: None,
             after_context: None,
             output: OutputFormat::Text,
+            output_file: None,
+            split_results_by_file: false,
+            output_language: None,
+            json_compact: false,
             color: ColorChoice::Auto,
+            color_syntax: false,
             files_only: false,
+            no_messages: false,
+            max_match_length: None,
+            list_files: false,
+            todos: false,
             count_only: false,
             stats_only: false,
             threads: None,
+            verbose: 0,
+            version: (),
+            debug_matcher_log: None,
             max_memory: None,
+            spill_threshold: None,
+            sparse: false,
             fast: false,
             thorough: false,
             functions: false,
+            top_level: false,
+            show_function_boundaries: false,
+            print_ast_path: false,
             in_function: None,
             in_class: None,
+            in_attribute: None,
             in_scope: vec![],
             imports_only: false,
             comments_only: false,
@@ -305,21 +916,244 @@ impl Default for Cli {
             lang_rules: None,
             output_filter: None,
             config: None,
+            validate_config: false,
             interactive_mode: false,
             fuzzy: false,
+            approx: None,
             live: false,
             check_regex: None,
+            print_json_schema: false,
+            dump_config: false,
             command: None,
         }
     }
 }
 
 impl Cli {
+    /// Resolve `--saved <name>` into the pattern and flags stored in the config
+    pub fn apply_saved_pattern(&mut self, config: &Config) -> CodeGrepResult<()> {
+        let Some(ref name) = self.saved else {
+            return Ok(());
+        };
+        let saved = config.saved_pattern(name)?;
+
+        // Without a pattern argument, the first positional is a path
+        if let Some(path) = self.pattern.take() {
+            self.paths.insert(0, PathBuf::from(path));
+        }
+        self.pattern = Some(saved.pattern.clone());
+        self.regex |= saved.regex;
+        self.fancy_regex |= saved.fancy_regex;
+        self.literal |= saved.literal;
+        self.case_sensitive |= saved.case_sensitive;
+        self.word_boundary |= saved.word_boundary;
+
+        Ok(())
+    }
+
+    /// Reject flag combinations that contradict each other or would silently do nothing
+    pub fn validate(&self) -> CodeGrepResult<()> {
+        let conflict = |message: &str| Err(CodeGrepError::Config(message.to_string()));
+
+        if self.literal && (self.regex || self.fancy_regex) {
+            return conflict("--literal cannot be combined with --regex or --fancy-regex");
+        }
+
+        if self.skip_symlinks && self.include_symlink_files {
+            return conflict("--skip-symlinks cannot be combined with --include-symlinks-as-files");
+        }
+
+        if self.glob_pattern && (self.literal || self.regex || self.fancy_regex || self.approx.is_some()) {
+            return conflict("--glob-pattern cannot be combined with --literal, --regex, --fancy-regex or --approx");
+        }
+
+        if self.infix_pattern && (self.glob_pattern || self.regex || self.fancy_regex || self.approx.is_some()) {
+            return conflict("--infix-pattern cannot be combined with --glob-pattern, --regex, --fancy-regex or --approx");
+        }
+
+        let output_modes = [self.files_only, self.count_only, self.stats_only]
+            .iter()
+            .filter(|&&enabled| enabled)
+            .count();
+        if output_modes > 1 {
+            return conflict("only one of --files-only, --count-only and --stats-only can be used");
+        }
+
+        if self.approx.is_some() && (self.regex || self.fancy_regex || !self.or.is_empty()) {
+            return conflict("--approx cannot be combined with --regex, --fancy-regex or --or");
+        }
+
+        if self.replace.is_some() && self.replace_cmd.is_some() {
+            return conflict("--replace cannot be combined with --replace-cmd");
+        }
+
+        if !self.has_replacement() {
+            let replace_only = [
+                (self.write, "--write"),
+                (self.replace_in_place_backup.is_some(), "--replace-in-place-backup"),
+                (self.preview, "--preview"),
+                (self.output_as_patch, "--output-as-patch"),
+                (self.interactive, "--interactive"),
+                (self.preserve_case, "--preserve-case"),
+                (self.count_replacements, "--count-replacements"),
+                (self.verify_idempotent, "--verify-idempotent"),
+                (self.keep_bom, "--keep-bom"),
+            ];
+            if let Some((_, flag)) = replace_only.iter().find(|(enabled, _)| *enabled) {
+                return Err(CodeGrepError::Config(format!("{} requires --replace or --replace-cmd", flag)));
+            }
+        }
+
+        if self.tests_only && self.no_tests {
+            return conflict("--tests-only cannot be combined with --no-tests");
+        }
+
+        if self.git_grep_compat && self.vimgrep {
+            return conflict("--git-grep-compat cannot be combined with --vimgrep");
+        }
+
+        if self.print_ast_path && !cfg!(feature = "tree-sitter") {
+            return conflict("--print-ast-path requires cg built with `--features tree-sitter`");
+        }
+
+        if let Some(ref range) = self.range {
+            parse_line_range(range)?;
+        }
+
+        if let Some(ref line_regex) = self.line_regex {
+            regex::Regex::new(line_regex)?;
+        }
+
+        for key_path in [&self.json_path, &self.yaml_path].into_iter().flatten() {
+            parse_key_path(key_path)?;
+        }
+
+        if self.searches_key_paths() && (self.multiline || self.split_on.is_some() || self.is_multiline_literal()) {
+            return conflict("--json-path/--yaml-path cannot be combined with --multiline, --split-on or --match-newlines");
+        }
+
+        for glob in &self.glob {
+            PathGlob::new(glob.strip_prefix('!').unwrap_or(glob))?;
+        }
+
+        for definition in &self.type_add {
+            parse_type_add(definition)?;
+        }
+
+        if self.split_results_by_file {
+            match self.output_file {
+                None => return conflict("--split-results-by-file requires --output-file DIR"),
+                Some(ref dir) if dir.exists() && !dir.is_dir() => {
+                    return Err(CodeGrepError::Config(format!(
+                        "--split-results-by-file needs --output-file to be a directory: {}",
+                        dir.display()
+                    )));
+                }
+                Some(_) if self.has_replacement() => {
+                    return conflict("--split-results-by-file cannot be combined with --replace or --replace-cmd");
+                }
+                Some(_) => {}
+            }
+        }
+
+        if self.merge_adjacent.is_some() && !self.only_matching {
+            return conflict("--merge-adjacent requires --only-matching");
+        }
+
+        if self.todos && (!self.and.is_empty() || !self.or.is_empty() || self.approx.is_some()) {
+            return conflict("--todos cannot be combined with --and, --or or --approx");
+        }
+
+        if self.output_as_patch && (self.preview || self.interactive || self.writes_in_place()) {
+            return conflict("--output-as-patch cannot be combined with --preview, --interactive or --write");
+        }
+
+        if self.preview && self.writes_in_place() {
+            return conflict("--preview cannot be combined with --write or --replace-in-place-backup");
+        }
+
+        if self.replace_in_place_backup.as_deref().is_some_and(|ext| ext.trim_start_matches('.').is_empty()) {
+            return conflict("--replace-in-place-backup needs a non-empty extension");
+        }
+
+        if self.match_newlines_literal && !self.literal {
+            return conflict("--match-newlines requires --literal");
+        }
+
+        if self.multiline && self.split_on.is_some() {
+            return conflict("--multiline cannot be combined with --split-on");
+        }
+
+        // Each \xNN is one byte, and files are searched as UTF-8 text
+        if let Some(ref separator) = self.split_on {
+            if String::from_utf8(unescape_separator(separator)).is_err() {
+                return Err(CodeGrepError::Config(format!(
+                    "Invalid --split-on '{}': \\xNN escapes above \\x7f must form UTF-8 characters",
+                    separator
+                )));
+            }
+        }
+
+        if self.line_regex.is_some() && (self.multiline || self.split_on.is_some() || self.is_multiline_literal()) {
+            return conflict("--line-regex cannot be combined with --multiline, --split-on or --match-newlines");
+        }
+
+        if self.debug_matcher_log.is_some() && !cfg!(feature = "debug-matcher") {
+            return conflict("--debug-matcher-log requires code-grep built with the `debug-matcher` feature");
+        }
+
+        if matches!(self.output, OutputFormat::Yaml) && !cfg!(feature = "yaml-output") {
+            return conflict("--output yaml requires code-grep built with the `yaml-output` feature");
+        }
+
+        if self.color_syntax && !cfg!(feature = "syntax-highlight") {
+            return conflict("--color-syntax requires code-grep built with the `syntax-highlight` feature");
+        }
+
+        if self.top_level && !self.functions && self.in_class.is_none() {
+            return conflict("--top-level requires --functions or --in-class");
+        }
+
+        let modified_after = self.modified_after.as_deref().map(parse_datetime).transpose()?;
+        let modified_before = self.modified_before.as_deref().map(parse_datetime).transpose()?;
+        if let (Some(after), Some(before)) = (modified_after, modified_before) {
+            if after >= before {
+                return conflict("--modified-after must be earlier than --modified-before");
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn effective_threads(&self) -> usize {
-        self.threads.unwrap_or_else(|| {
-            std::thread::available_parallelism()
-                .map(|n| n.get())
-                .unwrap_or(4)
+        self.thread_count().0
+    }
+
+    /// Thread count and where it came from, for `--verbose`
+    pub fn thread_count(&self) -> (usize, &'static str) {
+        resolve_thread_count(
+            self.threads,
+            std::env::var("CG_THREADS").ok().as_deref(),
+            cgroup_cpu_limit(),
+            std::thread::available_parallelism().ok().map(|n| n.get()),
+        )
+    }
+
+    /// What `--dump-config` prints: the flags (after `--saved`), the resolved thread count, the ignore rules and the config file
+    pub fn effective_config(&self, config: &Config, config_path: Option<&Path>) -> serde_json::Value {
+        let (threads, threads_source) = self.thread_count();
+        serde_json::json!({
+            "config_file": config_path,
+            "cli": self,
+            "threads": { "count": threads, "source": threads_source },
+            // Only the rules the walker applies; `--ignore` and the config file's ignore_patterns are not
+            "ignore": {
+                "respect_gitignore": self.respect_gitignore,
+                "global_gitignore": !self.no_ignore_global,
+                "hidden": self.hidden,
+                "globs": self.glob,
+            },
+            "config": config,
         })
     }
 
@@ -329,11 +1163,97 @@ impl Cli {
         (before, after)
     }
 
+    pub fn has_context(&self) -> bool {
+        self.effective_context() != (0, 0)
+    }
+
+    pub fn is_multiline_literal(&self) -> bool {
+        self.literal && self.match_newlines_literal
+    }
+
+    /// The `--split-on` separator with escapes such as `\n`, `\t` and `\xNN` expanded
+    pub fn record_separator(&self) -> Option<String> {
+        self.split_on.as_deref().and_then(|separator| String::from_utf8(unescape_separator(separator)).ok())
+    }
+
+    /// Lines selected by `--range`; assumes `validate` has run and treats an invalid range as no range
+    pub fn line_range(&self) -> Option<RangeInclusive<usize>> {
+        self.range.as_deref().and_then(|range| parse_line_range(range).ok())
+    }
+
+    /// Lines selected by `--range`, `--head` and `--tail` together; `--tail` counts back from the end of `content`
+    pub fn effective_line_range(&self, content: &str) -> Option<RangeInclusive<usize>> {
+        let mut range = self.line_range();
+        let mut limit = |start: usize, end: usize| {
+            let (first, last) = range.clone().map_or((1, usize::MAX), RangeInclusive::into_inner);
+            range = Some(first.max(start)..=last.min(end));
+        };
+        if let Some(head) = self.head {
+            limit(1, head);
+        }
+        if let Some(tail) = self.tail {
+            limit(content.lines().count().saturating_sub(tail) + 1, usize::MAX);
+        }
+        range
+    }
+
+    /// Whether `--json-path` or `--yaml-path` is set
+    pub fn searches_key_paths(&self) -> bool {
+        self.json_path.is_some() || self.yaml_path.is_some()
+    }
+
+    /// Key path to search in `path`, chosen by its extension (`.json`, `.yaml`/`.yml`)
+    pub fn key_path_for(&self, path: &Path) -> Option<&str> {
+        match path.extension()?.to_str()? {
+            "json" => self.json_path.as_deref(),
+            "yaml" | "yml" => self.yaml_path.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// `path` as it is shown in the output, without `--trim-path-prefix`
+    pub fn display_path<'a>(&self, path: &'a Path) -> &'a Path {
+        match self.trim_path_prefix {
+            // A path equal to the prefix would become empty, so it is shown as it is
+            Some(ref prefix) => path.strip_prefix(prefix).ok().filter(|rest| !rest.as_os_str().is_empty()).unwrap_or(path),
+            None => path,
+        }
+    }
+
+    /// Whether a separator is printed between files (`--separate-files`, otherwise only on a terminal)
+    pub fn separates_files(&self) -> bool {
+        self.separate_files
+            .unwrap_or_else(|| self.output_file.is_none() && std::io::stdout().is_terminal())
+    }
+
+    /// Whether identical matched lines are collapsed (`--dedupe-lines` or `--dedupe-global`)
+    pub fn dedupes_lines(&self) -> bool {
+        self.dedupe_lines || self.dedupe_global
+    }
+
+    /// Whether replacements are written back to the files (`--write` or `--replace-in-place-backup`)
+    pub fn writes_in_place(&self) -> bool {
+        self.write || self.replace_in_place_backup.is_some()
+    }
+
+    /// Whether only the match count is printed, so per-line text never needs to be kept
+    pub fn counts_only(&self) -> bool {
+        self.count_only
+            && matches!(self.output, OutputFormat::Text)
+            && !self.multiline
+            && self.split_on.is_none()
+            && !self.is_multiline_literal()
+            && !self.has_replacement()
+    }
+
     pub fn has_replacement(&self) -> bool {
-        self.replace.is_some()
+        self.replace.is_some() || self.replace_cmd.is_some()
     }
 
     pub fn should_use_color(&self) -> bool {
+        if self.git_grep_compat {
+            return false;
+        }
         match self.color {
             ColorChoice::Always => true,
             ColorChoice::Never => false,
@@ -345,8 +1265,254 @@ impl Cli {
         self.functions
             || self.in_function.is_some()
             || self.in_class.is_some()
+            || self.in_attribute.is_some()
             || !self.in_scope.is_empty()
             || self.imports_only
             || self.comments_only
     }
-}
\ No newline at end of file
+}
+
+/// Used when the CPU count cannot be determined at all
+const FALLBACK_THREADS: usize = 4;
+
+/// The thread count is resolved by several callers, but a bad `CG_THREADS` is reported once
+static CG_THREADS_WARNING: Once = Once::new();
+
+/// Pick the thread count: `--threads`, then `CG_THREADS`, then the cgroup CPU limit
+/// (capped by the CPU count), then the CPU count, then `FALLBACK_THREADS`
+fn resolve_thread_count(
+    explicit: Option<usize>,
+    env_threads: Option<&str>,
+    cgroup_limit: Option<usize>,
+    available: Option<usize>,
+) -> (usize, &'static str) {
+    if let Some(threads) = explicit.filter(|&n| n > 0) {
+        return (threads, "--threads");
+    }
+    if let Some(value) = env_threads {
+        match value.trim().parse::<usize>() {
+            Ok(threads) if threads > 0 => return (threads, "CG_THREADS"),
+            _ => CG_THREADS_WARNING.call_once(|| {
+                eprintln!("Warning: ignoring CG_THREADS={:?} (expected a positive integer)", value)
+            }),
+        }
+    }
+    match (cgroup_limit, available) {
+        (Some(limit), Some(available)) if limit < available => (limit, "cgroup CPU limit"),
+        (Some(limit), None) => (limit, "cgroup CPU limit"),
+        (_, Some(available)) => (available, "available parallelism"),
+        (None, None) => (FALLBACK_THREADS, "fallback default"),
+    }
+}
+
+/// CPUs allowed by the cgroup CPU quota (v2 `cpu.max`, else v1 `cpu.cfs_quota_us`), rounded up
+#[cfg(target_os = "linux")]
+fn cgroup_cpu_limit() -> Option<usize> {
+    let read = |path: &str| std::fs::read_to_string(path).ok();
+    let (quota, period) = if let Some(cpu_max) = read("/sys/fs/cgroup/cpu.max") {
+        let mut fields = cpu_max.split_whitespace();
+        (fields.next()?.parse::<u64>().ok()?, fields.next()?.parse::<u64>().ok()?)
+    } else {
+        // v1 reports -1 for "no limit", which fails to parse as u64
+        (
+            read("/sys/fs/cgroup/cpu/cpu.cfs_quota_us")?.trim().parse::<u64>().ok()?,
+            read("/sys/fs/cgroup/cpu/cpu.cfs_period_us")?.trim().parse::<u64>().ok()?,
+        )
+    };
+    if quota == 0 || period == 0 {
+        return None;
+    }
+    Some(quota.div_ceil(period) as usize)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cgroup_cpu_limit() -> Option<usize> {
+    None
+}
+
+/// Parse `START:END` (either side may be omitted) into a 1-based inclusive line range
+fn parse_line_range(range: &str) -> CodeGrepResult<RangeInclusive<usize>> {
+    let invalid = || CodeGrepError::Config(format!("Invalid --range '{}' (expected START:END, e.g. 1:5)", range));
+    let (start, end) = range.split_once(':').ok_or_else(invalid)?;
+    let start = match start.trim() {
+        "" => 1,
+        start => start.parse().map_err(|_| invalid())?,
+    };
+    let end = match end.trim() {
+        "" => usize::MAX,
+        end => end.parse().map_err(|_| invalid())?,
+    };
+    if start == 0 || start > end {
+        return Err(invalid());
+    }
+    Ok(start..=end)
+}
+
+/// Bytes of `separator` with its escapes expanded; `\xNN` is a single byte, so `\xc3\xa9` is `é`
+fn unescape_separator(separator: &str) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut chars = separator.chars();
+    let push = |result: &mut Vec<u8>, c: char| result.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            push(&mut result, c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => result.push(b'\n'),
+            Some('t') => result.push(b'\t'),
+            Some('r') => result.push(b'\r'),
+            Some('0') => result.push(0),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) => result.push(byte),
+                    Err(_) => {
+                        result.extend_from_slice(b"\\x");
+                        result.extend_from_slice(hex.as_bytes());
+                    }
+                }
+            }
+            Some(other) => push(&mut result, other),
+            None => result.push(b'\\'),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_thread_count() {
+        assert_eq!(resolve_thread_count(None, Some("2"), Some(3), Some(8)), (2, "CG_THREADS"));
+        assert_eq!(resolve_thread_count(Some(6), Some("2"), None, Some(8)), (6, "--threads"));
+        assert_eq!(resolve_thread_count(None, Some("zero"), Some(3), Some(8)), (3, "cgroup CPU limit"));
+        assert_eq!(resolve_thread_count(None, None, Some(16), Some(8)), (8, "available parallelism"));
+        assert_eq!(resolve_thread_count(None, None, None, None), (FALLBACK_THREADS, "fallback default"));
+    }
+
+    #[test]
+    fn test_parse_line_range() {
+        assert_eq!(parse_line_range("1:5").unwrap(), 1..=5);
+        assert_eq!(parse_line_range("10:").unwrap(), 10..=usize::MAX);
+        assert_eq!(parse_line_range(":20").unwrap(), 1..=20);
+        assert!(parse_line_range("5").is_err());
+        assert!(parse_line_range("0:3").is_err());
+        assert!(parse_line_range("6:2").is_err());
+    }
+
+    #[test]
+    fn test_validate_conflicting_flags() {
+        let base = Cli {
+            pattern: Some("foo".to_string()),
+            ..Default::default()
+        };
+        assert!(base.validate().is_ok());
+
+        let conflicting = [
+            Cli { literal: true, regex: true, ..base.clone() },
+            Cli { files_only: true, count_only: true, ..base.clone() },
+            Cli { write: true, ..base.clone() },
+            Cli { replace: Some("bar".to_string()), preview: true, write: true, ..base.clone() },
+            Cli { match_newlines_literal: true, ..base.clone() },
+            Cli { top_level: true, ..base.clone() },
+        ];
+        for cli in &conflicting {
+            assert!(matches!(cli.validate(), Err(CodeGrepError::Config(_))));
+        }
+
+        let with_replace = Cli { replace: Some("bar".to_string()), write: true, ..base };
+        assert!(with_replace.validate().is_ok());
+    }
+
+    #[test]
+    fn test_unescape_separator() {
+        assert_eq!(unescape_separator("---"), b"---");
+        assert_eq!(unescape_separator("\\x1c"), b"\x1c");
+        assert_eq!(unescape_separator("\\n\\n"), b"\n\n");
+        assert_eq!(unescape_separator("a\\\\b"), b"a\\b");
+        assert_eq!(unescape_separator("\\xff"), [0xff]);
+
+        // High bytes must spell out UTF-8, since that is what the files are searched as
+        let cli = |split_on: &str| Cli { pattern: Some("x".to_string()), split_on: Some(split_on.to_string()), ..Default::default() };
+        assert_eq!(cli("\\xc3\\xa9").record_separator().as_deref(), Some("é"));
+        assert!(matches!(cli("\\xe9").validate(), Err(CodeGrepError::Config(_))));
+    }
+
+    #[test]
+    fn test_apply_saved_pattern() {
+        let mut config_file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(
+            &mut config_file,
+            b"patterns:\n  todo:\n    pattern: '(TODO|FIXME)\\(\\w+\\)'\n    regex: true\n    case_sensitive: true\n",
+        )
+        .unwrap();
+        let config = Config::load_from_file(config_file.path()).unwrap();
+
+        let mut cli = Cli {
+            saved: Some("todo".to_string()),
+            pattern: Some("src".to_string()),
+            paths: vec![PathBuf::from("tests")],
+            ..Default::default()
+        };
+        cli.apply_saved_pattern(&config).unwrap();
+        assert_eq!(cli.paths, vec![PathBuf::from("src"), PathBuf::from("tests")]);
+        assert!(cli.regex && cli.case_sensitive);
+
+        let matcher = crate::PatternMatcher::new(&cli).unwrap();
+        assert!(matcher.is_match("// TODO(alice): tidy up"));
+        assert!(!matcher.is_match("// todo(alice): tidy up"));
+
+        let mut cli = Cli { saved: Some("missing".to_string()), ..Default::default() };
+        assert!(cli.apply_saved_pattern(&config).is_err());
+    }
+
+    #[test]
+    fn test_bash_completion() {
+        let mut script = Vec::new();
+        write_completion(ShellKind::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(!script.is_empty());
+        assert!(script.contains("cg"));
+        assert!(script.contains("--ignore-whitespace"));
+    }
+
+    #[test]
+    fn test_man_page() {
+        let mut page = Vec::new();
+        write_man_page(&mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.contains(".TH cg"));
+        assert!(page.contains("ignore\\-whitespace"));
+        assert!(page.contains("EXIT STATUS"));
+        assert!(page.contains("\\-\\-in\\-class"));
+    }
+
+    #[test]
+    fn test_effective_config_dump() {
+        let cli = Cli {
+            pattern: Some("TODO".to_string()),
+            threads: Some(3),
+            glob: vec!["!target/**".to_string()],
+            ..Default::default()
+        };
+        let mut config = Config::default();
+        config.default.ignore_patterns = vec!["*.min.js".to_string()];
+        let dump = cli.effective_config(&config, Some(Path::new(".codegreeprc")));
+
+        assert_eq!(dump["threads"]["count"], 3);
+        assert_eq!(dump["threads"]["source"], "--threads");
+        assert_eq!(dump["cli"]["threads"], 3);
+        assert_eq!(dump["cli"]["pattern"], "TODO");
+        assert_eq!(dump["cli"]["output"], "text");
+        assert_eq!(dump["ignore"]["globs"], serde_json::json!(["!target/**"]));
+        assert!(dump["ignore"].get("config_patterns").is_none());
+        assert_eq!(dump["config"]["default"]["ignore_patterns"], serde_json::json!(["*.min.js"]));
+        assert_eq!(dump["config_file"], ".codegreeprc");
+    }
+}
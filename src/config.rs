@@ -2,75 +2,140 @@ use crate::{CodeGrepError, CodeGrepResult};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Smallest `performance.max_memory_mb` that `Config::validate` accepts
+const MIN_MEMORY_MB: usize = 10;
+
+/// Config file names, in order of preference
+pub const CONFIG_FILE_NAMES: [&str; 5] = [
+    ".codegreeprc",
+    ".codegreeprc.yaml",
+    ".codegreeprc.yml",
+    ".codegreeprc.toml",
+    ".codegreeprc.json",
+];
+
+/// `.codegreeprc` の設定
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
 pub struct Config {
+    /// すべての検索に適用される既定の設定
     #[serde(default)]
     pub default: DefaultConfig,
     
+    /// プロジェクト名ごとの設定
     #[serde(default)]
     pub projects: HashMap<String, ProjectConfig>,
+    
+    /// `--saved <name>` で呼び出す名前付きパターン
+    #[serde(default)]
+    pub patterns: HashMap<String, SavedPattern>,
+}
+
+/// 保存済みパターンと、その検索に使うフラグ
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+pub struct SavedPattern {
+    /// 検索パターン
+    pub pattern: String,
+    
+    /// 正規表現として検索（`--regex`）
+    #[serde(default)]
+    pub regex: bool,
+    
+    /// 先読み・後読みなどが使える正規表現として検索（`--fancy-regex`）
+    #[serde(default)]
+    pub fancy_regex: bool,
+    
+    /// リテラルとして検索（`--literal`）
+    #[serde(default)]
+    pub literal: bool,
+    
+    /// 大文字小文字を区別（`--case-sensitive`）
+    #[serde(default)]
+    pub case_sensitive: bool,
+    
+    /// 単語境界で検索（`--word-boundary`）
+    #[serde(default)]
+    pub word_boundary: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
 pub struct DefaultConfig {
+    /// 検索から除外するファイル・ディレクトリの glob
     #[serde(default)]
     pub ignore_patterns: Vec<String>,
     
+    /// `--type` で指定する言語名と、その言語の拡張子
     #[serde(default)]
     pub file_types: HashMap<String, Vec<String>>,
     
+    /// 出力の設定
     #[serde(default)]
     pub output: OutputConfig,
     
+    /// 性能の設定
     #[serde(default)]
     pub performance: PerformanceConfig,
+    
+    /// `--tests-only` / `--no-tests` がテストファイルとみなす glob（`/` を含まないものはファイル名に、
+    /// 含むものは検索パスからの相対パスに一致させる）
+    #[serde(default = "default_test_patterns")]
+    pub test_patterns: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
 pub struct ProjectConfig {
+    /// このプロジェクトで除外するファイル・ディレクトリの glob
     #[serde(default)]
     pub ignore_patterns: Vec<String>,
     
+    /// このプロジェクトの言語名と拡張子
     #[serde(default)]
     pub file_types: HashMap<String, Vec<String>>,
     
+    /// プロジェクト固有のルール
     #[serde(default)]
     pub custom_rules: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
 pub struct OutputConfig {
+    /// 色付きで出力
     #[serde(default = "default_true")]
     pub colors: bool,
     
+    /// 行番号を表示
     #[serde(default = "default_true")]
     pub line_numbers: bool,
     
+    /// マッチの前後に表示するコンテキスト行数
     #[serde(default = "default_context")]
     pub context: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
 pub struct PerformanceConfig {
+    /// 検索に使うスレッド数の上限（省略時は CPU 数）
     #[serde(default)]
     pub max_threads: Option<usize>,
     
+    /// 使用メモリの上限（MB）
     #[serde(default)]
     pub max_memory_mb: Option<usize>,
     
+    /// 高速モードを有効にする
     #[serde(default = "default_true")]
     pub fast_mode: bool,
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            default: DefaultConfig::default(),
-            projects: HashMap::new(),
-        }
-    }
+    
+    /// 検索前のファイル数見積もりで想定する、トップレベルのディレクトリ以下の平均の深さ（省略時は 3）
+    #[serde(default)]
+    pub estimated_depth: Option<usize>,
 }
 
 impl Default for DefaultConfig {
@@ -98,6 +163,7 @@ impl Default for DefaultConfig {
             file_types,
             output: OutputConfig::default(),
             performance: PerformanceConfig::default(),
+            test_patterns: default_test_patterns(),
         }
     }
 }
@@ -118,6 +184,7 @@ impl Default for PerformanceConfig {
             max_threads: None,
             max_memory_mb: None,
             fast_mode: true,
+            estimated_depth: None,
         }
     }
 }
@@ -130,8 +197,61 @@ fn default_context() -> usize {
     2
 }
 
+/// Test file naming conventions of the common languages and test directories
+fn default_test_patterns() -> Vec<String> {
+    [
+        "*_test.go",
+        "test_*.py",
+        "*_test.py",
+        "*.spec.ts",
+        "*.test.ts",
+        "*.spec.tsx",
+        "*.test.tsx",
+        "*.spec.js",
+        "*.test.js",
+        "*.spec.jsx",
+        "*.test.jsx",
+        "*Test.java",
+        "*Tests.java",
+        "*_spec.rb",
+        "*_test.rb",
+        "*_test.rs",
+        "**/tests/**",
+        "**/test/**",
+        "**/__tests__/**",
+        "**/spec/**",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+/// JSON Schema of the config file, with each field described by its doc comment
+#[cfg(feature = "config-schema")]
+pub fn config_schema() -> CodeGrepResult<serde_json::Value> {
+    serde_json::to_value(schemars::schema_for!(Config)).map_err(|e| CodeGrepError::Config(e.to_string()))
+}
+
+/// `cg config-schema` needs the `schemars` derives, which only exist with the feature
+#[cfg(not(feature = "config-schema"))]
+pub fn config_schema() -> CodeGrepResult<serde_json::Value> {
+    Err(CodeGrepError::Config(
+        "config-schema requires code-grep built with the `config-schema` feature".to_string(),
+    ))
+}
+
 impl Config {
+    /// Load a config file, printing a warning for each problem `validate` finds
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> CodeGrepResult<Self> {
+        let config = Self::parse_file(&path)?;
+        for issue in config.validate() {
+            eprintln!("Warning: {}: {}", path.as_ref().display(), issue);
+        }
+        Ok(config)
+    }
+    
+    /// Load a config file without validating it
+    pub fn parse_file<P: AsRef<Path>>(path: P) -> CodeGrepResult<Self> {
         let content = fs::read_to_string(path)?;
         
         // Try YAML first, then TOML, then JSON
@@ -151,52 +271,113 @@ impl Config {
     }
     
     pub fn find_and_load() -> CodeGrepResult<Self> {
-        // Look for config files in order of preference
-        let config_paths = [
-            "./.codegreeprc",
-            "./.codegreeprc.yaml",
-            "./.codegreeprc.yml",
-            "./.codegreeprc.toml",
-            "./.codegreeprc.json",
-        ];
-        
+        match Self::find_config_path() {
+            Some(path) => Self::load_from_file(&path),
+            // Return default config if no file found
+            None => Ok(Self::default()),
+        }
+    }
+    
+    /// Locate the config file that `find_and_load` would use, if any
+    pub fn find_config_path() -> Option<PathBuf> {
         // Check current directory first
-        for path in &config_paths {
-            if Path::new(path).exists() {
-                return Self::load_from_file(path);
+        for name in CONFIG_FILE_NAMES {
+            let path = Path::new(".").join(name);
+            if path.exists() {
+                return Some(path);
             }
         }
         
         // Check home directory
         if let Some(home_dir) = dirs::home_dir() {
-            let home_config_paths = [
-                ".codegreeprc",
-                ".codegreeprc.yaml", 
-                ".codegreeprc.yml",
-                ".codegreeprc.toml",
-                ".codegreeprc.json",
-            ];
-            
-            for path in &home_config_paths {
-                let full_path = home_dir.join(path);
+            for name in CONFIG_FILE_NAMES {
+                let full_path = home_dir.join(name);
                 if full_path.exists() {
-                    return Self::load_from_file(&full_path);
+                    return Some(full_path);
                 }
             }
         }
         
-        // Return default config if no file found
-        Ok(Self::default())
+        None
     }
     
+    /// Writes the config, picking TOML or JSON from the extension and YAML otherwise
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> CodeGrepResult<()> {
-        let content = serde_yaml::to_string(self)
-            .map_err(|e| CodeGrepError::Config(format!("Failed to serialize config: {}", e)))?;
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+        let content = match extension.as_deref() {
+            Some("toml") => toml::to_string_pretty(self).map_err(|e| e.to_string()),
+            Some("json") => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            _ => serde_yaml::to_string(self).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| CodeGrepError::Config(format!("Failed to serialize config: {}", e)))?;
         
         fs::write(path, content)?;
         Ok(())
     }
     
+    pub fn saved_pattern(&self, name: &str) -> CodeGrepResult<&SavedPattern> {
+        self.patterns.get(name).ok_or_else(|| {
+            let mut known: Vec<_> = self.patterns.keys().map(String::as_str).collect();
+            known.sort();
+            CodeGrepError::Config(format!(
+                "Unknown saved pattern '{}' (available: {})",
+                name,
+                if known.is_empty() { "none".to_string() } else { known.join(", ") }
+            ))
+        })
+    }
+    
+    /// Lint the settings: ignore patterns must be valid globs, thread and memory limits must be
+    /// sensible, and an extension may belong to only one file type
+    pub fn validate(&self) -> Vec<CodeGrepError> {
+        let mut issues = Vec::new();
+        
+        let project_patterns = self.projects.values().flat_map(|project| &project.ignore_patterns);
+        for pattern in self.default.ignore_patterns.iter().chain(project_patterns) {
+            if let Err(e) = globset::Glob::new(pattern) {
+                issues.push(CodeGrepError::Config(format!("invalid ignore pattern '{}': {}", pattern, e.kind())));
+            }
+        }
+        for pattern in &self.default.test_patterns {
+            if let Err(e) = globset::Glob::new(pattern) {
+                issues.push(CodeGrepError::Config(format!("invalid test pattern '{}': {}", pattern, e.kind())));
+            }
+        }
+        
+        let performance = &self.default.performance;
+        if let (Some(max_threads), Ok(available)) = (performance.max_threads, std::thread::available_parallelism()) {
+            if max_threads > available.get() {
+                issues.push(CodeGrepError::Config(format!(
+                    "performance.max_threads is {} but only {} threads are available",
+                    max_threads, available
+                )));
+            }
+        }
+        if let Some(max_memory_mb) = performance.max_memory_mb.filter(|&mb| mb < MIN_MEMORY_MB) {
+            issues.push(CodeGrepError::Config(format!(
+                "performance.max_memory_mb is {} but must be at least {}",
+                max_memory_mb, MIN_MEMORY_MB
+            )));
+        }
+        
+        let mut types: Vec<_> = self.default.file_types.iter().collect();
+        types.sort();
+        let mut owners: HashMap<&str, &str> = HashMap::new();
+        for (file_type, extensions) in types {
+            for extension in extensions {
+                if let Some(owner) = owners.insert(extension, file_type) {
+                    issues.push(CodeGrepError::Config(format!(
+                        "extension '{}' is listed under file_types.{} and file_types.{}",
+                        extension, owner, file_type
+                    )));
+                }
+            }
+        }
+        
+        issues
+    }
+    
     pub fn get_file_extensions(&self, file_type: &str) -> Option<&Vec<String>> {
         self.default.file_types.get(file_type)
     }
@@ -231,8 +412,6 @@ fn glob_match(pattern: &str, text: &str) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::NamedTempFile;
-    use std::io::Write;
     
     #[test]
     fn test_default_config() {
@@ -257,4 +436,55 @@ mod tests {
         assert!(glob_match("node_modules*", "node_modules/something"));
         assert!(!glob_match("*.js", "test.rs"));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_validate_config() {
+        assert!(Config::default().validate().is_empty());
+        
+        let mut config = Config::default();
+        config.default.ignore_patterns.push("src/[unclosed".to_string());
+        config.default.performance.max_threads = Some(usize::MAX);
+        config.default.performance.max_memory_mb = Some(5);
+        config.default.file_types.insert("header".to_string(), vec!["h".to_string()]);
+        
+        let issues: Vec<String> = config.validate().iter().map(|issue| issue.to_string()).collect();
+        assert_eq!(issues.len(), 4, "{:?}", issues);
+        assert!(issues[0].contains("src/[unclosed"));
+        assert!(issues[1].contains("max_threads"));
+        assert!(issues[2].contains("max_memory_mb"));
+        assert!(issues[3].contains("'h'") && issues[3].contains("file_types.c") && issues[3].contains("file_types.header"));
+    }
+    
+    #[test]
+    fn test_save_to_file_picks_format_from_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.default.output.colors = false;
+        let expected = serde_json::to_value(&config).unwrap();
+        
+        for name in ["config.toml", "config.json", "config.yaml"] {
+            let path = dir.path().join(name);
+            config.save_to_file(&path).unwrap();
+            let loaded = Config::load_from_file(&path).unwrap();
+            assert_eq!(serde_json::to_value(&loaded).unwrap(), expected, "{}", name);
+        }
+        
+        let toml_text = fs::read_to_string(dir.path().join("config.toml")).unwrap();
+        assert!(toml::from_str::<Config>(&toml_text).is_ok());
+        let json_text = fs::read_to_string(dir.path().join("config.json")).unwrap();
+        assert!(serde_json::from_str::<Config>(&json_text).is_ok());
+    }
+    
+    #[cfg(feature = "config-schema")]
+    #[test]
+    fn test_config_schema() {
+        let schema = config_schema().unwrap();
+        assert_eq!(schema["title"], "Config");
+        assert_eq!(schema["description"], "`.codegreeprc` の設定");
+        
+        let output = &schema["definitions"]["OutputConfig"]["properties"];
+        assert_eq!(output["context"]["description"], "マッチの前後に表示するコンテキスト行数");
+        assert_eq!(output["context"]["default"], 2);
+        assert!(schema["definitions"]["SavedPattern"]["required"].as_array().unwrap().contains(&"pattern".into()));
+    }
+}
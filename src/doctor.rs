@@ -0,0 +1,101 @@
+use crate::{Cli, Config, FileWalker, CONFIG_FILE_NAMES};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// `cg doctor` の診断結果
+#[derive(Debug)]
+pub struct DoctorReport {
+    pub config_path: Option<PathBuf>,
+    pub misnamed_configs: Vec<PathBuf>,
+    pub threads: usize,
+    pub color_enabled: bool,
+    pub respect_gitignore: bool,
+    pub files_to_search: usize,
+}
+
+impl DoctorReport {
+    /// Collect the report using the given CLI options and config, walking the same paths a search would
+    pub fn collect(cli: &Cli, config: &Config) -> Self {
+        let walker = FileWalker::with_config(cli, config);
+        let files_to_search = walker
+            .walk()
+            .into_iter()
+            .filter_map(|path_result| path_result.ok())
+            .filter(|path| walker.should_include_file(path, cli))
+            .count();
+
+        Self {
+            config_path: Config::find_config_path(),
+            misnamed_configs: find_misnamed_configs(),
+            threads: cli.effective_threads(),
+            color_enabled: cli.should_use_color(),
+            respect_gitignore: cli.respect_gitignore,
+            files_to_search,
+        }
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Code-Grep Doctor")?;
+
+        match self.config_path {
+            Some(ref path) => writeln!(f, "  Config file:      {}", path.display())?,
+            None => writeln!(f, "  Config file:      (none, using defaults)")?,
+        }
+        for path in &self.misnamed_configs {
+            writeln!(
+                f,
+                "  Warning:          {} is ignored (config files are named {})",
+                path.display(),
+                CONFIG_FILE_NAMES[0]
+            )?;
+        }
+
+        writeln!(f, "  Threads:          {}", self.threads)?;
+        writeln!(f, "  Color output:     {}", if self.color_enabled { "enabled" } else { "disabled" })?;
+        writeln!(f, "  Respect ignores:  {}", if self.respect_gitignore { "yes" } else { "no" })?;
+        write!(f, "  Files to search:  {}", self.files_to_search)
+    }
+}
+
+/// Config files spelled `.codegreprc` look right but are never loaded
+fn find_misnamed_configs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from(".")];
+    if let Some(home_dir) = dirs::home_dir() {
+        dirs.push(home_dir);
+    }
+
+    dirs.iter()
+        .flat_map(|dir| {
+            CONFIG_FILE_NAMES
+                .iter()
+                .map(move |name| dir.join(name.replacen("greep", "grep", 1)))
+        })
+        .filter(|path| Path::new(path).exists())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_doctor_reports_file_count() {
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["a.rs", "b.rs", "c.txt"] {
+            fs::write(temp_dir.path().join(name), "content").unwrap();
+        }
+
+        let cli = Cli {
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+
+        let report = DoctorReport::collect(&cli, &Config::default());
+        assert_eq!(report.files_to_search, 3);
+        assert!(report.to_string().contains("Files to search:  3"));
+    }
+}
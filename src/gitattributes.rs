@@ -0,0 +1,138 @@
+use globset::{GlobBuilder, GlobMatcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// `.gitattributes` の `binary` / `-text` / `text` 指定によるバイナリ判定
+///
+/// ファイルのディレクトリからリポジトリのルート（`.git` のあるディレクトリ）まで
+/// `.gitattributes` を読み、後に書かれた・より深いディレクトリの指定を優先する。
+/// 読み込んだルールはディレクトリ単位でキャッシュする。
+#[derive(Default)]
+pub struct GitAttributes {
+    cache: Mutex<HashMap<PathBuf, Arc<Vec<AttributeRule>>>>,
+}
+
+struct AttributeRule {
+    base: PathBuf,
+    glob: GlobMatcher,
+    /// Patterns without a slash match the file name at any depth
+    match_file_name: bool,
+    binary: bool,
+}
+
+impl GitAttributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `Some(true)` for files marked binary, `Some(false)` for files marked text,
+    /// `None` when no `.gitattributes` rule says either way
+    pub fn is_binary(&self, path: &Path) -> Option<bool> {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let dir = dir.canonicalize().ok()?;
+        let path = dir.join(path.file_name()?);
+        let rules = self.rules_for(&dir);
+
+        // The last matching rule wins
+        rules.iter().rev().find(|rule| rule.matches(&path)).map(|rule| rule.binary)
+    }
+
+    fn rules_for(&self, dir: &Path) -> Arc<Vec<AttributeRule>> {
+        if let Some(rules) = self.cache.lock().unwrap().get(dir) {
+            return Arc::clone(rules);
+        }
+
+        // Collect from the repository root down so deeper files override shallower ones
+        let mut dirs = Vec::new();
+        for ancestor in dir.ancestors() {
+            dirs.push(ancestor);
+            if ancestor.join(".git").exists() {
+                break;
+            }
+        }
+
+        let rules: Vec<AttributeRule> = dirs
+            .into_iter()
+            .rev()
+            .filter_map(|dir| {
+                let content = fs::read_to_string(dir.join(".gitattributes")).ok()?;
+                Some(parse_gitattributes(&content, dir))
+            })
+            .flatten()
+            .collect();
+
+        let rules = Arc::new(rules);
+        self.cache.lock().unwrap().insert(dir.to_path_buf(), Arc::clone(&rules));
+        rules
+    }
+}
+
+impl AttributeRule {
+    fn matches(&self, path: &Path) -> bool {
+        if self.match_file_name {
+            path.file_name().is_some_and(|name| self.glob.is_match(name))
+        } else {
+            path.strip_prefix(&self.base).is_ok_and(|relative| self.glob.is_match(relative))
+        }
+    }
+}
+
+/// Parse the rules of one `.gitattributes` file that decide binary-ness
+fn parse_gitattributes(content: &str, base: &Path) -> Vec<AttributeRule> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            let mut fields = line.split_whitespace();
+            let pattern = fields.next()?;
+            let binary = fields.fold(None, |binary, attribute| match attribute {
+                "binary" | "-text" => Some(true),
+                "text" => Some(false),
+                _ => binary,
+            })?;
+
+            let match_file_name = !pattern.trim_end_matches('/').contains('/');
+            let glob = GlobBuilder::new(pattern.trim_start_matches('/'))
+                .literal_separator(true)
+                .build()
+                .ok()?
+                .compile_matcher();
+
+            Some(AttributeRule {
+                base: base.to_path_buf(),
+                glob,
+                match_file_name,
+                binary,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_gitattributes_rules() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("assets/text")).unwrap();
+        fs::write(root.join(".gitattributes"), "*.dat binary\n/assets/** -text\n*.md text\n").unwrap();
+        fs::write(root.join("assets/text/.gitattributes"), "*.svg text\n").unwrap();
+
+        let attributes = GitAttributes::new();
+        assert_eq!(attributes.is_binary(&root.join("data.dat")), Some(true));
+        assert_eq!(attributes.is_binary(&root.join("assets/logo.png")), Some(true));
+        assert_eq!(attributes.is_binary(&root.join("assets/text/logo.svg")), Some(false));
+        assert_eq!(attributes.is_binary(&root.join("README.md")), Some(false));
+        assert_eq!(attributes.is_binary(&root.join("main.rs")), None);
+    }
+}
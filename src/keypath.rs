@@ -0,0 +1,411 @@
+use crate::{CodeGrepError, CodeGrepResult};
+
+/// One step of a key path such as `servers[0].host`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySegment {
+    Key(String),
+    Index(usize),
+}
+
+/// A scalar value in a JSON or YAML document and where its text sits in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyedValue {
+    pub path: Vec<KeySegment>,
+    /// 1-based line of the value
+    pub line: usize,
+    /// Byte range of the value text within its line (without quotes)
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Parse dot/bracket notation: `database.host`, `servers[0].name`, `["key.with.dots"]`
+pub fn parse_key_path(path: &str) -> CodeGrepResult<Vec<KeySegment>> {
+    let invalid = |reason: &str| CodeGrepError::Config(format!("Invalid key path '{}': {}", path, reason));
+    let mut segments = Vec::new();
+    let mut chars = path.chars().peekable();
+    let mut key = String::new();
+    let mut after_bracket = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                // `a[0].b` has nothing between `]` and `.`; anywhere else that is an empty key
+                if !key.is_empty() {
+                    segments.push(KeySegment::Key(std::mem::take(&mut key)));
+                } else if !after_bracket {
+                    return Err(invalid("empty key"));
+                }
+                after_bracket = false;
+            }
+            '[' => {
+                if !key.is_empty() {
+                    segments.push(KeySegment::Key(std::mem::take(&mut key)));
+                }
+                let mut inner = String::new();
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    inner.push(c);
+                }
+                let quoted = inner
+                    .strip_prefix('"')
+                    .and_then(|inner| inner.strip_suffix('"'))
+                    .or_else(|| inner.strip_prefix('\'').and_then(|inner| inner.strip_suffix('\'')));
+                let segment = match quoted {
+                    Some(name) => KeySegment::Key(name.to_string()),
+                    None => KeySegment::Index(inner.trim().parse().map_err(|_| invalid("expected [N] or [\"key\"]"))?),
+                };
+                segments.push(segment);
+                after_bracket = true;
+            }
+            _ => {
+                key.push(c);
+                after_bracket = false;
+            }
+        }
+    }
+    if path.ends_with('.') {
+        return Err(invalid("empty key"));
+    }
+    if !key.is_empty() {
+        segments.push(KeySegment::Key(key));
+    }
+
+    if segments.is_empty() {
+        return Err(invalid("empty path"));
+    }
+    Ok(segments)
+}
+
+/// Scalar values of a JSON document with their positions
+pub fn json_values(source: &str) -> CodeGrepResult<Vec<KeyedValue>> {
+    // Let serde_json report malformed input; the scanner below can then assume valid JSON
+    serde_json::from_str::<serde::de::IgnoredAny>(source)
+        .map_err(|e| CodeGrepError::Parser(format!("Invalid JSON: {}", e)))?;
+
+    let mut scanner = JsonScanner {
+        source,
+        bytes: source.as_bytes(),
+        pos: 0,
+        path: Vec::new(),
+        lines: LineIndex::new(source),
+        values: Vec::new(),
+    };
+    scanner.value()?;
+    Ok(scanner.values)
+}
+
+/// Scalar values of a block-style YAML document with their positions.
+/// Flow collections (`{...}`, `[...]`) and block scalars (`|`, `>`) are not looked into.
+pub fn yaml_values(source: &str) -> Vec<KeyedValue> {
+    // Open mappings and sequence items, innermost last, with the column they start at
+    let mut frames: Vec<(usize, KeySegment)> = Vec::new();
+    let mut values = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let content = strip_yaml_comment(line);
+        let trimmed = content.trim_start();
+        if trimmed.is_empty() || trimmed == "---" || trimmed == "..." {
+            continue;
+        }
+
+        let mut column = content.len() - trimmed.len();
+        let mut rest = trimmed;
+
+        // `- item` opens the next index of the sequence at this column
+        while rest == "-" || rest.starts_with("- ") {
+            while frames.last().is_some_and(|(indent, _)| *indent > column) {
+                frames.pop();
+            }
+            let index = match frames.last() {
+                Some((indent, KeySegment::Index(previous))) if *indent == column => {
+                    let next = previous + 1;
+                    frames.pop();
+                    next
+                }
+                _ => 0,
+            };
+            frames.push((column, KeySegment::Index(index)));
+
+            let item = rest[1..].trim_start();
+            column += rest.len() - item.len();
+            rest = item;
+        }
+        if rest.is_empty() {
+            continue;
+        }
+
+        let value_start = match split_yaml_key(rest) {
+            Some((key, value_offset)) => {
+                while frames.last().is_some_and(|(indent, _)| *indent >= column) {
+                    frames.pop();
+                }
+                frames.push((column, KeySegment::Key(key)));
+                column + value_offset
+            }
+            None => column,
+        };
+
+        let value = content[value_start..].trim();
+        if value.is_empty() || value.starts_with(['|', '>', '{', '[', '&', '*']) {
+            continue;
+        }
+        let start = value_start + (content[value_start..].len() - content[value_start..].trim_start().len());
+        let (start, end) = unquote_span(content, start, start + value.len());
+        values.push(KeyedValue {
+            path: frames.iter().map(|(_, segment)| segment.clone()).collect(),
+            line: line_index + 1,
+            start,
+            end,
+        });
+    }
+
+    values
+}
+
+/// Drop a `# comment` that starts outside quotes
+fn strip_yaml_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && previous.is_whitespace() => return &line[..i],
+            None => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+/// `key: value` → the unquoted key and the byte offset just after the colon
+fn split_yaml_key(text: &str) -> Option<(String, usize)> {
+    let (key, after_key) = if let Some(quote) = text.chars().next().filter(|c| *c == '"' || *c == '\'') {
+        let close = text[1..].find(quote)? + 1;
+        (text[1..close].to_string(), close + 1)
+    } else {
+        let colon = text.match_indices(':').find(|(i, _)| {
+            text[i + 1..].is_empty() || text[i + 1..].starts_with(' ')
+        })?;
+        (text[..colon.0].trim_end().to_string(), colon.0)
+    };
+
+    let after_colon = text[after_key..].trim_start().strip_prefix(':')?;
+    if !after_colon.is_empty() && !after_colon.starts_with(' ') {
+        return None;
+    }
+    Some((key, text.len() - after_colon.len()))
+}
+
+/// Narrow a quoted scalar's span to the text between the quotes
+fn unquote_span(line: &str, start: usize, end: usize) -> (usize, usize) {
+    let value = &line[start..end];
+    let quoted = value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"')) || (value.starts_with('\'') && value.ends_with('\'')));
+    if quoted {
+        (start + 1, end - 1)
+    } else {
+        (start, end)
+    }
+}
+
+/// Byte offsets where each line starts, for turning offsets into (line, column)
+struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(source: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { starts }
+    }
+
+    /// 1-based line and the byte offset of its start
+    fn locate(&self, offset: usize) -> (usize, usize) {
+        let line = self.starts.partition_point(|&start| start <= offset);
+        (line, self.starts[line - 1])
+    }
+}
+
+struct JsonScanner<'a> {
+    source: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    path: Vec<KeySegment>,
+    lines: LineIndex,
+    values: Vec<KeyedValue>,
+}
+
+impl JsonScanner<'_> {
+    fn value(&mut self) -> CodeGrepResult<()> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() == Some(b'}') {
+                        self.pos += 1;
+                        return Ok(());
+                    }
+                    let (start, end) = self.string()?;
+                    let key = serde_json::from_str::<String>(&self.source[start - 1..end + 1])
+                        .map_err(|e| CodeGrepError::Parser(format!("Invalid JSON key: {}", e)))?;
+                    self.skip_whitespace();
+                    self.expect(b':')?;
+                    self.path.push(KeySegment::Key(key));
+                    self.value()?;
+                    self.path.pop();
+                    if !self.separator(b'}')? {
+                        return Ok(());
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut index = 0;
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() == Some(b']') {
+                        self.pos += 1;
+                        return Ok(());
+                    }
+                    self.path.push(KeySegment::Index(index));
+                    self.value()?;
+                    self.path.pop();
+                    index += 1;
+                    if !self.separator(b']')? {
+                        return Ok(());
+                    }
+                }
+            }
+            Some(b'"') => {
+                let (start, end) = self.string()?;
+                self.record(start, end);
+                Ok(())
+            }
+            Some(_) => {
+                let start = self.pos;
+                while self.peek().is_some_and(|b| !matches!(b, b',' | b']' | b'}') && !b.is_ascii_whitespace()) {
+                    self.pos += 1;
+                }
+                self.record(start, self.pos);
+                Ok(())
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    /// Inner byte range of the string starting at the current position
+    fn string(&mut self) -> CodeGrepResult<(usize, usize)> {
+        self.expect(b'"')?;
+        let start = self.pos;
+        while let Some(b) = self.peek() {
+            match b {
+                b'\\' => self.pos += 2,
+                b'"' => {
+                    self.pos += 1;
+                    return Ok((start, self.pos - 1));
+                }
+                _ => self.pos += 1,
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    /// After a member: `true` on `,`, `false` on the closing bracket
+    fn separator(&mut self, close: u8) -> CodeGrepResult<bool> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b',') => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(b) if b == close => {
+                self.pos += 1;
+                Ok(false)
+            }
+            _ => Err(self.error("expected ',' or closing bracket")),
+        }
+    }
+
+    fn record(&mut self, start: usize, end: usize) {
+        let (line, line_start) = self.lines.locate(start);
+        self.values.push(KeyedValue {
+            path: self.path.clone(),
+            line,
+            start: start - line_start,
+            end: end - line_start,
+        });
+    }
+
+    fn expect(&mut self, byte: u8) -> CodeGrepResult<()> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, message: &str) -> CodeGrepError {
+        CodeGrepError::Parser(format!("JSON scan failed at byte {}: {}", self.pos, message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> KeySegment {
+        KeySegment::Key(name.to_string())
+    }
+
+    #[test]
+    fn test_parse_key_path() {
+        assert_eq!(parse_key_path("database.host").unwrap(), vec![key("database"), key("host")]);
+        assert_eq!(
+            parse_key_path("servers[1].name").unwrap(),
+            vec![key("servers"), KeySegment::Index(1), key("name")]
+        );
+        assert_eq!(parse_key_path(r#"a["b.c"]"#).unwrap(), vec![key("a"), key("b.c")]);
+        assert!(parse_key_path("").is_err());
+        assert!(parse_key_path("a..b").is_err());
+        assert!(parse_key_path("a[x]").is_err());
+    }
+
+    #[test]
+    fn test_json_and_yaml_values() {
+        let json = "{\n  \"database\": {\"host\": \"db.local\", \"port\": 5432},\n  \"tags\": [\"a\", \"b\"]\n}";
+        let values = json_values(json).unwrap();
+        let host = values.iter().find(|v| v.path == vec![key("database"), key("host")]).unwrap();
+        assert_eq!((host.line, &json.lines().nth(1).unwrap()[host.start..host.end]), (2, "db.local"));
+        assert!(values.iter().any(|v| v.path == vec![key("tags"), KeySegment::Index(1)] && v.line == 3));
+
+        let yaml = "database:\n  host: db.local # primary\nservers:\n- name: one\n  port: 1\n- name: 'two'\n";
+        let values = yaml_values(yaml);
+        let lines: Vec<&str> = yaml.lines().collect();
+        let found: Vec<(usize, &str)> = values
+            .iter()
+            .filter(|v| matches!(v.path.last(), Some(KeySegment::Key(k)) if k == "host" || k == "name"))
+            .map(|v| (v.line, &lines[v.line - 1][v.start..v.end]))
+            .collect();
+        assert_eq!(found, vec![(2, "db.local"), (4, "one"), (6, "two")]);
+        assert_eq!(values[3].path, vec![key("servers"), KeySegment::Index(1), key("name")]);
+    }
+}
@@ -1,19 +1,47 @@
+pub mod app;
+pub mod ast;
+pub mod benchmark;
 pub mod cli;
 pub mod config;
+pub mod doctor;
+pub mod gitattributes;
+pub mod keypath;
 pub mod matcher;
+pub mod network;
 pub mod output;
 pub mod parser;
+pub mod posix_grep;
 pub mod replacer;
+pub mod rg_compat;
+pub mod rules;
 pub mod searcher;
+pub mod sparse;
+pub mod spool;
+#[cfg(feature = "syntax-highlight")]
+pub mod syntax;
 pub mod walker;
 
+pub use app::*;
+pub use ast::*;
+pub use benchmark::*;
 pub use cli::*;
 pub use config::*;
+pub use doctor::*;
+pub use gitattributes::*;
+pub use keypath::*;
 pub use matcher::*;
+pub use network::*;
 pub use output::*;
 pub use parser::*;
+pub use posix_grep::*;
 pub use replacer::*;
+pub use rg_compat::*;
+pub use rules::*;
 pub use searcher::*;
+pub use sparse::*;
+pub use spool::*;
+#[cfg(feature = "syntax-highlight")]
+pub use syntax::*;
 pub use walker::*;
 
 use anyhow::Result;
@@ -40,4 +68,4 @@ pub enum CodeGrepError {
     Parser(String),
 }
 
-pub type CodeGrepResult<T> = Result<T, CodeGrepError>;
\ No newline at end of file
+pub type CodeGrepResult<T> = Result<T, CodeGrepError>;
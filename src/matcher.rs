@@ -1,6 +1,37 @@
 use crate::{Cli, CodeGrepError, CodeGrepResult};
 use fancy_regex::Regex as FancyRegex;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::hint::black_box;
+use std::io::Read;
+use std::path::Path;
+#[cfg(feature = "debug-matcher")]
+use std::sync::Arc;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Size of the sample `--fast` times the candidate engines on
+const BENCHMARK_SAMPLE_BYTES: u64 = 64 * 1024;
+
+/// `--todos`: a marker, an optional `(author)`, and the note after an optional colon
+const TODO_PATTERN: &str = r"\b(TODO|FIXME|HACK|XXX)\b(?:\(([^)]*)\))?:?[ \t]*(.*)";
+
+/// `--todos` matches only the marker word, so that is what gets highlighted
+const TODO_MARKER_PATTERN: &str = r"\b(?:TODO|FIXME|HACK|XXX)\b";
+
+static TODO_REGEX: OnceLock<Regex> = OnceLock::new();
+static TODO_MARKER_REGEX: OnceLock<Regex> = OnceLock::new();
+
+#[cfg(feature = "debug-matcher")]
+thread_local! {
+    /// File the current thread is searching, for `--debug-matcher-log` entries
+    static DEBUG_FILE: std::cell::RefCell<Option<std::path::PathBuf>> = const { std::cell::RefCell::new(None) };
+}
+
+/// `--fast` engine decisions per pattern (true when the literal matcher won), kept for the process lifetime
+static FAST_ENGINE_CHOICES: OnceLock<Mutex<HashMap<String, bool>>> = OnceLock::new();
 
 #[derive(Clone)]
 pub enum PatternMatcher {
@@ -8,48 +39,154 @@ pub enum PatternMatcher {
     Basic(Regex),
     Fancy(FancyRegex),
     Multiple(Vec<PatternMatcher>),
+    /// `--approx`: substrings within `max_distance` edits of the pattern
+    Approx {
+        pattern: Vec<char>,
+        max_distance: usize,
+        case_sensitive: bool,
+    },
+    /// `--debug-matcher-log`: `inner` with every `find_matches` / `find_spans` call recorded in `log`
+    #[cfg(feature = "debug-matcher")]
+    Debug {
+        inner: Box<PatternMatcher>,
+        log: Arc<Mutex<Vec<String>>>,
+    },
 }
 
 impl PatternMatcher {
     pub fn new(cli: &Cli) -> CodeGrepResult<Self> {
+        Self::create(cli, None)
+    }
+    
+    /// Like `new`, with `--fast` timing the candidate engines on `sample`, the first file searched
+    pub fn with_benchmark_sample(cli: &Cli, sample: &Path) -> CodeGrepResult<Self> {
+        Self::create(cli, Some(sample))
+    }
+    
+    fn create(cli: &Cli, sample: Option<&Path>) -> CodeGrepResult<Self> {
+        let matcher = Self::build(cli, sample)?;
+        #[cfg(feature = "debug-matcher")]
+        if cli.debug_matcher_log.is_some() {
+            return Ok(PatternMatcher::Debug {
+                inner: Box::new(matcher),
+                log: Arc::new(Mutex::new(Vec::new())),
+            });
+        }
+        Ok(matcher)
+    }
+    
+    fn build(cli: &Cli, sample: Option<&Path>) -> CodeGrepResult<Self> {
+        if cli.todos {
+            return Ok(PatternMatcher::Basic(Regex::new(TODO_MARKER_PATTERN)?));
+        }
+        
         // Handle multiple patterns (AND/OR)
         if !cli.and.is_empty() {
             let mut matchers = Vec::new();
             if let Some(ref pattern) = cli.pattern {
-                matchers.push(Self::create_single_matcher(pattern, cli)?);
+                matchers.push(Self::create_single_matcher(pattern, cli, sample)?);
             }
             for pattern in &cli.and {
-                matchers.push(Self::create_single_matcher(pattern, cli)?);
+                matchers.push(Self::create_single_matcher(pattern, cli, sample)?);
             }
             return Ok(PatternMatcher::Multiple(matchers));
         }
         
         if !cli.or.is_empty() {
             let pattern = if let Some(ref p) = cli.pattern {
-                format!("({}|{})", p, cli.or.join("|"))
+                format!("(?:{}|{})", p, cli.or.join("|"))
             } else {
-                format!("({})", cli.or.join("|"))
+                format!("(?:{})", cli.or.join("|"))
             };
-            return Self::create_single_matcher(&pattern, cli);
+            return Self::create_single_matcher(&pattern, cli, sample);
         }
         
         // Single pattern
         if let Some(ref pattern) = cli.pattern {
-            Self::create_single_matcher(pattern, cli)
+            Self::create_single_matcher(pattern, cli, sample)
         } else {
             Err(CodeGrepError::Config("No pattern provided".to_string()))
         }
     }
     
-    fn create_single_matcher(pattern: &str, cli: &Cli) -> CodeGrepResult<Self> {
-        if cli.literal {
-            Ok(PatternMatcher::Literal(pattern.to_string()))
+    /// Matcher for `--line-regex`, which a whole line must match before the primary pattern is tried
+    pub fn line_filter(cli: &Cli) -> CodeGrepResult<Option<Self>> {
+        cli.line_regex
+            .as_deref()
+            .map(|pattern| Ok(PatternMatcher::Basic(Regex::new(pattern)?)))
+            .transpose()
+    }
+    
+    fn create_single_matcher(pattern: &str, cli: &Cli, sample: Option<&Path>) -> CodeGrepResult<Self> {
+        // Only matching ignores case; match and line text are still sliced from the file as is
+        if cli.ignore_case_pattern && cli.case_sensitive {
+            return Self::create_single_matcher(pattern, &Cli {
+                case_sensitive: false,
+                ..cli.clone()
+            }, sample);
+        }
+        
+        // A glob is matched as the regex it translates to
+        if cli.glob_pattern {
+            return Self::create_single_matcher(&glob_to_regex(pattern), &Cli {
+                glob_pattern: false,
+                regex: true,
+                ..cli.clone()
+            }, sample);
+        }
+        
+        // Likewise the token around an infix, which the pattern is taken literally in
+        if cli.infix_pattern {
+            return Self::create_single_matcher(&infix_regex(pattern), &Cli {
+                infix_pattern: false,
+                literal: false,
+                regex: true,
+                ..cli.clone()
+            }, sample);
+        }
+        
+        // --thorough always runs the fancy engine; text taken literally is escaped for it first
+        if cli.thorough && !cli.fancy_regex && cli.approx.is_none() {
+            let is_regex = cli.regex || (!cli.literal && has_regex_metachar(pattern));
+            let (regex_pattern, ignore_whitespace) = match (is_regex, cli.ignore_whitespace) {
+                (true, ignore_whitespace) => (pattern.to_string(), ignore_whitespace),
+                (false, true) => (whitespace_insensitive_regex(pattern), false),
+                (false, false) => (regex::escape(pattern), false),
+            };
+            return Self::create_single_matcher(&regex_pattern, &Cli {
+                fancy_regex: true,
+                regex: false,
+                literal: false,
+                ignore_whitespace,
+                ..cli.clone()
+            }, sample);
+        }
+        
+        if cli.fast && cli.approx.is_none() {
+            if let Some(matcher) = Self::fastest_literal_matcher(pattern, cli, sample)? {
+                return Ok(matcher);
+            }
+        }
+        
+        if let Some(max_distance) = cli.approx {
+            Ok(PatternMatcher::Approx {
+                pattern: pattern.chars().collect(),
+                max_distance,
+                case_sensitive: cli.case_sensitive,
+            })
+        } else if cli.literal {
+            Self::create_literal_matcher(pattern, cli)
         } else if cli.fancy_regex {
             let mut regex_pattern = pattern.to_string();
             
             // Add word boundaries if requested
             if cli.word_boundary {
-                regex_pattern = format!(r"\b{}\b", regex_pattern);
+                regex_pattern = word_bounded(&regex_pattern);
+            }
+            
+            // Extended mode: unescaped whitespace in the pattern is ignored
+            if cli.ignore_whitespace {
+                regex_pattern = format!("(?x){}", regex_pattern);
             }
             
             let regex = if cli.case_sensitive {
@@ -64,100 +201,460 @@ impl PatternMatcher {
             
             // Add word boundaries if requested
             if cli.word_boundary {
-                regex_pattern = format!(r"\b{}\b", regex_pattern);
+                regex_pattern = word_bounded(&regex_pattern);
             }
             
             let mut builder = regex::RegexBuilder::new(&regex_pattern);
             builder.case_insensitive(!cli.case_sensitive);
+            builder.ignore_whitespace(cli.ignore_whitespace);
             
             let regex = builder.build()?;
             Ok(PatternMatcher::Basic(regex))
         } else {
             // Default: treat as literal unless it contains regex metacharacters
-            if pattern.chars().any(|c| matches!(c, '.' | '*' | '+' | '?' | '^' | '$' | '|' | '[' | ']' | '(' | ')' | '{' | '}' | '\\')) {
+            if has_regex_metachar(pattern) {
                 Self::create_single_matcher(pattern, &Cli {
                     regex: true,
                     ..cli.clone()
-                })
+                }, sample)
             } else {
-                Ok(PatternMatcher::Literal(pattern.to_string()))
+                Self::create_literal_matcher(pattern, cli)
+            }
+        }
+    }
+    
+    /// `--fast`: for plain-text patterns both the literal matcher and the regex engine give the
+    /// same matches, so time each on a sample of the first searched file and keep the faster one.
+    /// `None` when only one engine applies, or when the choice is not cached and there is no sample.
+    fn fastest_literal_matcher(pattern: &str, cli: &Cli, sample: Option<&Path>) -> CodeGrepResult<Option<Self>> {
+        // The literal matcher always ignores case and knows nothing of word boundaries or whitespace
+        let is_plain_text = cli.literal || (!cli.fancy_regex && regex::escape(pattern) == pattern);
+        if !is_plain_text || cli.case_sensitive || cli.word_boundary || cli.ignore_whitespace || pattern.is_empty() {
+            return Ok(None);
+        }
+        
+        let literal = PatternMatcher::Literal(pattern.to_string());
+        let basic = PatternMatcher::Basic(
+            regex::RegexBuilder::new(&regex::escape(pattern))
+                .case_insensitive(true)
+                .build()?,
+        );
+        
+        let choices = FAST_ENGINE_CHOICES.get_or_init(Default::default);
+        let cached = choices.lock().unwrap().get(pattern).copied();
+        let literal_wins = match cached {
+            Some(literal_wins) => literal_wins,
+            None => {
+                let Some(sample) = sample.and_then(benchmark_sample) else {
+                    return Ok(None);
+                };
+                let literal_wins = time_matcher(&literal, &sample) <= time_matcher(&basic, &sample);
+                choices.lock().unwrap().insert(pattern.to_string(), literal_wins);
+                literal_wins
             }
+        };
+        
+        Ok(Some(if literal_wins { literal } else { basic }))
+    }
+    
+    fn create_literal_matcher(pattern: &str, cli: &Cli) -> CodeGrepResult<Self> {
+        if !cli.ignore_whitespace && !cli.word_boundary {
+            return Ok(PatternMatcher::Literal(pattern.to_string()));
         }
+        if !cli.ignore_whitespace {
+            let mut builder = regex::RegexBuilder::new(&word_bounded(&regex::escape(pattern)));
+            builder.case_insensitive(!cli.case_sensitive);
+            return Ok(PatternMatcher::Basic(builder.build()?));
+        }
+        
+        let mut regex_pattern = whitespace_insensitive_regex(pattern);
+        if cli.word_boundary {
+            regex_pattern = word_bounded(&regex_pattern);
+        }
+        
+        let mut builder = regex::RegexBuilder::new(&regex_pattern);
+        builder.case_insensitive(!cli.case_sensitive);
+        Ok(PatternMatcher::Basic(builder.build()?))
     }
     
     pub fn find_matches(&self, text: &str) -> Vec<Match> {
+        match self {
+            #[cfg(feature = "debug-matcher")]
+            PatternMatcher::Debug { inner, log } => {
+                let matches = inner.find_matches(text);
+                record_debug_entry(log, text, matches.iter().map(|m| (m.start, m.end)));
+                matches
+            }
+            PatternMatcher::Basic(regex) if regex.captures_len() > 1 => regex
+                .captures_iter(text)
+                .map(|caps| {
+                    let whole = caps.get(0).expect("group 0 always matches");
+                    let groups = caps.iter().skip(1).map(|group| group.map(|g| (g.start(), g.end())));
+                    Match::with_captures(text, whole.start(), whole.end(), groups)
+                })
+                .collect(),
+            PatternMatcher::Fancy(regex) if regex.captures_len() > 1 => regex
+                .captures_iter(text)
+                .filter_map(|caps| caps.ok())
+                .filter_map(|caps| {
+                    let whole = caps.get(0)?;
+                    let groups = caps.iter().skip(1).map(|group| group.map(|g| (g.start(), g.end())));
+                    Some(Match::with_captures(text, whole.start(), whole.end(), groups))
+                })
+                .collect(),
+            _ => self
+                .find_spans(text)
+                .into_iter()
+                .map(|(start, end)| Match::with_captures(text, start, end, std::iter::empty()))
+                .collect(),
+        }
+    }
+    
+    /// Byte ranges of the matches in `text`, without copying the matched text
+    pub fn find_spans(&self, text: &str) -> Vec<(usize, usize)> {
         match self {
             PatternMatcher::Literal(pattern) => {
-                let mut matches = Vec::new();
-                let search_text = text.to_lowercase();
+                let mut spans = Vec::new();
+                let (search_text, offsets) = fold_case(text);
                 let search_pattern = pattern.to_lowercase();
                 
                 let mut start = 0;
                 while let Some(pos) = search_text[start..].find(&search_pattern) {
                     let absolute_pos = start + pos;
-                    matches.push(Match {
-                        start: absolute_pos,
-                        end: absolute_pos + pattern.len(),
-                        text: text[absolute_pos..absolute_pos + pattern.len()].to_string(),
+                    let end = absolute_pos + search_pattern.len();
+                    spans.push(match offsets {
+                        Some(ref offsets) if end > absolute_pos => (offsets[absolute_pos].0, offsets[end - 1].1),
+                        Some(ref offsets) => {
+                            let at = offsets.get(absolute_pos).map_or(text.len(), |&(char_start, _)| char_start);
+                            (at, at)
+                        }
+                        None => (absolute_pos, end),
                     });
-                    start = absolute_pos + 1;
+                    
+                    // Continue after the match, moving at least one character for empty patterns
+                    start = if end > absolute_pos {
+                        end
+                    } else {
+                        match search_text[absolute_pos..].chars().next() {
+                            Some(c) => absolute_pos + c.len_utf8(),
+                            None => break,
+                        }
+                    };
                 }
-                matches
+                spans
             }
             PatternMatcher::Basic(regex) => {
                 regex.find_iter(text)
-                    .map(|m| Match {
-                        start: m.start(),
-                        end: m.end(),
-                        text: m.as_str().to_string(),
-                    })
+                    .map(|m| (m.start(), m.end()))
                     .collect()
             }
             PatternMatcher::Fancy(regex) => {
                 regex.find_iter(text)
                     .filter_map(|m| m.ok())
-                    .map(|m| Match {
-                        start: m.start(),
-                        end: m.end(),
-                        text: m.as_str().to_string(),
-                    })
+                    .map(|m| (m.start(), m.end()))
                     .collect()
             }
+            PatternMatcher::Approx { pattern, max_distance, case_sensitive } => {
+                find_approximate(text, pattern, *max_distance, *case_sensitive)
+            }
             PatternMatcher::Multiple(matchers) => {
                 // For AND operation, all patterns must match on the same line
-                if matchers.iter().all(|matcher| !matcher.find_matches(text).is_empty()) {
+                if matchers.iter().all(|matcher| !matcher.find_spans(text).is_empty()) {
                     // Return matches from the first pattern
-                    matchers[0].find_matches(text)
+                    matchers[0].find_spans(text)
                 } else {
                     Vec::new()
                 }
             }
+            #[cfg(feature = "debug-matcher")]
+            PatternMatcher::Debug { inner, log } => {
+                let spans = inner.find_spans(text);
+                record_debug_entry(log, text, spans.iter().copied());
+                spans
+            }
+        }
+    }
+    
+    /// Capture groups 1.. of the match starting at byte offset `start` (empty for literals)
+    pub fn capture_groups(&self, text: &str, start: usize) -> Vec<Option<String>> {
+        let to_strings = |groups: Vec<Option<&str>>| groups.into_iter().map(|g| g.map(str::to_string)).collect();
+        
+        match self {
+            PatternMatcher::Literal(_) | PatternMatcher::Approx { .. } => Vec::new(),
+            PatternMatcher::Basic(regex) => regex
+                .captures_at(text, start)
+                .map(|caps| to_strings(caps.iter().skip(1).map(|g| g.map(|m| m.as_str())).collect()))
+                .unwrap_or_default(),
+            PatternMatcher::Fancy(regex) => regex
+                .captures_from_pos(text, start)
+                .ok()
+                .flatten()
+                .map(|caps| to_strings(caps.iter().skip(1).map(|g| g.map(|m| m.as_str())).collect()))
+                .unwrap_or_default(),
+            PatternMatcher::Multiple(matchers) => matchers
+                .first()
+                .map(|matcher| matcher.capture_groups(text, start))
+                .unwrap_or_default(),
+            #[cfg(feature = "debug-matcher")]
+            PatternMatcher::Debug { inner, .. } => inner.capture_groups(text, start),
         }
     }
     
+    /// Writes the `--debug-matcher-log` entries recorded so far to `path`, one per line
+    #[cfg(feature = "debug-matcher")]
+    pub fn write_debug_log(&self, path: &Path) -> CodeGrepResult<()> {
+        if let PatternMatcher::Debug { log, .. } = self {
+            let log = log.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let mut content = log.join("\n");
+            content.push('\n');
+            std::fs::write(path, content)?;
+        }
+        Ok(())
+    }
+    
     pub fn is_match(&self, text: &str) -> bool {
         !self.find_matches(text).is_empty()
     }
+    
+    /// Match against the whole text and report each match by its line/column range
+    pub fn find_matches_multiline(&self, text: &str) -> Vec<MultilineMatch> {
+        self.find_matches(text)
+            .into_iter()
+            .map(|m| {
+                let (start_line, start_col) = line_and_column(text, m.start);
+                let (end_line, end_col) = line_and_column(text, m.end);
+                MultilineMatch {
+                    start_line,
+                    end_line,
+                    start_col,
+                    end_col,
+                    text: m.text,
+                }
+            })
+            .collect()
+    }
 }
 
-#[derive(Debug, Clone)]
+/// `text` lowercased for the case-insensitive literal search. Lowercasing can change the byte
+/// length of a character (`İ` becomes `i̇`), so for non-ASCII text each byte of the folded text
+/// also gets the span of the character of `text` it came from; ASCII text keeps its offsets.
+fn fold_case(text: &str) -> (String, Option<Vec<(usize, usize)>>) {
+    if text.is_ascii() {
+        return (text.to_ascii_lowercase(), None);
+    }
+    let mut folded = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+    for (start, c) in text.char_indices() {
+        let before = folded.len();
+        folded.extend(c.to_lowercase());
+        offsets.resize(offsets.len() + folded.len() - before, (start, start + c.len_utf8()));
+    }
+    (folded, Some(offsets))
+}
+
+/// Regex matching what the shell glob `glob` matches within a line: `*` is any run of
+/// characters, `?` any one character and `[..]` (or `[!..]`) a character class. `\` escapes
+/// the next character and a `[` that is never closed is an ordinary character.
+pub fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                regex.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            '[' => {
+                // `]` right after the opening (or `!`) is part of the class
+                let negated = chars.get(i + 1) == Some(&'!');
+                let first = i + 1 + negated as usize;
+                let close = (first + 1..chars.len()).find(|&j| chars[j] == ']');
+                match close {
+                    Some(close) => {
+                        regex.push('[');
+                        if negated {
+                            regex.push('^');
+                        }
+                        for &c in &chars[first..close] {
+                            if matches!(c, '[' | ']' | '\\' | '^' | '&' | '~') {
+                                regex.push('\\');
+                            }
+                            regex.push(c);
+                        }
+                        regex.push(']');
+                        i = close;
+                    }
+                    None => regex.push_str(r"\["),
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    regex
+}
+
+/// Regex matching the whitespace-delimited tokens that contain `infix`. The leftmost match
+/// starts where the token does, and the greedy tail runs to its end.
+pub fn infix_regex(infix: &str) -> String {
+    format!(r"\S*{}\S*", regex::escape(infix))
+}
+
+/// `pattern` wrapped in `\b(?:...)\b`, after any leading inline flag groups such as `(?i)`
+/// so they keep applying to the whole pattern, e.g. `(?i)a|b` becomes `(?i)\b(?:a|b)\b`
+fn word_bounded(pattern: &str) -> String {
+    let (flags, rest) = split_inline_flags(pattern);
+    format!(r"{}\b(?:{})\b", flags, rest)
+}
+
+/// Splits the leading inline flag groups (`(?i)`, `(?im)`, `(?-i)`, ...) off `pattern`;
+/// scoped groups like `(?i:...)` and `(?:...)` are part of the pattern, not flags
+fn split_inline_flags(pattern: &str) -> (&str, &str) {
+    let mut end = 0;
+    while let Some(group) = pattern[end..].strip_prefix("(?") {
+        let flags_len = group.find(|c: char| !(c.is_ascii_alphabetic() || c == '-')).unwrap_or(group.len());
+        if flags_len == 0 || !group[flags_len..].starts_with(')') {
+            break;
+        }
+        end += 2 + flags_len + 1;
+    }
+    pattern.split_at(end)
+}
+
+/// Approximate substring search (Sellers' algorithm): spans of `text` within `max_distance`
+/// insertions, deletions or substitutions of `pattern`. Overlapping candidates are resolved
+/// to the one with the fewest edits, preferring the earliest.
+fn find_approximate(text: &str, pattern: &[char], max_distance: usize, case_sensitive: bool) -> Vec<(usize, usize)> {
+    let fold = |c: char| if case_sensitive { c } else { c.to_lowercase().next().unwrap_or(c) };
+    let pattern: Vec<char> = pattern.iter().map(|&c| fold(c)).collect();
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let byte_offset = |index: usize| chars.get(index).map(|&(offset, _)| offset).unwrap_or(text.len());
+    
+    // costs[i] / starts[i]: fewest edits turning pattern[..i] into a substring ending here, and where it starts
+    let mut costs: Vec<usize> = (0..=pattern.len()).collect();
+    let mut starts = vec![0; pattern.len() + 1];
+    let mut new_costs = vec![0; pattern.len() + 1];
+    let mut new_starts = vec![0; pattern.len() + 1];
+    let mut candidates = Vec::new();
+    
+    for (j, &(_, c)) in chars.iter().enumerate() {
+        let c = fold(c);
+        new_starts[0] = j + 1;
+        for i in 1..=pattern.len() {
+            let substitution = (costs[i - 1] + usize::from(pattern[i - 1] != c), starts[i - 1]);
+            let insertion = (costs[i] + 1, starts[i]);
+            let deletion = (new_costs[i - 1] + 1, new_starts[i - 1]);
+            // Fewest edits first, then the later (shorter) start
+            let (cost, start) = [substitution, insertion, deletion]
+                .into_iter()
+                .min_by_key(|&(cost, start)| (cost, std::cmp::Reverse(start)))
+                .unwrap();
+            new_costs[i] = cost;
+            new_starts[i] = start;
+        }
+        std::mem::swap(&mut costs, &mut new_costs);
+        std::mem::swap(&mut starts, &mut new_starts);
+        
+        let (cost, start) = (costs[pattern.len()], starts[pattern.len()]);
+        if cost <= max_distance && start < j + 1 {
+            candidates.push((start, j + 1, cost));
+        }
+    }
+    
+    let mut matches = Vec::new();
+    let mut index = 0;
+    while index < candidates.len() {
+        // Candidates overlapping the first one compete; the fewest edits win
+        let first_end = candidates[index].1;
+        let mut best = candidates[index];
+        let mut next = index + 1;
+        while next < candidates.len() && candidates[next].0 < first_end {
+            if candidates[next].2 < best.2 {
+                best = candidates[next];
+            }
+            next += 1;
+        }
+        
+        matches.push((byte_offset(best.0), byte_offset(best.1)));
+        index = next;
+        while index < candidates.len() && candidates[index].0 < best.1 {
+            index += 1;
+        }
+    }
+    matches
+}
+
+/// 1-based line number and 0-based byte column of `offset` within `text`
+fn line_and_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = offset - before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    (line, column)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Match {
     pub start: usize,
     pub end: usize,
     pub text: String,
+    /// Capture group spans relative to `start`: `captures[i]` is group `i + 1`, `None` when
+    /// the group took no part in the match. Only filled for regexes that have groups.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub captures: Vec<Option<(usize, usize)>>,
 }
 
 impl Match {
+    /// Match of `text[start..end]` with capture group spans given as offsets into `text`
+    fn with_captures(
+        text: &str,
+        start: usize,
+        end: usize,
+        groups: impl Iterator<Item = Option<(usize, usize)>>,
+    ) -> Self {
+        Self {
+            start,
+            end,
+            text: text[start..end].to_string(),
+            captures: groups.map(|group| group.map(|(s, e)| (s - start, e - start))).collect(),
+        }
+    }
+    
     pub fn len(&self) -> usize {
         self.end - self.start
     }
+    
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+    
+    /// The matched span of `source` (the text the offsets refer to); empty instead of panicking
+    /// when the offsets do not fit it
+    pub fn as_str<'a>(&self, source: &'a str) -> &'a str {
+        debug_assert!(self.end <= source.len(), "match {}..{} is outside the source", self.start, self.end);
+        source.get(self.start..self.end).unwrap_or("")
+    }
+}
+
+/// `--multiline` 検索の結果（複数行にまたがる可能性があるマッチ）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultilineMatch {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub start_col: usize,
+    pub end_col: usize,
+    pub text: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LineMatch {
     pub line_number: usize,
     pub line_text: String,
     pub matches: Vec<Match>,
+    /// Identical lines `--dedupe-lines` collapsed into this one, itself included
+    pub occurrences: usize,
 }
 
 impl LineMatch {
@@ -166,6 +663,7 @@ impl LineMatch {
             line_number,
             line_text,
             matches,
+            occurrences: 1,
         }
     }
     
@@ -174,11 +672,152 @@ impl LineMatch {
     }
 }
 
-pub fn find_in_text(text: &str, matcher: &PatternMatcher) -> Vec<LineMatch> {
+/// A TODO-style marker parsed out of a line, e.g. `// TODO(bob): fix later`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoMarker {
+    /// Byte offset of the marker word in the line
+    pub start: usize,
+    pub marker: String,
+    pub author: Option<String>,
+    pub note: String,
+}
+
+/// Parse each TODO/FIXME/HACK/XXX marker in `line`; a marker's note runs up to the next marker
+pub fn parse_todos(line: &str) -> Vec<TodoMarker> {
+    let regex = TODO_REGEX.get_or_init(|| Regex::new(TODO_PATTERN).unwrap());
+    let marker_regex = TODO_MARKER_REGEX.get_or_init(|| Regex::new(TODO_MARKER_PATTERN).unwrap());
+    let starts: Vec<usize> = marker_regex.find_iter(line).map(|m| m.start()).collect();
+    
+    starts
+        .iter()
+        .enumerate()
+        .filter_map(|(index, &start)| {
+            let end = starts.get(index + 1).copied().unwrap_or(line.len());
+            let captures = regex.captures(&line[start..end])?;
+            let note = captures.get(3).map_or("", |m| m.as_str()).trim_end();
+            // Block comments close on the same line as the note
+            let note = note.strip_suffix("*/").unwrap_or(note).trim_end();
+            
+            Some(TodoMarker {
+                start,
+                marker: captures[1].to_string(),
+                author: captures
+                    .get(2)
+                    .map(|m| m.as_str().trim().to_string())
+                    .filter(|author| !author.is_empty()),
+                note: note.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Whether a pattern given without `--regex` or `--literal` is taken as a regex
+fn has_regex_metachar(pattern: &str) -> bool {
+    pattern.chars().any(|c| matches!(c, '.' | '*' | '+' | '?' | '^' | '$' | '|' | '[' | ']' | '(' | ')' | '{' | '}' | '\\'))
+}
+
+/// Regex for a literal where every run of whitespace is "one or more whitespace characters",
+/// which is the same as collapsing whitespace runs on both sides before comparing
+fn whitespace_insensitive_regex(pattern: &str) -> String {
+    let mut regex_pattern = pattern
+        .split_whitespace()
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(r"\s+");
+    if pattern.starts_with(char::is_whitespace) {
+        regex_pattern = format!(r"\s+{}", regex_pattern);
+    }
+    if pattern.ends_with(char::is_whitespace) && !pattern.trim().is_empty() {
+        regex_pattern.push_str(r"\s+");
+    }
+    regex_pattern
+}
+
+/// Runs `search` with the `--debug-matcher-log` entries it makes on this thread attributed to `path`
+pub fn with_debug_file<T>(path: &Path, search: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "debug-matcher")]
+    {
+        let previous = DEBUG_FILE.with(|file| file.replace(Some(path.to_path_buf())));
+        let result = search();
+        DEBUG_FILE.with(|file| *file.borrow_mut() = previous);
+        result
+    }
+    #[cfg(not(feature = "debug-matcher"))]
+    {
+        let _ = path;
+        search()
+    }
+}
+
+/// One `--debug-matcher-log` line: the file being searched (if any), the text and what matched in it
+#[cfg(feature = "debug-matcher")]
+fn record_debug_entry(log: &Mutex<Vec<String>>, text: &str, spans: impl Iterator<Item = (usize, usize)>) {
+    let found: Vec<String> = spans
+        .map(|(start, end)| format!("{}..{} {:?}", start, end, text.get(start..end).unwrap_or_default()))
+        .collect();
+    let mut entry = DEBUG_FILE.with(|file| file.borrow().as_ref().map(|path| format!("{}: ", path.display())).unwrap_or_default());
+    entry.push_str(&format!("{:?} => [{}]", text, found.join(", ")));
+    log.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(entry);
+}
+
+/// Up to `BENCHMARK_SAMPLE_BYTES` from the start of `path`
+fn benchmark_sample(path: &Path) -> Option<String> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(BENCHMARK_SAMPLE_BYTES)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    let sample = String::from_utf8_lossy(&bytes).into_owned();
+    (!sample.is_empty()).then_some(sample)
+}
+
+/// Best of three line-by-line passes over `sample`, the way files are searched
+fn time_matcher(matcher: &PatternMatcher, sample: &str) -> Duration {
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            for line in sample.lines() {
+                black_box(matcher.find_spans(line));
+            }
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+/// Upper bound on matches reported for a single line
+pub const MAX_MATCHES_PER_LINE: usize = 1000;
+
+/// Truncate pathological match lists (e.g. zero-width patterns on very long lines)
+fn cap_matches<T>(mut matches: Vec<T>, line_number: usize) -> Vec<T> {
+    if matches.len() > MAX_MATCHES_PER_LINE {
+        eprintln!(
+            "Warning: line {} has {} matches; only the first {} are shown",
+            line_number,
+            matches.len(),
+            MAX_MATCHES_PER_LINE
+        );
+        matches.truncate(MAX_MATCHES_PER_LINE);
+    }
+    matches
+}
+
+/// Order matches by position and drop repeats of the same span, so each line reports a span once
+fn merge_matches(mut matches: Vec<Match>) -> Vec<Match> {
+    matches.sort_by_key(|m| (m.start, m.end));
+    matches.dedup_by(|a, b| a.start == b.start && a.end == b.end);
+    matches
+}
+
+/// Search line by line; each line yields at most one `LineMatch` holding all of its matches.
+/// Lines that `line_filter` (`--line-regex`) does not match are skipped before the primary search.
+pub fn find_in_text(text: &str, matcher: &PatternMatcher, line_filter: Option<&PatternMatcher>) -> Vec<LineMatch> {
     text.lines()
         .enumerate()
+        .filter(|(_, line)| line_filter.is_none_or(|filter| filter.is_match(line)))
         .filter_map(|(line_num, line)| {
-            let matches = matcher.find_matches(line);
+            let matches = cap_matches(merge_matches(matcher.find_matches(line)), line_num + 1);
             if !matches.is_empty() {
                 Some(LineMatch::new(line_num + 1, line.to_string(), matches))
             } else {
@@ -188,6 +827,111 @@ pub fn find_in_text(text: &str, matcher: &PatternMatcher) -> Vec<LineMatch> {
         .collect()
 }
 
+/// Counting-only counterpart of `find_in_text`: (line number, match count) for each line with
+/// matches, counted the same way but without copying any line or match text
+pub fn count_in_text(
+    text: &str,
+    matcher: &PatternMatcher,
+    line_filter: Option<&PatternMatcher>,
+    zero_length_matches: bool,
+) -> Vec<(usize, usize)> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| line_filter.is_none_or(|filter| filter.is_match(line)))
+        .filter_map(|(line_num, line)| {
+            let mut spans = matcher.find_spans(line);
+            spans.sort_unstable();
+            spans.dedup();
+            let mut spans = cap_matches(spans, line_num + 1);
+            if !zero_length_matches {
+                spans.retain(|(start, end)| start != end);
+            }
+            (!spans.is_empty()).then_some((line_num + 1, spans.len()))
+        })
+        .collect()
+}
+
+/// Like `find_in_text`, but splits the content on `separator` instead of newlines.
+/// Each segment is treated as one "line" numbered by its 1-based segment index.
+pub fn find_in_records(text: &str, separator: &str, matcher: &PatternMatcher) -> Vec<LineMatch> {
+    if separator.is_empty() {
+        return find_in_text(text, matcher, None);
+    }
+    
+    text.split(separator)
+        .enumerate()
+        .filter_map(|(index, segment)| {
+            let matches = cap_matches(merge_matches(matcher.find_matches(segment)), index + 1);
+            if !matches.is_empty() {
+                Some(LineMatch::new(index + 1, segment.to_string(), matches))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Remove zero-length matches, dropping lines that are left without any match
+pub fn drop_zero_length_matches(line_matches: Vec<LineMatch>) -> Vec<LineMatch> {
+    line_matches
+        .into_iter()
+        .filter_map(|mut line_match| {
+            line_match.matches.retain(|m| !m.is_empty());
+            if line_match.has_matches() {
+                Some(line_match)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Search the whole text at once so that patterns containing newlines can match.
+/// Each match is reported as a `LineMatch` whose `line_text` spans every line the
+/// match touches, with match offsets relative to the start of that span.
+pub fn find_in_text_multiline(text: &str, matcher: &PatternMatcher) -> Vec<LineMatch> {
+    let mut line_matches: Vec<LineMatch> = Vec::new();
+    let mut span_start = 0;
+    let mut span_end = 0;
+    
+    
+    for m in matcher.find_matches(text) {
+        let line_start = text[..m.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = text[m.end..].find('\n').map(|i| m.end + i).unwrap_or(text.len());
+        
+        // Merge with the previous span when the match starts inside it
+        if let Some(last) = line_matches.last_mut() {
+            if line_start <= span_end {
+                span_end = span_end.max(line_end);
+                last.line_text = text[span_start..span_end].to_string();
+                last.matches.push(Match {
+                    start: m.start - span_start,
+                    end: m.end - span_start,
+                    text: m.text,
+                    captures: m.captures,
+                });
+                continue;
+            }
+        }
+        
+        span_start = line_start;
+        span_end = line_end;
+        let line_number = text[..line_start].matches('\n').count() + 1;
+        line_matches.push(LineMatch::new(
+            line_number,
+            text[line_start..line_end].to_string(),
+            vec![Match {
+                start: m.start - line_start,
+                end: m.end - line_start,
+                text: m.text,
+                captures: m.captures,
+            }],
+        ));
+    }
+    
+    line_matches
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -195,41 +939,114 @@ mod tests {
     fn test_cli() -> Cli {
         Cli {
             pattern: Some("test".to_string()),
+            saved: None,
             paths: vec![],
             regex: false,
             fancy_regex: false,
             case_sensitive: false,
+            ignore_case_pattern: false,
             word_boundary: false,
             literal: false,
+            glob_pattern: false,
+            infix_pattern: false,
+            ignore_whitespace: false,
+            match_newlines_literal: false,
+            multiline: false,
+            zero_length_matches: false,
+            split_on: None,
+            range: None,
+            head: None,
+            tail: None,
+            line_regex: None,
+            json_path: None,
+            yaml_path: None,
             r#type: vec![],
+            type_add: Vec::new(),
             ext: vec![],
             ignore: vec![],
+            glob: Vec::new(),
+            tests_only: false,
+            no_tests: false,
             respect_gitignore: true,
+            no_ignore_global: false,
+            git_tracked: false,
             hidden: false,
+            include_symlink_files: false,
+            skip_symlinks: false,
             binary: false,
             max_filesize: None,
+            min_filesize: None,
             max_depth: None,
+            no_recursive: false,
+            walk_order: crate::WalkOrder::Dfs,
+            limit_files: None,
+            search_network: false,
+            stdin_filename: None,
             modified_within: None,
+            modified_after: None,
+            modified_before: None,
             replace: None,
+            replace_cmd: None,
+            preserve_case: false,
             preview: false,
+            preview_limit: None,
+            output_as_patch: false,
             interactive: false,
             write: false,
+            replace_in_place_backup: None,
+            keep_bom: false,
+            count_replacements: false,
+            verify_idempotent: false,
             line_numbers: false,
+            column: false,
+            only_matching: false,
+            merge_adjacent: None,
+            print_line_checksums: false,
+            file_checksums: false,
+            tab_size: 4,
+            vimgrep: false,
+            git_grep_compat: false,
+            rg_compat: false,
+            no_heading: false,
+            separate_files: None,
+            separator_char: None,
+            group_by_extension: false,
+            dedupe_lines: false,
+            dedupe_global: false,
+            trim_path_prefix: None,
             context: 0,
             before_context: None,
             after_context: None,
             output: crate::OutputFormat::Text,
+            output_file: None,
+            split_results_by_file: false,
+            output_language: None,
+            json_compact: false,
             color: crate::ColorChoice::Auto,
+            color_syntax: false,
             files_only: false,
+            no_messages: false,
+            max_match_length: None,
+            list_files: false,
+            todos: false,
             count_only: false,
             stats_only: false,
             threads: None,
+            verbose: 0,
+            version: (),
+            debug_matcher_log: None,
             max_memory: None,
+            spill_threshold: None,
+            sparse: false,
             fast: false,
             thorough: false,
             functions: false,
+            top_level: false,
+            show_function_boundaries: false,
+            print_ast_path: false,
             in_function: None,
             in_class: None,
+            in_attribute: None,
             in_scope: vec![],
             imports_only: false,
             comments_only: false,
@@ -239,10 +1056,14 @@ mod tests {
             lang_rules: None,
             output_filter: None,
             config: None,
+            validate_config: false,
             interactive_mode: false,
             fuzzy: false,
+            approx: None,
             live: false,
             check_regex: None,
+            print_json_schema: false,
+            dump_config: false,
             command: None,
         }
     }
@@ -271,4 +1092,402 @@ mod tests {
         assert_eq!(matches[0].text, "123");
         assert_eq!(matches[1].text, "456");
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_multiline_literal() {
+        let cli = Cli {
+            pattern: Some("world\nThis".to_string()),
+            literal: true,
+            match_newlines_literal: true,
+            ..test_cli()
+        };
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        
+        let line_matches = find_in_text_multiline("Hello\nHello world\nThis is a test\nEnd", &matcher);
+        assert_eq!(line_matches.len(), 1);
+        assert_eq!(line_matches[0].line_number, 2);
+        assert_eq!(line_matches[0].line_text, "Hello world\nThis is a test");
+        assert_eq!(line_matches[0].matches[0].start, 6);
+        assert_eq!(line_matches[0].matches[0].text, "world\nThis");
+    }
+    
+    #[test]
+    fn test_find_in_records() {
+        let matcher = PatternMatcher::Literal("error".to_string());
+        let text = "ok line\n---\nerror: one\nmore\n---\nfine";
+        
+        let records = find_in_records(text, "---", &matcher);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].line_number, 2);
+        assert_eq!(records[0].line_text, "\nerror: one\nmore\n");
+    }
+    
+    #[test]
+    fn test_find_matches_multiline() {
+        let cli = Cli {
+            pattern: Some(r"fn \w+\(\)\s*\{\n\s*todo".to_string()),
+            regex: true,
+            multiline: true,
+            ..test_cli()
+        };
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        
+        let matches = matcher.find_matches_multiline("use x;\n\nfn main() {\n    todo!()\n}");
+        assert_eq!(
+            matches,
+            vec![MultilineMatch {
+                start_line: 3,
+                end_line: 4,
+                start_col: 0,
+                end_col: 8,
+                text: "fn main() {\n    todo".to_string(),
+            }]
+        );
+    }
+    
+    #[test]
+    fn test_zero_width_matches_terminate() {
+        let cli = Cli {
+            pattern: Some("()*".to_string()),
+            regex: true,
+            ..test_cli()
+        };
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        
+        let long_line = "x".repeat(MAX_MATCHES_PER_LINE * 2);
+        let line_matches = find_in_text(&long_line, &matcher, None);
+        assert_eq!(line_matches.len(), 1);
+        assert_eq!(line_matches[0].matches.len(), MAX_MATCHES_PER_LINE);
+        
+        // Empty literals advance by whole characters, even across multi-byte text
+        let literal = PatternMatcher::Literal(String::new());
+        let matches = literal.find_matches("héllo");
+        assert_eq!(matches.len(), 6);
+        assert!(matches.iter().all(|m| m.start == m.end));
+    }
+    
+    #[test]
+    fn test_drop_zero_length_matches() {
+        let cli = Cli {
+            pattern: Some("^".to_string()),
+            regex: true,
+            ..test_cli()
+        };
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        
+        let line_matches = find_in_text("abc\ndef", &matcher, None);
+        assert_eq!(line_matches.len(), 2);
+        assert!(line_matches[0].matches[0].is_empty());
+        assert!(drop_zero_length_matches(line_matches).is_empty());
+    }
+    
+    #[test]
+    fn test_ignore_whitespace() {
+        let cli = Cli {
+            pattern: Some("foo( x )".to_string()),
+            literal: true,
+            ignore_whitespace: true,
+            ..test_cli()
+        };
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        let matches = matcher.find_matches("call foo(   x\t) here");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].text, "foo(   x\t)");
+        assert!(!matcher.is_match("foo(x)"));
+        
+        // In regex mode whitespace in the pattern itself is ignored (x flag)
+        let cli = Cli {
+            pattern: Some(r"\d+ px".to_string()),
+            regex: true,
+            ignore_whitespace: true,
+            ..test_cli()
+        };
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        assert_eq!(matcher.find_matches("width: 12px")[0].text, "12px");
+    }
+    
+    #[test]
+    fn test_or_terms_on_same_line() {
+        let cli = Cli {
+            pattern: None,
+            or: vec!["foo".to_string(), "bar".to_string()],
+            ..test_cli()
+        };
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        let line_matches = find_in_text("foo and bar\nnothing\nbar", &matcher, None);
+        
+        assert_eq!(line_matches.len(), 2);
+        assert_eq!(line_matches[0].line_number, 1);
+        let texts: Vec<_> = line_matches[0].matches.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["foo", "bar"]);
+        
+        let duplicated = vec![
+            Match { start: 8, end: 11, text: "bar".to_string(), captures: Vec::new() },
+            Match { start: 0, end: 3, text: "foo".to_string(), captures: Vec::new() },
+            Match { start: 8, end: 11, text: "bar".to_string(), captures: Vec::new() },
+        ];
+        let merged: Vec<_> = merge_matches(duplicated).into_iter().map(|m| m.start).collect();
+        assert_eq!(merged, vec![0, 8]);
+    }
+    
+    #[test]
+    fn test_approximate_matcher() {
+        let mut cli = test_cli();
+        cli.pattern = Some("config".to_string());
+        cli.approx = Some(1);
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        
+        // One deletion, one substitution, one insertion
+        for text in ["let confg = 1;", "let confog = 1;", "let conffig = 1;"] {
+            let matches = matcher.find_matches(text);
+            assert_eq!(matches.len(), 1, "{}", text);
+            assert!(matches[0].text.starts_with("con"), "{}", text);
+        }
+        assert_eq!(matcher.find_matches("let config = 1;")[0].text, "config");
+        
+        // Two edits away
+        assert!(matcher.find_matches("let cnfg = 1;").is_empty());
+        assert!(matcher.find_matches("let konfog = 1;").is_empty());
+    }
+    
+    #[test]
+    fn test_match_as_str() {
+        let source = "let héllo = 1;";
+        let found = Match { start: 4, end: 10, text: "héllo".to_string(), captures: Vec::new() };
+        assert_eq!(found.as_str(source), "héllo");
+        assert!(!found.is_empty());
+        
+        // Offsets that split a character yield an empty string rather than a panic
+        let split = Match { start: 5, end: 6, text: String::new(), captures: Vec::new() };
+        assert_eq!(split.as_str(source), "");
+        assert!(Match { start: 3, end: 3, text: String::new(), captures: Vec::new() }.is_empty());
+    }
+    
+    #[test]
+    fn test_line_regex_gates_lines() {
+        let cli = Cli {
+            pattern: Some("TODO".to_string()),
+            line_regex: Some(r"^\s*//".to_string()),
+            ..test_cli()
+        };
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        let line_filter = PatternMatcher::line_filter(&cli).unwrap();
+        let text = "let todo = 1; // TODO later\n    // TODO: fix\n\"TODO\"";
+        
+        let line_matches = find_in_text(text, &matcher, line_filter.as_ref());
+        let lines: Vec<usize> = line_matches.iter().map(|lm| lm.line_number).collect();
+        assert_eq!(lines, vec![2]);
+        assert_eq!(count_in_text(text, &matcher, line_filter.as_ref(), false), vec![(2, 1)]);
+        
+        let invalid = Cli { line_regex: Some("(".to_string()), ..cli };
+        assert!(PatternMatcher::line_filter(&invalid).is_err());
+    }
+    
+    #[test]
+    fn test_fast_and_thorough_engine_selection() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let sample = temp_dir.path().join("sample.txt");
+        std::fs::write(&sample, "Benchmark needle\nnothing\n".repeat(100)).unwrap();
+        
+        let fast = |pattern: &str| Cli { pattern: Some(pattern.to_string()), fast: true, ..test_cli() };
+        let is_literal = |matcher: &PatternMatcher| matches!(matcher, PatternMatcher::Literal(_));
+        let matcher = PatternMatcher::with_benchmark_sample(&fast("benchmark needle"), &sample).unwrap();
+        assert!(matches!(matcher, PatternMatcher::Literal(_) | PatternMatcher::Basic(_)));
+        assert_eq!(matcher.find_spans("a BENCHMARK NEEDLE"), vec![(2, 18)]);
+        
+        // The timed choice is cached for the process, so later matchers need no sample
+        let cached = FAST_ENGINE_CHOICES.get().unwrap().lock().unwrap().get("benchmark needle").copied();
+        assert_eq!(cached, Some(is_literal(&matcher)));
+        assert_eq!(is_literal(&PatternMatcher::new(&fast("benchmark needle")).unwrap()), is_literal(&matcher));
+        FAST_ENGINE_CHOICES.get().unwrap().lock().unwrap().insert("cached regex".to_string(), false);
+        assert!(matches!(PatternMatcher::new(&fast("cached regex")).unwrap(), PatternMatcher::Basic(_)));
+        // Nothing cached and nothing to time: the usual engine
+        assert!(is_literal(&PatternMatcher::new(&fast("never timed")).unwrap()));
+        
+        let thorough = |pattern: &str, literal: bool| Cli { pattern: Some(pattern.to_string()), thorough: true, literal, ..test_cli() };
+        for cli in [thorough(r"fo+\d", false), thorough("needle", false), thorough("a.b", true)] {
+            assert!(matches!(PatternMatcher::new(&cli).unwrap(), PatternMatcher::Fancy(_)));
+        }
+        let literal = PatternMatcher::new(&thorough("a.b", true)).unwrap();
+        assert_eq!((literal.find_spans("a.b"), literal.find_spans("axb")), (vec![(0, 3)], vec![]));
+    }
+    
+    #[test]
+    fn test_parse_todos() {
+        assert_eq!(
+            parse_todos("    // TODO(bob): fix later"),
+            vec![TodoMarker {
+                start: 7,
+                marker: "TODO".to_string(),
+                author: Some("bob".to_string()),
+                note: "fix later".to_string(),
+            }]
+        );
+        let fixme = parse_todos("/* FIXME handle errors */").remove(0);
+        assert_eq!((fixme.marker.as_str(), fixme.author, fixme.note.as_str()), ("FIXME", None, "handle errors"));
+        assert!(parse_todos("let todos = TODOS;").is_empty());
+        
+        let both: Vec<_> = parse_todos("// XXX(ann): odd; HACK(joe) fine")
+            .into_iter()
+            .map(|todo| (todo.start, todo.marker, todo.author, todo.note))
+            .collect();
+        assert_eq!(
+            both,
+            vec![
+                (3, "XXX".to_string(), Some("ann".to_string()), "odd;".to_string()),
+                (18, "HACK".to_string(), Some("joe".to_string()), "fine".to_string()),
+            ]
+        );
+        
+        let cli = Cli { todos: true, ..test_cli() };
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        assert_eq!(matcher.find_spans("// XXX(ann): odd; HACK"), vec![(3, 6), (18, 22)]);
+    }
+    
+    #[test]
+    fn test_ignore_case_pattern_keeps_original_text() {
+        let cli = Cli {
+            pattern: Some("foo".to_string()),
+            case_sensitive: true,
+            ignore_case_pattern: true,
+            ..test_cli()
+        };
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        let line_matches = find_in_text("Foo FOO foo", &matcher, None);
+        
+        let texts: Vec<&str> = line_matches[0].matches.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["Foo", "FOO", "foo"]);
+        assert_eq!(line_matches[0].line_text, "Foo FOO foo");
+    }
+    
+    #[test]
+    fn test_find_matches_carries_captures() {
+        let matcher = PatternMatcher::Basic(Regex::new(r"(\w+)(?:-(\d+))?").unwrap());
+        let matches = matcher.find_matches("  ab-12 cd");
+        assert_eq!(matches[0].start, 2);
+        assert_eq!(matches[0].captures, vec![Some((0, 2)), Some((3, 5))]);
+        assert_eq!(matches[1].captures, vec![Some((0, 2)), None]);
+        
+        let plain = PatternMatcher::Basic(Regex::new(r"\w+").unwrap());
+        assert!(plain.find_matches("ab").iter().all(|m| m.captures.is_empty()));
+    }
+    
+    #[test]
+    fn test_glob_pattern() {
+        assert_eq!(glob_to_regex("foo*bar"), "foo.*bar");
+        assert_eq!(glob_to_regex("v?.[0-9]"), r"v.\.[0-9]");
+        assert_eq!(glob_to_regex("[!a]x[]y]"), r"[^a]x[\]y]");
+        assert_eq!(glob_to_regex(r"a\*[b"), r"a\*\[b");
+        
+        let glob = |pattern: &str| {
+            PatternMatcher::new(&Cli { pattern: Some(pattern.to_string()), glob_pattern: true, ..test_cli() }).unwrap()
+        };
+        let matcher = glob("foo*bar");
+        assert_eq!(matcher.find_matches("let foo_and_bar = 1;")[0].text, "foo_and_bar");
+        assert!(!matcher.is_match("bar before foo"));
+        
+        let matcher = glob("log.?(*)");
+        assert!(matcher.is_match("log.i(\"started\")"));
+        assert!(!matcher.is_match("logXi(1)"));
+        
+        let matcher = glob("v[0-9].[!0]");
+        assert!(matcher.is_match("version v1.2"));
+        assert!(!matcher.is_match("version v1.0"));
+        
+        let conflicting = Cli { pattern: Some("a*".to_string()), glob_pattern: true, regex: true, ..test_cli() };
+        assert!(conflicting.validate().is_err());
+    }
+    
+    #[test]
+    fn test_infix_pattern() {
+        let infix = |pattern: &str, case_sensitive: bool| {
+            let cli = Cli { pattern: Some(pattern.to_string()), infix_pattern: true, case_sensitive, ..test_cli() };
+            PatternMatcher::new(&cli).unwrap()
+        };
+        
+        let matches = infix("parse", false).find_matches("parseJson(x) + reparse.all y.Parse() none");
+        let spans: Vec<_> = matches.iter().map(|m| (m.start, m.end, m.text.as_str())).collect();
+        assert_eq!(
+            spans,
+            vec![(0, 12, "parseJson(x)"), (15, 26, "reparse.all"), (27, 36, "y.Parse()")]
+        );
+        assert_eq!(infix("parse", true).find_matches("parseJson y.Parse()").len(), 1);
+        
+        // The pattern is a plain substring, not a regex
+        assert_eq!(infix("a.b", false).find_matches("axb a.b.c")[0].text, "a.b.c");
+    }
+    
+    #[test]
+    fn test_word_boundary_keeps_inline_flags_in_front() {
+        assert_eq!(split_inline_flags("(?i)(?-m)foo"), ("(?i)(?-m)", "foo"));
+        assert_eq!(split_inline_flags("(?i:foo)bar"), ("", "(?i:foo)bar"));
+        assert_eq!(word_bounded("(?i)foo|bar"), r"(?i)\b(?:foo|bar)\b");
+        
+        for fancy_regex in [false, true] {
+            let cli = Cli {
+                pattern: Some("(?i)hello".to_string()),
+                regex: true,
+                fancy_regex,
+                word_boundary: true,
+                case_sensitive: true,
+                ..test_cli()
+            };
+            let matcher = PatternMatcher::new(&cli).unwrap();
+            assert!(matcher.is_match("say HELLO there"));
+            assert!(!matcher.is_match("helloworld"));
+        }
+    }
+    
+    #[test]
+    fn test_literal_word_boundary() {
+        let matcher = |pattern: &str, case_sensitive: bool| {
+            let cli = Cli { pattern: Some(pattern.to_string()), word_boundary: true, case_sensitive, ..test_cli() };
+            PatternMatcher::new(&cli).unwrap()
+        };
+        
+        let spans: Vec<_> = matcher("old_name", false)
+            .find_matches("old_name_helper(); OLD_NAME(); x.old_name")
+            .iter()
+            .map(|m| (m.start, m.end))
+            .collect();
+        assert_eq!(spans, vec![(19, 27), (33, 41)]);
+        assert_eq!(matcher("old_name", true).find_matches("OLD_NAME old_name").len(), 1);
+        
+        // With --literal, regex metacharacters are matched as they are
+        let cli = Cli { pattern: Some("a.b".to_string()), word_boundary: true, literal: true, ..test_cli() };
+        let literal = PatternMatcher::new(&cli).unwrap();
+        assert!(literal.is_match("(a.b)"));
+        assert!(!literal.is_match("axb") && !literal.is_match("xa.b"));
+    }
+    
+    #[cfg(feature = "debug-matcher")]
+    #[test]
+    fn test_debug_matcher_logs_every_call() {
+        let cli = Cli {
+            pattern: Some("fo+".to_string()),
+            regex: true,
+            debug_matcher_log: Some("unused.log".into()),
+            ..test_cli()
+        };
+        let matcher = PatternMatcher::new(&cli).unwrap();
+        assert_eq!(matcher.find_matches("a foo").len(), 1);
+        assert!(!matcher.is_match("bar"));
+        // Counting goes through find_spans, and entries name the file being searched
+        with_debug_file(Path::new("src/lib.rs"), || {
+            assert_eq!(count_in_text("x\nfoo foo\n", &matcher, None, false), vec![(2, 2)]);
+        });
+        
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("matcher.log");
+        matcher.write_debug_log(&log_path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&log_path).unwrap(),
+            concat!(
+                "\"a foo\" => [2..5 \"foo\"]\n",
+                "\"bar\" => []\n",
+                "src/lib.rs: \"x\" => []\n",
+                "src/lib.rs: \"foo foo\" => [0..3 \"foo\", 4..7 \"foo\"]\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{CodeGrepError, CodeGrepResult};
+use std::path::Path;
+
+/// Whether a search path is actually an http(s) URL
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .map(|s| s.starts_with("http://") || s.starts_with("https://"))
+        .unwrap_or(false)
+}
+
+/// Fetch a remote file so it can be searched in memory
+#[cfg(feature = "network")]
+pub fn fetch_url(url: &str) -> CodeGrepResult<String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .map_err(|e| CodeGrepError::Search(format!("Failed to create HTTP client: {}", e)))?;
+
+    let response = client
+        .get(url)
+        .send()
+        .map_err(|e| CodeGrepError::Search(format!("Failed to fetch {}: {}", url, e)))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(CodeGrepError::Search(format!(
+            "Failed to fetch {}: HTTP {}",
+            url,
+            status.as_u16()
+        )));
+    }
+
+    response
+        .text()
+        .map_err(|e| CodeGrepError::Search(format!("Failed to read {}: {}", url, e)))
+}
+
+#[cfg(not(feature = "network"))]
+pub fn fetch_url(url: &str) -> CodeGrepResult<String> {
+    Err(CodeGrepError::Search(format!(
+        "Cannot fetch {}: cg was built without network support; rebuild with `cargo build --features network`",
+        url
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url(Path::new("https://example.com/main.rs")));
+        assert!(is_url(Path::new("http://example.com")));
+        assert!(!is_url(Path::new("./src/main.rs")));
+    }
+}
@@ -1,34 +1,104 @@
-use crate::{Cli, ColorChoice, FileMatch, LineMatch, OutputFormat, SearchStats};
+use crate::{
+    dedupe_lines, extension_language, parse_todos, Cli, CodeGrepResult, ColorChoice, FileMatch, LineMatch, Match, MultilineMatch, OutputFormat,
+    ResultSpool, SearchStats,
+};
 use serde_json::json;
-use std::io::{self, Write};
-use termcolor::{Color, ColorChoice as TermColorChoice, ColorSpec, StandardStream, WriteColor};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use termcolor::{Color, ColorChoice as TermColorChoice, ColorSpec, NoColor, StandardStream, WriteColor};
+
+/// Highlight colors of capture groups 1, 2, ... (cycling), distinct from the red of the whole match
+const CAPTURE_COLORS: [Color; 4] = [Color::Cyan, Color::Yellow, Color::Magenta, Color::Blue];
+
+/// Length of the `--separator-char` rule printed between files
+const FILE_SEPARATOR_WIDTH: usize = 3;
 
 pub struct OutputFormatter {
     cli: Cli,
-    stdout: StandardStream,
+    stdout: Box<dyn WriteColor>,
+    /// Where notes that must not mix with the results go, like the `--files-only` footer
+    stderr: Box<dyn Write>,
+    /// File name printed before each line with `--no-heading`
+    line_prefix: Option<String>,
+    /// Files printed so far, so the separator only goes between files
+    files_printed: usize,
+    /// `--color-syntax` highlighter, when colors are on
+    #[cfg(feature = "syntax-highlight")]
+    syntax: Option<crate::SyntaxHighlighter>,
 }
 
 impl OutputFormatter {
     pub fn new(cli: Cli) -> Self {
         let color_choice = match cli.color {
+            _ if cli.git_grep_compat => TermColorChoice::Never,
             ColorChoice::Always => TermColorChoice::Always,
             ColorChoice::Never => TermColorChoice::Never,
             ColorChoice::Auto => TermColorChoice::Auto,
         };
         
         Self {
+            #[cfg(feature = "syntax-highlight")]
+            syntax: syntax_highlighter(&cli),
+            cli,
+            stdout: Box::new(StandardStream::stdout(color_choice)),
+            stderr: Box::new(io::stderr()),
+            line_prefix: None,
+            files_printed: 0,
+        }
+    }
+    
+    /// Formatter for `cli` that writes to `--output-file` (uncolored) when one is given
+    pub fn open(cli: Cli) -> io::Result<Self> {
+        match cli.output_file.clone() {
+            Some(path) if !cli.split_results_by_file => {
+                let file = BufWriter::new(File::create(path)?);
+                Ok(Self::with_writer(cli, NoColor::new(file)))
+            }
+            _ => Ok(Self::new(cli)),
+        }
+    }
+    
+    /// Formatter writing to `writer` instead of stdout, such as a file or an in-memory buffer
+    /// (wrap plain writers in `termcolor::NoColor` or `termcolor::Ansi`)
+    pub fn with_writer(cli: Cli, writer: impl WriteColor + 'static) -> Self {
+        Self::with_writers(cli, writer, io::stderr())
+    }
+    
+    /// Formatter writing results to `writer` and notes to `messages` instead of stdout and stderr
+    fn with_writers(cli: Cli, writer: impl WriteColor + 'static, messages: impl Write + 'static) -> Self {
+        Self {
+            #[cfg(feature = "syntax-highlight")]
+            syntax: syntax_highlighter(&cli),
             cli,
-            stdout: StandardStream::stdout(color_choice),
+            stdout: Box::new(writer),
+            stderr: Box::new(messages),
+            line_prefix: None,
+            files_printed: 0,
         }
     }
     
     pub fn print_results(&mut self, file_matches: &[FileMatch], stats: &SearchStats) -> io::Result<()> {
+        let deduped;
+        let file_matches = if self.cli.dedupes_lines() {
+            deduped = dedupe_lines(file_matches, self.cli.dedupe_global);
+            &deduped[..]
+        } else {
+            file_matches
+        };
+        
         match self.cli.output {
-            OutputFormat::Text => self.print_text_results(file_matches, stats),
-            OutputFormat::Json => self.print_json_results(file_matches, stats),
-            OutputFormat::Csv => self.print_csv_results(file_matches),
-            OutputFormat::Xml => self.print_xml_results(file_matches),
+            OutputFormat::Text => self.print_text_results(file_matches, stats)?,
+            OutputFormat::Json => self.print_json_results(file_matches, stats)?,
+            OutputFormat::Csv => self.print_csv_results(file_matches)?,
+            OutputFormat::Xml => self.print_xml_results(file_matches)?,
+            OutputFormat::Markdown => self.print_markdown_results(file_matches)?,
+            OutputFormat::Html => self.print_html_results(file_matches)?,
+            OutputFormat::Sarif => self.print_sarif_results(file_matches)?,
+            OutputFormat::Yaml => self.print_yaml_results(file_matches, stats)?,
         }
+        self.stdout.flush()
     }
     
     fn print_text_results(&mut self, file_matches: &[FileMatch], stats: &SearchStats) -> io::Result<()> {
@@ -39,163 +109,688 @@ impl OutputFormatter {
         
         if self.cli.count_only {
             let total_matches: usize = file_matches.iter().map(|fm| fm.total_matches).sum();
-            println!("{}", total_matches);
+            writeln!(self.stdout, "{}", total_matches)?;
+            return Ok(());
+        }
+        
+        if self.cli.vimgrep {
+            for file_match in file_matches {
+                self.print_vimgrep(file_match)?;
+            }
+            return Ok(());
+        }
+        
+        if self.cli.git_grep_compat {
+            for file_match in file_matches {
+                write!(self.stdout, "{}", git_grep_lines(file_match, self.cli.display_path(&file_match.path)))?;
+            }
             return Ok(());
         }
         
         if self.cli.files_only {
             for file_match in file_matches {
                 if file_match.has_matches() {
-                    println!("{}", file_match.path.display());
+                    writeln!(self.stdout, "{}", self.cli.display_path(&file_match.path).display())?;
                 }
             }
-            return Ok(());
+            return self.print_files_only_footer(stats);
         }
         
         // Regular output
-        for file_match in file_matches {
-            if !file_match.has_matches() {
-                continue;
+        if self.cli.group_by_extension {
+            self.print_grouped_by_extension(file_matches)?;
+        } else {
+            for file_match in file_matches {
+                self.print_file_match(file_match)?;
+            }
+        }
+        
+        // Print stats if not in quiet mode
+        if !self.cli.files_only && !self.cli.count_only {
+            self.print_summary_stats(stats)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// `--split-results-by-file`: write each file's results to `dir/<relative path>.<format extension>`,
+    /// creating directories as needed. Returns the files written.
+    pub fn write_split_results(&self, dir: &Path, file_matches: &[FileMatch], stats: &SearchStats) -> io::Result<Vec<PathBuf>> {
+        let mut written = Vec::new();
+        for file_match in file_matches.iter().filter(|fm| fm.has_matches()) {
+            let mut target = dir.join(split_result_path(&file_match.path));
+            target.as_mut_os_string().push(format!(".{}", self.cli.output.extension()));
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
             }
             
-            self.print_file_header(&file_match.path.display().to_string())?;
+            let file_stats = SearchStats::new(
+                1,
+                1,
+                file_match.total_matches,
+                file_match.matched_lines(),
+                stats.elapsed_time,
+            );
+            let file = BufWriter::new(File::create(&target)?);
+            let mut formatter = Self::with_writer(self.cli.clone(), NoColor::new(file));
+            formatter.print_results(std::slice::from_ref(file_match), &file_stats)?;
+            written.push(target);
+        }
+        Ok(written)
+    }
+    
+    /// Print results that may have been spilled to disk, streaming them where possible
+    pub fn print_spooled_results(&mut self, spool: ResultSpool, stats: &SearchStats) -> CodeGrepResult<()> {
+        // Only regular text output can be streamed; other modes need the full result set
+        let streamable = matches!(self.cli.output, OutputFormat::Text)
+            && !self.cli.stats_only
+            && !self.cli.count_only
+            && !self.cli.vimgrep
+            && !self.cli.git_grep_compat
+            && !self.cli.group_by_extension
+            && !self.cli.dedupe_global;
+        
+        if !streamable {
+            let file_matches = spool.into_vec()?;
+            self.print_results(&file_matches, stats)?;
+            return Ok(());
+        }
+        
+        for file_match in spool.stream()? {
+            let mut file_match = file_match?;
+            if self.cli.dedupe_lines {
+                // Per-file deduplication never drops the file
+                file_match = dedupe_lines(std::slice::from_ref(&file_match), false).remove(0);
+            }
+            if self.cli.files_only {
+                writeln!(self.stdout, "{}", self.cli.display_path(&file_match.path).display())?;
+            } else {
+                self.print_file_match(&file_match)?;
+            }
+        }
+        
+        if self.cli.files_only {
+            self.print_files_only_footer(stats)?;
+        } else {
+            self.print_summary_stats(stats)?;
+        }
+        
+        self.stdout.flush()?;
+        Ok(())
+    }
+    
+    /// `--group-by-extension`: files sorted by extension (stable within each), each group under
+    /// a `=== .rs files ===` header; files without an extension come last
+    fn print_grouped_by_extension(&mut self, file_matches: &[FileMatch]) -> io::Result<()> {
+        let extension = |file_match: &FileMatch| {
+            file_match.path.extension().map(|ext| format!(".{}", ext.to_string_lossy()))
+        };
+        let mut sorted: Vec<&FileMatch> = file_matches.iter().filter(|fm| fm.has_matches()).collect();
+        sorted.sort_by_key(|fm| (extension(fm).is_none(), extension(fm)));
+        
+        let mut current_group = None;
+        for file_match in sorted {
+            let group = extension(file_match);
+            if current_group.as_ref() != Some(&group) {
+                if current_group.is_some() {
+                    writeln!(self.stdout)?;
+                }
+                let header = format!("=== {} files ===", group.as_deref().unwrap_or("(no extension)"));
+                self.write_colored(&header, ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+                writeln!(self.stdout)?;
+                // The first file of a group needs no separator from the previous group's last file
+                self.files_printed = 0;
+                current_group = Some(group);
+            }
+            self.print_file_match(file_match)?;
+        }
+        Ok(())
+    }
+    
+    fn print_file_match(&mut self, file_match: &FileMatch) -> io::Result<()> {
+        if !file_match.has_matches() {
+            return Ok(());
+        }
+        
+        if self.files_printed > 0 && self.cli.separates_files() && !self.cli.no_heading {
+            self.print_file_separator()?;
+        }
+        self.files_printed += 1;
+        #[cfg(feature = "syntax-highlight")]
+        if let Some(ref mut syntax) = self.syntax {
+            syntax.set_file(&file_match.path);
+        }
+        
+        if self.cli.no_heading {
+            self.line_prefix = Some(self.cli.display_path(&file_match.path).display().to_string());
+        } else {
+            let mut header = self.cli.display_path(&file_match.path).display().to_string();
+            if self.cli.file_checksums {
+                if let Some(checksum) = file_checksum(&file_match.path) {
+                    header.push_str(&format!(" [sha256: {}]", checksum));
+                }
+            }
+            self.print_file_header(&header)?;
+        }
+        
+        let (before_context, after_context) = self.cli.effective_context();
+        let record_separator = self.cli.record_separator();
+        
+        for multiline_match in &file_match.multiline_matches {
+            self.print_multiline_match(multiline_match)?;
+        }
+        
+        // Context lines come from the source stored during search, never from disk
+        let records = if self.cli.has_context() {
+            file_match.source_records(record_separator.as_deref())
+        } else {
+            None
+        };
+        
+        let mut last_printed = 0;
+        for (index, line_match) in file_match.line_matches.iter().enumerate() {
+            let line_number = line_match.line_number;
             
-            let (before_context, after_context) = self.cli.effective_context();
+            if let Some(ref records) = records {
+                let first = line_number.saturating_sub(before_context).max(last_printed + 1).max(1);
+                for context_number in first..line_number {
+                    self.print_group_separator(file_match, context_number, last_printed, record_separator.as_deref())?;
+                    self.print_context_line(context_number, records[context_number - 1])?;
+                    last_printed = context_number;
+                }
+            }
             
-            for line_match in &file_match.line_matches {
-                self.print_line_match(line_match, before_context, after_context)?;
+            self.print_group_separator(file_match, line_number, last_printed, record_separator.as_deref())?;
+            if let Some(ast_path) = file_match.ast_path(line_number) {
+                self.print_ast_path(ast_path)?;
             }
+            self.print_line_match(line_match, before_context, after_context)?;
+            last_printed = line_number;
             
-            // Add separator between files
-            if file_matches.len() > 1 {
-                println!();
+            if let Some(ref records) = records {
+                // Stop before the next match so it is printed as a match, not as context
+                let next_match = file_match
+                    .line_matches
+                    .get(index + 1)
+                    .map(|next| next.line_number)
+                    .unwrap_or(usize::MAX);
+                let last = (line_number + after_context).min(records.len()).min(next_match - 1);
+                for context_number in (line_number + 1)..=last {
+                    self.print_group_separator(file_match, context_number, last_printed, record_separator.as_deref())?;
+                    self.print_context_line(context_number, records[context_number - 1])?;
+                    last_printed = context_number;
+                }
             }
         }
         
-        // Print stats if not in quiet mode
-        if !self.cli.files_only && !self.cli.count_only {
-            self.print_summary_stats(stats)?;
+        Ok(())
+    }
+    
+    /// Blank line between files, or a short rule of `--separator-char`
+    fn print_file_separator(&mut self) -> io::Result<()> {
+        match self.cli.separator_char {
+            Some(c) => writeln!(self.stdout, "{}", c.to_string().repeat(FILE_SEPARATOR_WIDTH)),
+            None => writeln!(self.stdout),
+        }
+    }
+    
+    /// Print `--` (or the `--split-on` record separator in its place) between non-adjacent groups
+    fn print_group_separator(
+        &mut self,
+        file_match: &FileMatch,
+        line_number: usize,
+        last_printed: usize,
+        record_separator: Option<&str>,
+    ) -> io::Result<()> {
+        if last_printed == 0 || line_number <= last_printed + 1 {
+            return Ok(());
         }
         
-        Ok(())
+        match record_separator {
+            Some(separator) if self.cli.has_context() => writeln!(self.stdout, "{}", separator),
+            Some(_) => Ok(()),
+            None if self.cli.show_function_boundaries => {
+                writeln!(self.stdout, "{}", function_boundary(file_match, line_number))
+            }
+            None if self.cli.has_context() => writeln!(self.stdout, "--"),
+            None => Ok(()),
+        }
     }
     
-    fn print_file_header(&mut self, filename: &str) -> io::Result<()> {
+    fn print_context_line(&mut self, line_number: usize, text: &str) -> io::Result<()> {
+        self.print_line_prefix('-')?;
+        if self.cli.line_numbers {
+            self.write_colored(&format!("{}-", line_number), ColorSpec::new().set_fg(Some(Color::Green)))?;
+        }
+        
+        if !self.print_syntax_highlighted(text, &[])? {
+            write!(self.stdout, "{}", text)?;
+        }
+        writeln!(self.stdout)
+    }
+    
+    /// `--color-syntax`: write `line` in its syntax colors, with `matches` bold and underlined on
+    /// top of them. Returns `false`, writing nothing, when the line is to be printed as usual.
+    #[cfg(feature = "syntax-highlight")]
+    fn print_syntax_highlighted(&mut self, line: &str, matches: &[Match]) -> io::Result<bool> {
+        let Some(regions) = self.syntax.as_ref().and_then(|syntax| syntax.regions(line)) else {
+            return Ok(false);
+        };
+        // Spans outside the line or off character boundaries are left unmarked, as in plain output
+        let matches: Vec<&Match> = matches.iter().filter(|m| m.end <= line.len() && line.get(m.start..m.end).is_some()).collect();
+        
+        for (spec, region) in regions {
+            // Split the region where a match starts or ends
+            let mut start = region.start;
+            while start < region.end {
+                let in_match = matches.iter().find(|m| m.start <= start && start < m.end);
+                let end = match in_match {
+                    Some(m) => m.end.min(region.end),
+                    None => matches
+                        .iter()
+                        .map(|m| m.start)
+                        .filter(|&match_start| start < match_start && match_start < region.end)
+                        .min()
+                        .unwrap_or(region.end),
+                };
+                let mut spec = spec.clone();
+                if in_match.is_some() {
+                    spec.set_bold(true).set_underline(true);
+                }
+                self.write_colored(line.get(start..end).unwrap_or(""), &spec)?;
+                start = end;
+            }
+        }
+        Ok(true)
+    }
+    
+    #[cfg(not(feature = "syntax-highlight"))]
+    fn print_syntax_highlighted(&mut self, _line: &str, _matches: &[Match]) -> io::Result<bool> {
+        Ok(false)
+    }
+    
+    /// Write `text` in the given color when colors are on; plain text goes through the same
+    /// stream so escape sequences and text never interleave, and the color is reset before any newline
+    fn write_colored(&mut self, text: &str, color: &ColorSpec) -> io::Result<()> {
         if self.cli.should_use_color() {
-            self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)).set_bold(true))?;
-            write!(self.stdout, "{}", filename)?;
-            self.stdout.reset()?;
-            writeln!(self.stdout)?;
+            self.stdout.set_color(color)?;
+            write!(self.stdout, "{}", text)?;
+            self.stdout.reset()
         } else {
-            println!("{}", filename);
+            write!(self.stdout, "{}", text)
+        }
+    }
+    
+    fn print_file_header(&mut self, filename: &str) -> io::Result<()> {
+        self.write_colored(filename, ColorSpec::new().set_fg(Some(Color::Magenta)).set_bold(true))?;
+        writeln!(self.stdout)
+    }
+    
+    fn print_ast_path(&mut self, ast_path: &str) -> io::Result<()> {
+        self.write_colored(&format!("[AST: {}]", ast_path), ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+        writeln!(self.stdout)
+    }
+    
+    /// Print the file name before a line when headings are off (`path:` for matches, `path-` for context)
+    fn print_line_prefix(&mut self, separator: char) -> io::Result<()> {
+        let Some(prefix) = self.line_prefix.clone() else {
+            return Ok(());
+        };
+        self.write_colored(&prefix, ColorSpec::new().set_fg(Some(Color::Magenta)))?;
+        write!(self.stdout, "{}", separator)
+    }
+    
+    fn print_vimgrep(&mut self, file_match: &FileMatch) -> io::Result<()> {
+        for line_match in &file_match.line_matches {
+            for match_info in &line_match.matches {
+                writeln!(
+                    self.stdout,
+                    "{}:{}:{}:{}",
+                    self.cli.display_path(&file_match.path).display(),
+                    line_match.line_number,
+                    display_column(&line_match.line_text, match_info.start, self.cli.tab_size),
+                    line_match.line_text
+                )?;
+            }
         }
         Ok(())
     }
     
     fn print_line_match(&mut self, line_match: &LineMatch, _before: usize, _after: usize) -> io::Result<()> {
+        if self.cli.only_matching {
+            return self.print_only_matching(line_match);
+        }
+        
+        self.print_line_prefix(':')?;
+        
+        // Occurrence count, right-aligned like `uniq -c`
+        if self.cli.dedupes_lines() {
+            self.write_colored(&format!("{:>7} ", line_match.occurrences), ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+        }
+        
         // Line number
         if self.cli.line_numbers {
-            if self.cli.should_use_color() {
-                self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?;
-                write!(self.stdout, "{}:", line_match.line_number)?;
-                self.stdout.reset()?;
-            } else {
-                print!("{}:", line_match.line_number);
+            self.write_colored(&format!("{}:", line_match.line_number), ColorSpec::new().set_fg(Some(Color::Green)))?;
+        }
+        
+        // Column of the first match
+        if self.cli.column {
+            if let Some(first) = line_match.matches.first() {
+                let column = display_column(&line_match.line_text, first.start, self.cli.tab_size);
+                self.write_colored(&format!("{}:", column), ColorSpec::new().set_fg(Some(Color::Green)))?;
             }
         }
         
         // Print line with highlighted matches
-        let mut last_end = 0;
         let line_text = &line_match.line_text;
-        
-        for match_info in &line_match.matches {
-            // Print text before match
-            print!("{}", &line_text[last_end..match_info.start]);
-            
-            // Print highlighted match
-            if self.cli.should_use_color() {
-                self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
-                write!(self.stdout, "{}", &match_info.text)?;
-                self.stdout.reset()?;
-            } else {
-                print!("{}", &match_info.text);
+        if !self.print_syntax_highlighted(line_text, &line_match.matches)? {
+            let mut last_end = 0;
+            for match_info in &line_match.matches {
+                // Skip overlapping or out-of-order spans rather than slicing backwards
+                if match_info.start < last_end || match_info.end > line_text.len() {
+                    continue;
+                }
+                
+                // Print text before match
+                write!(self.stdout, "{}", line_text.get(last_end..match_info.start).unwrap_or(""))?;
+                
+                // Print highlighted match
+                self.print_highlighted_match(match_info, line_text)?;
+                
+                last_end = match_info.end;
             }
             
-            last_end = match_info.end;
+            // Print remaining text
+            write!(self.stdout, "{}", line_text.get(last_end..).unwrap_or(""))?;
+        }
+        if self.cli.print_line_checksums {
+            write!(self.stdout, " [crc32: {}]", line_checksum(line_text))?;
         }
+        writeln!(self.stdout)?;
         
-        // Print remaining text
-        println!("{}", &line_text[last_end..]);
+        // Zero-length matches have nothing to highlight, so mark their position below the line
+        if line_match.matches.iter().any(|m| m.is_empty()) {
+            self.print_zero_length_markers(line_match)?;
+        }
         
         Ok(())
     }
     
-    fn print_stats_only(&mut self, stats: &SearchStats) -> io::Result<()> {
-        println!("Files searched: {}", stats.files_searched);
-        println!("Files with matches: {}", stats.files_with_matches);
-        println!("Total matches: {}", stats.total_matches);
-        println!("Total lines: {}", stats.total_lines);
-        println!("Elapsed time: {:.3}s", stats.elapsed_time.as_secs_f64());
-        println!("Search rate: {:.1} files/s", stats.search_rate);
+    /// Match in red with its capture groups in their own colors; where groups nest, the innermost wins
+    fn print_highlighted_match(&mut self, match_info: &Match, line_text: &str) -> io::Result<()> {
+        let full = match_info.as_str(line_text);
+        let truncated = self.truncated_match(full);
+        let matched = truncated.unwrap_or(full);
+        let groups: Vec<(usize, usize, usize)> = match_info
+            .captures
+            .iter()
+            .enumerate()
+            .filter_map(|(index, span)| span.map(|(start, end)| (index, start, end.min(matched.len()))))
+            .filter(|&(_, start, end)| start < end && matched.get(start..end).is_some())
+            .collect();
+        if groups.is_empty() {
+            self.write_colored(matched, ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+            return self.print_truncation_marker(truncated.is_some());
+        }
+        
+        let mut boundaries: Vec<usize> = groups.iter().flat_map(|&(_, start, end)| [start, end]).collect();
+        boundaries.extend([0, matched.len()]);
+        boundaries.sort_unstable();
+        boundaries.dedup();
+        for window in boundaries.windows(2) {
+            let (start, end) = (window[0], window[1]);
+            let color = groups
+                .iter()
+                .rev()
+                .find(|&&(_, group_start, group_end)| group_start <= start && end <= group_end)
+                .map(|&(index, _, _)| CAPTURE_COLORS[index % CAPTURE_COLORS.len()])
+                .unwrap_or(Color::Red);
+            self.write_colored(&matched[start..end], ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+        }
+        self.print_truncation_marker(truncated.is_some())
+    }
+    
+    /// `matched` cut to `--max-match-length` characters, or `None` when it fits
+    fn truncated_match<'a>(&self, matched: &'a str) -> Option<&'a str> {
+        let max = self.cli.max_match_length?;
+        matched.char_indices().nth(max).map(|(cut, _)| &matched[..cut])
+    }
+    
+    fn print_truncation_marker(&mut self, truncated: bool) -> io::Result<()> {
+        if truncated {
+            self.write_colored(truncation_marker(), ColorSpec::new().set_dimmed(true))?;
+        }
         Ok(())
     }
     
-    fn print_summary_stats(&mut self, stats: &SearchStats) -> io::Result<()> {
-        if self.cli.should_use_color() {
-            self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Blue)))?;
-            writeln!(
-                self.stdout,
-                "Searched {} files in {:.3}s ({} matches found)",
-                stats.files_searched,
-                stats.elapsed_time.as_secs_f64(),
-                stats.total_matches
-            )?;
-            self.stdout.reset()?;
-        } else {
-            println!(
-                "Searched {} files in {:.3}s ({} matches found)",
-                stats.files_searched,
-                stats.elapsed_time.as_secs_f64(),
-                stats.total_matches
-            );
+    /// `--only-matching`: one output line per match (or per merged run with `--merge-adjacent`)
+    fn print_only_matching(&mut self, line_match: &LineMatch) -> io::Result<()> {
+        let line_text = &line_match.line_text;
+        let spans: Vec<(usize, usize)> = line_match
+            .matches
+            .iter()
+            .filter(|m| !m.is_empty() && line_text.get(m.start..m.end).is_some())
+            .map(|m| (m.start, m.end))
+            .collect();
+        let spans = match self.cli.merge_adjacent {
+            Some(gap) => merge_adjacent_spans(&spans, line_text, gap),
+            None => spans,
+        };
+        
+        for (start, end) in spans {
+            self.print_line_prefix(':')?;
+            if self.cli.line_numbers {
+                self.write_colored(&format!("{}:", line_match.line_number), ColorSpec::new().set_fg(Some(Color::Green)))?;
+            }
+            if self.cli.column {
+                let column = display_column(line_text, start, self.cli.tab_size);
+                self.write_colored(&format!("{}:", column), ColorSpec::new().set_fg(Some(Color::Green)))?;
+            }
+            let matched = &line_text[start..end];
+            let truncated = self.truncated_match(matched);
+            self.write_colored(truncated.unwrap_or(matched), ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+            self.print_truncation_marker(truncated.is_some())?;
+            writeln!(self.stdout)?;
         }
         Ok(())
     }
     
+    fn print_zero_length_markers(&mut self, line_match: &LineMatch) -> io::Result<()> {
+        let file_width = self.line_prefix.as_ref().map_or(0, |prefix| prefix.chars().count() + 1);
+        let prefix_width = if self.cli.line_numbers {
+            file_width + line_match.line_number.to_string().len() + 1
+        } else {
+            file_width
+        };
+        
+        let mut markers = " ".repeat(prefix_width);
+        let mut column = 0;
+        for match_info in line_match.matches.iter().filter(|m| m.is_empty()) {
+            let target = line_match.line_text.get(..match_info.start).unwrap_or("").chars().count();
+            if target < column {
+                continue;
+            }
+            markers.push_str(&" ".repeat(target - column));
+            markers.push('^');
+            column = target + 1;
+        }
+        
+        self.write_colored(&markers, ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+        writeln!(self.stdout)
+    }
+    
+    fn print_multiline_match(&mut self, multiline_match: &MultilineMatch) -> io::Result<()> {
+        self.print_line_prefix(':')?;
+        if self.cli.line_numbers {
+            let range = if multiline_match.start_line == multiline_match.end_line {
+                format!("{}:", multiline_match.start_line)
+            } else {
+                format!("{}-{}:", multiline_match.start_line, multiline_match.end_line)
+            };
+            self.write_colored(&range, ColorSpec::new().set_fg(Some(Color::Green)))?;
+        }
+        
+        // Highlight each visual line separately so colors don't bleed across line breaks
+        for (index, segment) in multiline_match.text.split('\n').enumerate() {
+            if index > 0 {
+                writeln!(self.stdout)?;
+            }
+            self.write_colored(segment, ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+        }
+        writeln!(self.stdout)
+    }
+    
+    fn print_stats_only(&mut self, stats: &SearchStats) -> io::Result<()> {
+        let out = &mut self.stdout;
+        writeln!(out, "Files searched: {}", stats.files_searched)?;
+        writeln!(out, "Files with matches: {}", stats.files_with_matches)?;
+        writeln!(out, "Total matches: {}", stats.total_matches)?;
+        writeln!(out, "Total lines: {}", stats.total_lines)?;
+        writeln!(out, "Elapsed time: {:.3}s", stats.elapsed_time.as_secs_f64())?;
+        writeln!(out, "Search rate: {:.1} files/s", stats.search_rate)?;
+        writeln!(out, "Bytes searched: {}", stats.bytes_searched)?;
+        writeln!(out, "Lines searched: {}", stats.lines_searched)?;
+        writeln!(out, "Throughput: {:.1} MB/s, {:.0} lines/s", stats.bytes_per_second / 1_000_000.0, stats.lines_per_second)
+    }
+    
+    /// `N files matched of M searched` on stderr, so the path list on stdout stays clean
+    fn print_files_only_footer(&mut self, stats: &SearchStats) -> io::Result<()> {
+        if self.cli.no_messages || self.cli.rg_compat {
+            return Ok(());
+        }
+        writeln!(
+            self.stderr,
+            "{} file{} matched of {} searched",
+            stats.files_with_matches,
+            if stats.files_with_matches == 1 { "" } else { "s" },
+            stats.files_searched
+        )?;
+        self.stderr.flush()
+    }
+    
+    fn print_summary_stats(&mut self, stats: &SearchStats) -> io::Result<()> {
+        // ripgrep prints nothing after the results
+        if self.cli.rg_compat {
+            return Ok(());
+        }
+        
+        let summary = format!(
+            "Searched {} files in {:.3}s ({} matches found)",
+            stats.files_searched,
+            stats.elapsed_time.as_secs_f64(),
+            stats.total_matches
+        );
+        self.write_colored(&summary, ColorSpec::new().set_fg(Some(Color::Blue)))?;
+        writeln!(self.stdout)
+    }
+    
     fn print_json_results(&mut self, file_matches: &[FileMatch], stats: &SearchStats) -> io::Result<()> {
+        let json = self.render_json_results(file_matches, stats).map_err(io::Error::other)?;
+        writeln!(self.stdout, "{}", json)?;
+        Ok(())
+    }
+    
+    fn render_json_results(&self, file_matches: &[FileMatch], stats: &SearchStats) -> serde_json::Result<String> {
+        let result = self.json_results(file_matches, stats);
+        if self.cli.json_compact {
+            serde_json::to_string(&result)
+        } else {
+            serde_json::to_string_pretty(&result)
+        }
+    }
+    
+    /// Same document as the JSON output, so both formats share one schema
+    #[cfg(feature = "yaml-output")]
+    fn print_yaml_results(&mut self, file_matches: &[FileMatch], stats: &SearchStats) -> io::Result<()> {
+        let result = self.json_results(file_matches, stats);
+        serde_yaml::to_writer(&mut self.stdout, &result).map_err(io::Error::other)
+    }
+    
+    /// `Cli::validate` rejects `--output yaml` without the feature
+    #[cfg(not(feature = "yaml-output"))]
+    fn print_yaml_results(&mut self, _file_matches: &[FileMatch], _stats: &SearchStats) -> io::Result<()> {
+        Err(io::Error::other("YAML output requires the `yaml-output` feature"))
+    }
+    
+    fn json_results(&self, file_matches: &[FileMatch], stats: &SearchStats) -> serde_json::Value {
         let mut json_files = Vec::new();
+        let (before_context, after_context) = self.cli.effective_context();
+        let record_separator = self.cli.record_separator();
         
         for file_match in file_matches {
             if !file_match.has_matches() {
                 continue;
             }
             
+            // Context text comes from the source stored during search
+            let records = if self.cli.has_context() {
+                file_match.source_records(record_separator.as_deref())
+            } else {
+                None
+            };
+            
             let mut json_lines = Vec::new();
             for line_match in &file_match.line_matches {
+                let todos = if self.cli.todos { parse_todos(&line_match.line_text) } else { Vec::new() };
                 let mut json_matches = Vec::new();
                 for match_info in &line_match.matches {
-                    json_matches.push(json!({
+                    let mut json_match = json!({
                         "start": match_info.start,
                         "end": match_info.end,
                         "text": match_info.text
-                    }));
+                    });
+                    // The full text is kept; consumers learn that a terminal would have cut it
+                    if self.truncated_match(&match_info.text).is_some() {
+                        json_match["truncated"] = json!(true);
+                    }
+                    // --todos matches the marker words, so each match carries its marker's fields
+                    if let Some(todo) = todos.iter().find(|todo| todo.start == match_info.start) {
+                        json_match["marker"] = json!(todo.marker);
+                        json_match["author"] = json!(todo.author);
+                        json_match["note"] = json!(todo.note);
+                    }
+                    json_matches.push(json_match);
                 }
                 
-                json_lines.push(json!({
+                let mut json_line = json!({
                     "line_number": line_match.line_number,
                     "line_text": line_match.line_text,
                     "matches": json_matches
-                }));
+                });
+                if let Some(ref records) = records {
+                    let index = line_match.line_number - 1;
+                    let first = index.saturating_sub(before_context);
+                    let last = (index + after_context).min(records.len().saturating_sub(1));
+                    json_line["before"] = json!(records.get(first..index).unwrap_or_default());
+                    json_line["after"] = json!(records.get(index + 1..=last).unwrap_or_default());
+                }
+                if let Some(ast_path) = file_match.ast_path(line_match.line_number) {
+                    json_line["ast_path"] = json!(ast_path);
+                }
+                if self.cli.dedupes_lines() {
+                    json_line["occurrences"] = json!(line_match.occurrences);
+                }
+                if self.cli.print_line_checksums {
+                    json_line["checksum"] = json!(line_checksum(&line_match.line_text));
+                }
+                json_lines.push(json_line);
             }
             
-            json_files.push(json!({
-                "path": file_match.path.display().to_string(),
+            let mut json_file = json!({
+                "path": self.cli.display_path(&file_match.path).display().to_string(),
                 "total_matches": file_match.total_matches,
                 "lines": json_lines
-            }));
+            });
+            if !file_match.multiline_matches.is_empty() {
+                json_file["multiline_matches"] = json!(file_match.multiline_matches);
+            }
+            if self.cli.file_checksums {
+                json_file["sha256"] = json!(file_checksum(&file_match.path));
+            }
+            json_files.push(json_file);
         }
         
-        let result = json!({
+        json!({
             "files": json_files,
             "stats": {
                 "files_searched": stats.files_searched,
@@ -203,16 +798,17 @@ impl OutputFormatter {
                 "total_matches": stats.total_matches,
                 "total_lines": stats.total_lines,
                 "elapsed_time_seconds": stats.elapsed_time.as_secs_f64(),
-                "search_rate_files_per_second": stats.search_rate
+                "search_rate_files_per_second": stats.search_rate,
+                "bytes_searched": stats.bytes_searched,
+                "lines_searched": stats.lines_searched,
+                "bytes_per_second": stats.bytes_per_second,
+                "lines_per_second": stats.lines_per_second
             }
-        });
-        
-        println!("{}", serde_json::to_string_pretty(&result).unwrap());
-        Ok(())
+        })
     }
     
     fn print_csv_results(&mut self, file_matches: &[FileMatch]) -> io::Result<()> {
-        println!("file,line_number,line_text,match_start,match_end,match_text");
+        writeln!(self.stdout, "file,line_number,line_text,match_start,match_end,match_text")?;
         
         for file_match in file_matches {
             if !file_match.has_matches() {
@@ -221,15 +817,16 @@ impl OutputFormatter {
             
             for line_match in &file_match.line_matches {
                 for match_info in &line_match.matches {
-                    println!(
+                    writeln!(
+                        self.stdout,
                         "\"{}\",{},\"{}\",{},{},\"{}\"",
-                        file_match.path.display(),
+                        self.cli.display_path(&file_match.path).display(),
                         line_match.line_number,
                         line_match.line_text.replace('"', "\"\""),
                         match_info.start,
                         match_info.end,
                         match_info.text.replace('"', "\"\"")
-                    );
+                    )?;
                 }
             }
         }
@@ -238,38 +835,388 @@ impl OutputFormatter {
     }
     
     fn print_xml_results(&mut self, file_matches: &[FileMatch]) -> io::Result<()> {
-        println!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
-        println!("<search_results>");
+        writeln!(self.stdout, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(self.stdout, "<search_results>")?;
         
         for file_match in file_matches {
             if !file_match.has_matches() {
                 continue;
             }
             
-            println!("  <file path=\"{}\" total_matches=\"{}\">", 
-                     html_escape(&file_match.path.display().to_string()),
-                     file_match.total_matches);
+            writeln!(
+                self.stdout,
+                "  <file path=\"{}\" total_matches=\"{}\">",
+                html_escape(&self.cli.display_path(&file_match.path).display().to_string()),
+                file_match.total_matches
+            )?;
             
             for line_match in &file_match.line_matches {
-                println!("    <line number=\"{}\">", line_match.line_number);
-                println!("      <text>{}</text>", html_escape(&line_match.line_text));
+                writeln!(self.stdout, "    <line number=\"{}\">", line_match.line_number)?;
+                writeln!(self.stdout, "      <text>{}</text>", html_escape(&line_match.line_text))?;
                 
                 for match_info in &line_match.matches {
-                    println!("      <match start=\"{}\" end=\"{}\">{}</match>",
-                             match_info.start,
-                             match_info.end,
-                             html_escape(&match_info.text));
+                    writeln!(
+                        self.stdout,
+                        "      <match start=\"{}\" end=\"{}\">{}</match>",
+                        match_info.start,
+                        match_info.end,
+                        html_escape(&match_info.text)
+                    )?;
                 }
                 
-                println!("    </line>");
+                writeln!(self.stdout, "    </line>")?;
             }
             
-            println!("  </file>");
+            writeln!(self.stdout, "  </file>")?;
         }
         
-        println!("</search_results>");
+        writeln!(self.stdout, "</search_results>")?;
         Ok(())
     }
+    
+    fn print_markdown_results(&mut self, file_matches: &[FileMatch]) -> io::Result<()> {
+        for file_match in file_matches {
+            if !file_match.has_matches() {
+                continue;
+            }
+            
+            writeln!(self.stdout, "### {}", self.cli.display_path(&file_match.path).display())?;
+            writeln!(self.stdout)?;
+            let language = self.code_language(&file_match.path).unwrap_or_default();
+            writeln!(self.stdout, "```{}", language)?;
+            for line_match in &file_match.line_matches {
+                writeln!(self.stdout, "{}: {}", line_match.line_number, line_match.line_text)?;
+            }
+            writeln!(self.stdout, "```")?;
+            writeln!(self.stdout)?;
+        }
+        
+        Ok(())
+    }
+    
+    fn print_html_results(&mut self, file_matches: &[FileMatch]) -> io::Result<()> {
+        writeln!(self.stdout, "<div class=\"search-results\">")?;
+        
+        for file_match in file_matches {
+            if !file_match.has_matches() {
+                continue;
+            }
+            
+            let data_lang = self
+                .code_language(&file_match.path)
+                .map(|lang| format!(" data-lang=\"{}\"", html_escape(&lang)))
+                .unwrap_or_default();
+            
+            writeln!(self.stdout, "  <h3>{}</h3>", html_escape(&self.cli.display_path(&file_match.path).display().to_string()))?;
+            writeln!(self.stdout, "  <pre{}>", data_lang)?;
+            for line_match in &file_match.line_matches {
+                writeln!(self.stdout, "{}: {}", line_match.line_number, html_escape(&line_match.line_text))?;
+            }
+            writeln!(self.stdout, "  </pre>")?;
+        }
+        
+        writeln!(self.stdout, "</div>")?;
+        Ok(())
+    }
+    
+    fn print_sarif_results(&mut self, file_matches: &[FileMatch]) -> io::Result<()> {
+        let sarif = serde_json::to_string_pretty(&self.render_sarif_results(file_matches)).map_err(io::Error::other)?;
+        writeln!(self.stdout, "{}", sarif)?;
+        Ok(())
+    }
+    
+    /// SARIF 2.1.0 log with one result per match
+    fn render_sarif_results(&self, file_matches: &[FileMatch]) -> serde_json::Value {
+        let rule_id = "cg/pattern-match";
+        let pattern = self.cli.pattern.clone().unwrap_or_default();
+        let mut results = Vec::new();
+        
+        for file_match in file_matches {
+            let uri = sarif_uri(self.cli.display_path(&file_match.path));
+            
+            for line_match in &file_match.line_matches {
+                for match_info in &line_match.matches {
+                    // SARIF columns are 1-based character positions
+                    let line_text = &line_match.line_text;
+                    let start_column = line_text.get(..match_info.start).unwrap_or("").chars().count() + 1;
+                    let end_column = start_column + match_info.as_str(line_text).chars().count();
+                    results.push(sarif_result(rule_id, &match_info.text, &uri, json!({
+                        "startLine": line_match.line_number,
+                        "startColumn": start_column,
+                        "endColumn": end_column
+                    })));
+                }
+            }
+            
+            for multiline_match in &file_match.multiline_matches {
+                results.push(sarif_result(rule_id, &multiline_match.text, &uri, json!({
+                    "startLine": multiline_match.start_line,
+                    "startColumn": multiline_match.start_col + 1,
+                    "endLine": multiline_match.end_line,
+                    "endColumn": multiline_match.end_col + 1
+                })));
+            }
+        }
+        
+        json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "code-grep",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                        "rules": [{
+                            "id": rule_id,
+                            "shortDescription": { "text": format!("Matches of '{}'", pattern) }
+                        }]
+                    }
+                },
+                "results": results
+            }]
+        })
+    }
+    
+    /// Language of Markdown code fences and HTML `data-lang`: `--output-language`, else the extension
+    fn code_language(&self, path: &Path) -> Option<String> {
+        if let Some(ref language) = self.cli.output_language {
+            return Some(language.clone());
+        }
+        
+        let language = path.extension().and_then(|e| e.to_str()).and_then(extension_language)?;
+        Some(language.to_string())
+    }
+}
+
+/// JSON Schema (draft 2020-12) describing the `--output json` document
+pub fn json_output_schema() -> serde_json::Value {
+    let count = json!({ "type": "integer", "minimum": 0 });
+    let context = json!({ "type": "array", "items": { "type": "string" } });
+    
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": "https://github.com/yamadatarousan/code-grep/schemas/output.json",
+        "title": "code-grep JSON output",
+        "type": "object",
+        "required": ["files", "stats"],
+        "properties": {
+            "files": {
+                "type": "array",
+                "items": { "$ref": "#/$defs/file" }
+            },
+            "stats": {
+                "type": "object",
+                "required": [
+                    "files_searched",
+                    "files_with_matches",
+                    "total_matches",
+                    "total_lines",
+                    "elapsed_time_seconds",
+                    "search_rate_files_per_second",
+                    "bytes_searched",
+                    "lines_searched",
+                    "bytes_per_second",
+                    "lines_per_second"
+                ],
+                "properties": {
+                    "files_searched": count,
+                    "files_with_matches": count,
+                    "total_matches": count,
+                    "total_lines": count,
+                    "elapsed_time_seconds": { "type": "number", "minimum": 0 },
+                    "search_rate_files_per_second": { "type": "number", "minimum": 0 },
+                    "bytes_searched": count,
+                    "lines_searched": count,
+                    "bytes_per_second": { "type": "number", "minimum": 0 },
+                    "lines_per_second": { "type": "number", "minimum": 0 }
+                }
+            }
+        },
+        "$defs": {
+            "file": {
+                "type": "object",
+                "required": ["path", "total_matches", "lines"],
+                "properties": {
+                    "path": { "type": "string" },
+                    "total_matches": count,
+                    "lines": {
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/line" }
+                    },
+                    "multiline_matches": {
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/multiline_match" }
+                    },
+                    "sha256": { "type": ["string", "null"], "pattern": "^[0-9a-f]{64}$" }
+                }
+            },
+            "line": {
+                "type": "object",
+                "required": ["line_number", "line_text", "matches"],
+                "properties": {
+                    "line_number": { "type": "integer", "minimum": 1 },
+                    "line_text": { "type": "string" },
+                    "matches": {
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/match" }
+                    },
+                    "before": context,
+                    "after": context,
+                    "occurrences": { "type": "integer", "minimum": 1 },
+                    "checksum": { "type": "string", "pattern": "^0x[0-9A-F]{8}$" },
+                    "ast_path": { "type": "string" },
+                    "marker": { "type": "string", "enum": ["TODO", "FIXME", "HACK", "XXX"] },
+                    "author": { "type": ["string", "null"] },
+                    "note": { "type": "string" }
+                }
+            },
+            "match": {
+                "type": "object",
+                "required": ["start", "end", "text"],
+                "properties": {
+                    "start": count,
+                    "end": count,
+                    "text": { "type": "string" },
+                    "truncated": { "type": "boolean" }
+                }
+            },
+            "multiline_match": {
+                "type": "object",
+                "required": ["start_line", "end_line", "start_col", "end_col", "text"],
+                "properties": {
+                    "start_line": { "type": "integer", "minimum": 1 },
+                    "end_line": { "type": "integer", "minimum": 1 },
+                    "start_col": count,
+                    "end_col": count,
+                    "text": { "type": "string" }
+                }
+            }
+        }
+    })
+}
+
+/// CRC32 of a matched line, formatted like `0xDEADBEEF`
+fn line_checksum(line: &str) -> String {
+    format!("0x{:08X}", crc32fast::hash(line.as_bytes()))
+}
+
+/// Hex SHA-256 of a file's content on disk, or `None` when it cannot be read
+fn file_checksum(path: &Path) -> Option<String> {
+    let content = std::fs::read(path).ok()?;
+    let digest = Sha256::digest(&content);
+    Some(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Join byte spans (sorted by start) whose gap is at most `gap` characters of `line`;
+/// overlapping spans always join
+fn merge_adjacent_spans(spans: &[(usize, usize)], line: &str, gap: usize) -> Vec<(usize, usize)> {
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for &(start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 || line[last.1..start].chars().count() <= gap => {
+                last.1 = last.1.max(end);
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Where a source file's split results go inside the output directory: its path relative to the
+/// current directory, keeping only plain components so nothing can land outside the directory
+fn split_result_path(path: &Path) -> PathBuf {
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok())
+        .unwrap_or(path);
+    relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Highlighter for `--color-syntax`; loading the syntaxes takes a moment, so only when colors are on
+#[cfg(feature = "syntax-highlight")]
+fn syntax_highlighter(cli: &Cli) -> Option<crate::SyntaxHighlighter> {
+    (cli.color_syntax && cli.should_use_color()).then(crate::SyntaxHighlighter::new)
+}
+
+/// Appended to matches cut by `--max-match-length`: an ellipsis, or `...` when the locale is not UTF-8
+fn truncation_marker() -> &'static str {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()));
+    match locale {
+        Some(locale) if !locale.to_ascii_lowercase().replace('-', "").contains("utf8") => "...",
+        _ => "…",
+    }
+}
+
+/// SARIF artifact URI: a forward-slash relative path without a leading `./`
+fn sarif_uri(path: &Path) -> String {
+    let path = path.strip_prefix(".").unwrap_or(path);
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn sarif_result(rule_id: &str, text: &str, uri: &str, region: serde_json::Value) -> serde_json::Value {
+    json!({
+        "ruleId": rule_id,
+        "level": "note",
+        "message": { "text": text },
+        "locations": [{
+            "physicalLocation": {
+                "artifactLocation": { "uri": uri },
+                "region": region
+            }
+        }]
+    })
+}
+
+/// `git grep -n` style lines: `file:line: text`
+fn git_grep_lines(file_match: &FileMatch, path: &Path) -> String {
+    file_match
+        .line_matches
+        .iter()
+        .map(|line_match| {
+            format!(
+                "{}:{}: {}\n",
+                path.display(),
+                line_match.line_number,
+                line_match.line_text
+            )
+        })
+        .collect()
+}
+
+/// Separator naming the function the next group is in, or plain `--` outside functions
+fn function_boundary(file_match: &FileMatch, line_number: usize) -> String {
+    match file_match.enclosing_function(line_number) {
+        Some(func) => format!("-- inside: {} (lines {}–{}) --", func.name, func.start_line, func.end_line),
+        None => "--".to_string(),
+    }
+}
+
+/// 1-based visual column of `byte_offset`, expanding tabs to the next tab stop
+pub fn display_column(line: &str, byte_offset: usize, tab_size: usize) -> usize {
+    let tab_size = tab_size.max(1);
+    let mut column = 0;
+    
+    for (index, c) in line.char_indices() {
+        if index >= byte_offset {
+            break;
+        }
+        if c == '\t' {
+            column += tab_size - column % tab_size;
+        } else {
+            column += 1;
+        }
+    }
+    
+    column + 1
 }
 
 fn html_escape(text: &str) -> String {
@@ -283,8 +1230,7 @@ fn html_escape(text: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{LineMatch, Match};
-    use std::path::PathBuf;
+    use crate::PatternMatcher;
     
     #[test]
     fn test_html_escape() {
@@ -292,6 +1238,18 @@ mod tests {
         assert_eq!(html_escape("\"test\""), "&quot;test&quot;");
     }
     
+    #[test]
+    fn test_display_column_expands_tabs() {
+        let line = "\tlet x = 1;";
+        let byte_offset = line.find("let").unwrap();
+        
+        assert_eq!(byte_offset, 1);
+        assert_eq!(display_column(line, byte_offset, 4), 5);
+        assert_eq!(display_column(line, byte_offset, 8), 9);
+        assert_eq!(display_column("ab\tc", 3, 4), 5);
+        assert_eq!(display_column("abc", 1, 4), 2);
+    }
+    
     #[test]
     fn test_output_formatter_creation() {
         let cli = Cli {
@@ -300,8 +1258,709 @@ mod tests {
             ..Default::default()
         };
         
-        let formatter = OutputFormatter::new(cli);
         // Just test that it creates without panicking
-        assert!(true);
+        let _formatter = OutputFormatter::new(cli);
+    }
+    
+    #[test]
+    fn test_context_uses_stored_source() {
+        use crate::{LineMatch, Match};
+        use std::path::PathBuf;
+        
+        // The path does not exist, so context can only come from the stored source
+        let file_match = FileMatch::new(
+            PathBuf::from("/nonexistent/code-grep/test.txt"),
+            vec![LineMatch::new(
+                2,
+                "two".to_string(),
+                vec![Match { start: 0, end: 3, text: "two".to_string(), captures: Vec::new() }],
+            )],
+        )
+        .with_source("one\ntwo\nthree".into());
+        
+        assert_eq!(file_match.source_records(None), Some(vec!["one", "two", "three"]));
+        
+        let cli = Cli {
+            color: ColorChoice::Never,
+            context: 1,
+            line_numbers: true,
+            ..Default::default()
+        };
+        let stats = SearchStats::new(1, 1, 1, 1, std::time::Duration::from_millis(1));
+        let output = render(cli, &[file_match], &stats);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[..4], ["/nonexistent/code-grep/test.txt", "1-one", "2:two", "3-three"]);
+    }
+    
+    #[test]
+    fn test_code_language() {
+        let formatter = OutputFormatter::new(Cli { color: ColorChoice::Never, ..Default::default() });
+        assert_eq!(formatter.code_language(Path::new("main.rs")).as_deref(), Some("rust"));
+        assert_eq!(formatter.code_language(Path::new("index.mjs")).as_deref(), Some("javascript"));
+        assert_eq!(formatter.code_language(Path::new("setup.bash")).as_deref(), Some("bash"));
+        assert_eq!(formatter.code_language(Path::new("app.log")), None);
+        
+        let formatter = OutputFormatter::new(Cli {
+            color: ColorChoice::Never,
+            output_language: Some("log".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(formatter.code_language(Path::new("app.log")).as_deref(), Some("log"));
+        assert_eq!(formatter.code_language(Path::new("main.rs")).as_deref(), Some("log"));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_json_compact() {
+        use crate::{LineMatch, Match};
+        use std::path::PathBuf;
+        use std::time::Duration;
+        
+        let file_matches = vec![FileMatch::new(
+            PathBuf::from("a.rs"),
+            vec![LineMatch::new(
+                1,
+                "fn main() {}".to_string(),
+                vec![Match { start: 3, end: 7, text: "main".to_string(), captures: Vec::new() }],
+            )],
+        )];
+        let stats = SearchStats::new(1, 1, 1, 1, Duration::from_millis(5));
+        
+        let formatter = OutputFormatter::new(Cli { color: ColorChoice::Never, ..Default::default() });
+        assert!(formatter.render_json_results(&file_matches, &stats).unwrap().contains('\n'));
+        
+        let formatter = OutputFormatter::new(Cli {
+            color: ColorChoice::Never,
+            json_compact: true,
+            ..Default::default()
+        });
+        let compact = formatter.render_json_results(&file_matches, &stats).unwrap();
+        assert!(!compact.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&compact).unwrap();
+        assert_eq!(value["files"][0]["lines"][0]["matches"][0]["text"], "main");
+    }
+    
+    #[test]
+    fn test_function_boundary_separator() {
+        use crate::FunctionInfo;
+        use std::path::PathBuf;
+        
+        let function = |name: &str, start_line, end_line| FunctionInfo {
+            name: name.to_string(),
+            start_line,
+            end_line,
+            signature: String::new(),
+        };
+        let file_match = FileMatch::new(PathBuf::from("lib.rs"), vec![])
+            .with_functions(vec![function("outer", 1, 10), function("inner", 3, 5)]);
+        
+        assert_eq!(function_boundary(&file_match, 4), "-- inside: inner (lines 3–5) --");
+        assert_eq!(function_boundary(&file_match, 8), "-- inside: outer (lines 1–10) --");
+        assert_eq!(function_boundary(&file_match, 12), "--");
+    }
+    
+    #[test]
+    fn test_git_grep_compat_format() {
+        use crate::{LineMatch, Match};
+        use std::path::PathBuf;
+        
+        let file_match = FileMatch::new(
+            PathBuf::from("src/lib.rs"),
+            vec![
+                LineMatch::new(3, "use foo;".to_string(), vec![Match { start: 4, end: 7, text: "foo".to_string(), captures: Vec::new() }]),
+                LineMatch::new(9, "foo();".to_string(), vec![Match { start: 0, end: 3, text: "foo".to_string(), captures: Vec::new() }]),
+            ],
+        );
+        assert_eq!(git_grep_lines(&file_match, &file_match.path), "src/lib.rs:3: use foo;\nsrc/lib.rs:9: foo();\n");
+        
+        let cli = Cli { git_grep_compat: true, color: ColorChoice::Always, ..Default::default() };
+        assert!(!cli.should_use_color());
+    }
+    
+    #[test]
+    fn test_json_context_lines() {
+        use crate::{LineMatch, Match};
+        use std::path::PathBuf;
+        use std::time::Duration;
+        
+        let file_match = FileMatch::new(
+            PathBuf::from("a.txt"),
+            vec![
+                LineMatch::new(1, "one".to_string(), vec![Match { start: 0, end: 3, text: "one".to_string(), captures: Vec::new() }]),
+                LineMatch::new(3, "three".to_string(), vec![Match { start: 0, end: 5, text: "three".to_string(), captures: Vec::new() }]),
+            ],
+        )
+        .with_source("one\ntwo\nthree\nfour".into());
+        let stats = SearchStats::new(1, 1, 2, 2, Duration::from_millis(1));
+        
+        let formatter = OutputFormatter::new(Cli {
+            color: ColorChoice::Never,
+            context: 1,
+            ..Default::default()
+        });
+        let value: serde_json::Value =
+            serde_json::from_str(&formatter.render_json_results(&[file_match], &stats).unwrap()).unwrap();
+        let lines = &value["files"][0]["lines"];
+        assert_eq!(lines[0]["before"], json!([]));
+        assert_eq!(lines[0]["after"], json!(["two"]));
+        assert_eq!(lines[1]["before"], json!(["two"]));
+        assert_eq!(lines[1]["after"], json!(["four"]));
+    }
+    
+    #[test]
+    fn test_json_output_schema_covers_output() {
+        use crate::{LineMatch, Match};
+        use std::path::PathBuf;
+        use std::time::Duration;
+        
+        let schema = json_output_schema();
+        let file_matches = vec![FileMatch::new(
+            PathBuf::from("a.rs"),
+            vec![LineMatch::new(
+                1,
+                "fn main() {}".to_string(),
+                vec![Match { start: 3, end: 7, text: "main".to_string(), captures: Vec::new() }],
+            )],
+        )];
+        let stats = SearchStats::new(1, 1, 1, 1, Duration::from_millis(5));
+        let formatter = OutputFormatter::new(Cli { color: ColorChoice::Never, ..Default::default() });
+        let output: serde_json::Value =
+            serde_json::from_str(&formatter.render_json_results(&file_matches, &stats).unwrap()).unwrap();
+        
+        // Every key the renderer emits is described by the schema
+        let properties = |def: &str| schema["$defs"][def]["properties"].as_object().unwrap().clone();
+        for key in output["stats"].as_object().unwrap().keys() {
+            assert!(schema["properties"]["stats"]["properties"].get(key).is_some(), "{}", key);
+        }
+        let file = &output["files"][0];
+        assert!(file.as_object().unwrap().keys().all(|key| properties("file").contains_key(key)));
+        assert!(file["lines"][0].as_object().unwrap().keys().all(|key| properties("line").contains_key(key)));
+        assert_eq!(schema["$schema"], "https://json-schema.org/draft/2020-12/schema");
+    }
+    
+    #[test]
+    fn test_checksums() {
+        assert_eq!(line_checksum("hello"), "0x3610A686");
+        
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file.write_all(b"abc").unwrap();
+        assert_eq!(
+            file_checksum(temp_file.path()).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(file_checksum(Path::new("/nonexistent/file")), None);
+    }
+    
+    #[test]
+    fn test_sarif_results() {
+        use crate::{LineMatch, Match};
+        use std::path::PathBuf;
+        
+        let file_matches = vec![FileMatch::new(
+            PathBuf::from("./src/main.rs"),
+            vec![LineMatch::new(
+                4,
+                "\tlet todo = \"é TODO\";".to_string(),
+                vec![Match { start: 16, end: 20, text: "TODO".to_string(), captures: Vec::new() }],
+            )],
+        )];
+        let formatter = OutputFormatter::new(Cli {
+            pattern: Some("TODO".to_string()),
+            output: OutputFormat::Sarif,
+            ..Default::default()
+        });
+        let sarif = formatter.render_sarif_results(&file_matches);
+        
+        // The SARIF 2.1.0 schema's definitions for everything the log contains
+        let schema: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/sarif-schema-2.1.0.json")).unwrap();
+        let validator = jsonschema::JSONSchema::options().should_validate_formats(true).compile(&schema).unwrap();
+        if let Err(errors) = validator.validate(&sarif) {
+            let errors: Vec<String> = errors.map(|error| format!("{} at {}", error, error.instance_path)).collect();
+            panic!("invalid SARIF: {:?}", errors);
+        }
+        
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "code-grep");
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        let result = &results[0];
+        assert_eq!(result["ruleId"], run["tool"]["driver"]["rules"][0]["id"]);
+        assert_eq!(result["message"]["text"], "TODO");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "src/main.rs");
+        assert_eq!(location["region"]["startLine"], 4);
+        // Columns count characters, so the two-byte 'é' counts once
+        assert_eq!(location["region"]["startColumn"], 16);
+        assert_eq!(location["region"]["endColumn"], 20);
+    }
+    
+    /// In-memory sink whose contents stay readable after the formatter takes ownership of it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+    
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    
+    #[test]
+    fn test_color_always_writes_well_formed_ansi() {
+        use crate::{LineMatch, Match};
+        use std::path::PathBuf;
+        use std::time::Duration;
+        
+        let file_matches = vec![FileMatch::new(
+            PathBuf::from("a.rs"),
+            vec![LineMatch::new(
+                3,
+                "fn main() {}".to_string(),
+                vec![Match { start: 3, end: 7, text: "main".to_string(), captures: Vec::new() }],
+            )],
+        )];
+        let stats = SearchStats::new(1, 1, 1, 1, Duration::from_millis(5));
+        
+        let buffer = SharedBuffer::default();
+        let cli = Cli {
+            color: ColorChoice::Always,
+            line_numbers: true,
+            ..Default::default()
+        };
+        let mut formatter = OutputFormatter::with_writer(cli, termcolor::Ansi::new(buffer.clone()));
+        formatter.print_results(&file_matches, &stats).unwrap();
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        
+        let escape = regex::Regex::new(r"\x1b\[[0-9;]*m").unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(escape.replace_all(lines[0], ""), "a.rs");
+        assert_eq!(escape.replace_all(lines[1], ""), "3:fn main() {}");
+        assert!(lines[1].contains("fn \x1b[0m\x1b[1m\x1b[31mmain\x1b[0m() {}"), "{:?}", lines[1]);
+        for line in lines {
+            // Every escape is a complete SGR sequence and colors are reset before the line ends
+            assert_eq!(line.matches('\x1b').count(), escape.find_iter(line).count(), "{:?}", line);
+            if let Some(last) = escape.find_iter(line).last() {
+                assert_eq!(last.as_str(), "\x1b[0m", "{:?}", line);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_split_results_by_file() {
+        use crate::Match;
+        
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let out_dir = temp_dir.path().join("out");
+        let file_matches = vec![
+            FileMatch::new(
+                PathBuf::from("src/lib.rs"),
+                vec![LineMatch::new(
+                    1,
+                    "pub mod cli;".to_string(),
+                    vec![Match { start: 8, end: 11, text: "cli".to_string(), captures: Vec::new() }],
+                )],
+            ),
+            FileMatch::new(PathBuf::from("src/empty.rs"), Vec::new()),
+        ];
+        let stats = SearchStats::new(2, 1, 1, 1, std::time::Duration::from_millis(1));
+        
+        let formatter = OutputFormatter::new(Cli {
+            output: OutputFormat::Json,
+            output_file: Some(out_dir.clone()),
+            split_results_by_file: true,
+            ..Default::default()
+        });
+        let written = formatter.write_split_results(&out_dir, &file_matches, &stats).unwrap();
+        
+        assert_eq!(written, vec![out_dir.join("src/lib.rs.json")]);
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&written[0]).unwrap()).unwrap();
+        assert_eq!(json["files"][0]["path"], "src/lib.rs");
+        assert_eq!(json["files"].as_array().unwrap().len(), 1);
+        assert_eq!(split_result_path(Path::new("/../a/./b.rs")), PathBuf::from("a/b.rs"));
+    }
+    
+    #[test]
+    fn test_only_matching_merges_adjacent_matches() {
+        use crate::{LineMatch, Match};
+        use std::path::PathBuf;
+        use std::time::Duration;
+        
+        let line = "ab-ab  ab";
+        let file_matches = vec![FileMatch::new(
+            PathBuf::from("a.txt"),
+            vec![LineMatch::new(
+                1,
+                line.to_string(),
+                vec![
+                    Match { start: 0, end: 2, text: "ab".to_string(), captures: Vec::new() },
+                    Match { start: 3, end: 5, text: "ab".to_string(), captures: Vec::new() },
+                    Match { start: 7, end: 9, text: "ab".to_string(), captures: Vec::new() },
+                ],
+            )],
+        )];
+        let stats = SearchStats::new(1, 1, 3, 1, Duration::from_millis(1));
+        let output = |merge_adjacent: Option<usize>| {
+            let buffer = SharedBuffer::default();
+            let cli = Cli { only_matching: true, merge_adjacent, rg_compat: true, ..Default::default() };
+            let mut formatter = OutputFormatter::with_writer(cli, NoColor::new(buffer.clone()));
+            formatter.print_results(&file_matches, &stats).unwrap();
+            let bytes = buffer.0.borrow().clone();
+            String::from_utf8(bytes).unwrap()
+        };
+        
+        assert_eq!(output(None), "a.txt\nab\nab\nab\n");
+        assert_eq!(output(Some(1)), "a.txt\nab-ab\nab\n");
+        assert_eq!(output(Some(2)), "a.txt\nab-ab  ab\n");
+    }
+    
+    #[test]
+    fn test_separator_only_between_files() {
+        use crate::{LineMatch, Match};
+        use std::time::Duration;
+        
+        let file_match = |name: &str| {
+            FileMatch::new(
+                PathBuf::from(name),
+                vec![LineMatch::new(1, "x".to_string(), vec![Match { start: 0, end: 1, text: "x".to_string(), captures: Vec::new() }])],
+            )
+        };
+        let file_matches = vec![file_match("a.txt"), file_match("b.txt")];
+        let stats = SearchStats::new(2, 2, 2, 2, Duration::from_millis(1));
+        let output = |separate_files: Option<bool>, separator_char: Option<char>| {
+            let buffer = SharedBuffer::default();
+            let cli = Cli { separate_files, separator_char, rg_compat: true, ..Default::default() };
+            let mut formatter = OutputFormatter::with_writer(cli, NoColor::new(buffer.clone()));
+            formatter.print_results(&file_matches, &stats).unwrap();
+            let bytes = buffer.0.borrow().clone();
+            String::from_utf8(bytes).unwrap()
+        };
+        
+        assert_eq!(output(Some(true), None), "a.txt\nx\n\nb.txt\nx\n");
+        assert_eq!(output(Some(true), Some('═')), "a.txt\nx\n═══\nb.txt\nx\n");
+        assert_eq!(output(Some(false), Some('-')), "a.txt\nx\nb.txt\nx\n");
+    }
+    
+    #[test]
+    fn test_trim_path_prefix() {
+        use crate::{LineMatch, Match};
+        use std::time::Duration;
+        
+        let file_match = |name: &str| {
+            FileMatch::new(
+                PathBuf::from(name),
+                vec![LineMatch::new(2, "x".to_string(), vec![Match { start: 0, end: 1, text: "x".to_string(), captures: Vec::new() }])],
+            )
+        };
+        let file_matches = vec![file_match("/ci/workspace/app/src/main.rs"), file_match("/other/lib.rs")];
+        let stats = SearchStats::new(2, 2, 2, 2, Duration::from_millis(1));
+        let buffer = SharedBuffer::default();
+        let cli = Cli {
+            trim_path_prefix: Some(PathBuf::from("/ci/workspace/app/")),
+            vimgrep: true,
+            ..Default::default()
+        };
+        let mut formatter = OutputFormatter::with_writer(cli, NoColor::new(buffer.clone()));
+        formatter.print_results(&file_matches, &stats).unwrap();
+        let bytes = buffer.0.borrow().clone();
+        
+        assert_eq!(String::from_utf8(bytes).unwrap(), "src/main.rs:2:1:x\n/other/lib.rs:2:1:x\n");
+    }
+    
+    #[test]
+    fn test_capture_groups_highlighted_distinctly() {
+        use std::time::Duration;
+        
+        let matcher = PatternMatcher::Basic(regex::Regex::new(r"(\w+)=(\d+)").unwrap());
+        let line = "set key=42;";
+        let file_matches = vec![FileMatch::new(
+            PathBuf::from("a.txt"),
+            vec![LineMatch::new(1, line.to_string(), matcher.find_matches(line))],
+        )];
+        let stats = SearchStats::new(1, 1, 1, 1, Duration::from_millis(1));
+        let buffer = SharedBuffer::default();
+        let cli = Cli { color: ColorChoice::Always, rg_compat: true, ..Default::default() };
+        let mut formatter = OutputFormatter::with_writer(cli, termcolor::Ansi::new(buffer.clone()));
+        formatter.print_results(&file_matches, &stats).unwrap();
+        let bytes = buffer.0.borrow().clone();
+        let output = String::from_utf8(bytes).unwrap();
+        
+        let line = output.lines().nth(1).unwrap();
+        assert!(
+            line.ends_with("set \x1b[0m\x1b[1m\x1b[36mkey\x1b[0m\x1b[0m\x1b[1m\x1b[31m=\x1b[0m\x1b[0m\x1b[1m\x1b[33m42\x1b[0m;"),
+            "{:?}",
+            line
+        );
+    }
+    
+    #[test]
+    fn test_files_only_footer_goes_to_stderr() {
+        use std::time::Duration;
+        
+        let file_matches = vec![
+            FileMatch::new(
+                PathBuf::from("a.rs"),
+                vec![LineMatch::new(1, "x".to_string(), vec![Match { start: 0, end: 1, text: "x".to_string(), captures: Vec::new() }])],
+            ),
+            FileMatch::new(PathBuf::from("b.rs"), Vec::new()),
+        ];
+        let stats = SearchStats::new(5, 1, 1, 1, Duration::from_millis(1));
+        let output = |no_messages: bool| {
+            let (stdout, stderr) = (SharedBuffer::default(), SharedBuffer::default());
+            let cli = Cli { files_only: true, no_messages, ..Default::default() };
+            let mut formatter = OutputFormatter::with_writers(cli, NoColor::new(stdout.clone()), stderr.clone());
+            formatter.print_results(&file_matches, &stats).unwrap();
+            let (stdout, stderr) = (stdout.0.borrow().clone(), stderr.0.borrow().clone());
+            (String::from_utf8(stdout).unwrap(), String::from_utf8(stderr).unwrap())
+        };
+        
+        assert_eq!(output(false), ("a.rs\n".to_string(), "1 file matched of 5 searched\n".to_string()));
+        assert_eq!(output(true), ("a.rs\n".to_string(), String::new()));
+    }
+    
+    #[test]
+    fn test_max_match_length() {
+        use std::time::Duration;
+        
+        let line = "x = \"aaaaaaaaaa\";";
+        let file_matches = vec![FileMatch::new(
+            PathBuf::from("a.js"),
+            vec![LineMatch::new(1, line.to_string(), vec![Match { start: 5, end: 15, text: "aaaaaaaaaa".to_string(), captures: Vec::new() }])],
+        )];
+        let stats = SearchStats::new(1, 1, 1, 1, Duration::from_millis(1));
+        let output = |cli: Cli| {
+            let buffer = SharedBuffer::default();
+            let mut formatter = OutputFormatter::with_writer(Cli { max_match_length: Some(4), rg_compat: true, ..cli }, NoColor::new(buffer.clone()));
+            formatter.print_results(&file_matches, &stats).unwrap();
+            let bytes = buffer.0.borrow().clone();
+            String::from_utf8(bytes).unwrap()
+        };
+        let marker = truncation_marker();
+        
+        assert_eq!(output(Cli::default()), format!("a.js\nx = \"aaaa{}\";\n", marker));
+        assert_eq!(output(Cli { only_matching: true, ..Default::default() }), format!("a.js\naaaa{}\n", marker));
+        let json: serde_json::Value = serde_json::from_str(&output(Cli { output: OutputFormat::Json, ..Default::default() })).unwrap();
+        let json_match = &json["files"][0]["lines"][0]["matches"][0];
+        assert_eq!(json_match["text"], "aaaaaaaaaa");
+        assert_eq!(json_match["truncated"], true);
+    }
+    
+    #[test]
+    fn test_group_by_extension() {
+        use std::time::Duration;
+        
+        let file_match = |name: &str| {
+            FileMatch::new(
+                PathBuf::from(name),
+                vec![LineMatch::new(1, "x".to_string(), vec![Match { start: 0, end: 1, text: "x".to_string(), captures: Vec::new() }])],
+            )
+        };
+        let file_matches = vec![file_match("b.rs"), file_match("Makefile"), file_match("a.py"), file_match("a.rs")];
+        let stats = SearchStats::new(4, 4, 4, 4, Duration::from_millis(1));
+        let buffer = SharedBuffer::default();
+        let cli = Cli { group_by_extension: true, separate_files: Some(false), rg_compat: true, ..Default::default() };
+        let mut formatter = OutputFormatter::with_writer(cli, NoColor::new(buffer.clone()));
+        formatter.print_results(&file_matches, &stats).unwrap();
+        let bytes = buffer.0.borrow().clone();
+        
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "=== .py files ===\na.py\nx\n\n=== .rs files ===\nb.rs\nx\na.rs\nx\n\n=== (no extension) files ===\nMakefile\nx\n"
+        );
+    }
+    
+    /// Everything `print_results` writes for `cli`, uncolored
+    fn render(cli: Cli, file_matches: &[FileMatch], stats: &SearchStats) -> String {
+        let buffer = SharedBuffer::default();
+        let mut formatter = OutputFormatter::with_writer(cli, NoColor::new(buffer.clone()));
+        formatter.print_results(file_matches, stats).unwrap();
+        let bytes = buffer.0.borrow().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+    
+    #[test]
+    fn test_record_separator_replaces_group_separator() {
+        use std::time::Duration;
+        
+        let source = "a---b1---c---d---e---f1";
+        let line_match = |number: usize, text: &str| {
+            LineMatch::new(number, text.to_string(), vec![Match { start: 1, end: 2, text: "1".to_string(), captures: Vec::new() }])
+        };
+        let file_matches = vec![FileMatch::new(PathBuf::from("a.txt"), vec![line_match(2, "b1"), line_match(6, "f1")])
+            .with_source(std::sync::Arc::from(source))];
+        let stats = SearchStats::new(1, 1, 2, 1, Duration::from_millis(1));
+        let cli = Cli { split_on: Some("---".to_string()), context: 1, line_numbers: true, ..Default::default() };
+        
+        let output = render(cli, &file_matches, &stats);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[..7], ["a.txt", "1-a", "2:b1", "3-c", "---", "5-e", "6:f1"]);
+    }
+    
+    #[test]
+    fn test_todo_fields_in_json() {
+        use crate::{find_in_text, PatternMatcher};
+        use std::time::Duration;
+        
+        let cli = Cli { todos: true, output: OutputFormat::Json, ..Default::default() };
+        let line_matches = find_in_text("// TODO(bob): fix later\n// XXX odd; HACK(ann) fine\n", &PatternMatcher::new(&cli).unwrap(), None);
+        let file_matches = vec![FileMatch::new(PathBuf::from("a.rs"), line_matches)];
+        let stats = SearchStats::new(1, 1, 3, 2, Duration::from_millis(1));
+        
+        let json: serde_json::Value = serde_json::from_str(&render(cli, &file_matches, &stats)).unwrap();
+        let fields: Vec<_> = json["files"][0]["lines"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|line| line["matches"].as_array().unwrap())
+            .map(|m| (m["marker"].clone(), m["author"].clone(), m["note"].clone()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                (json!("TODO"), json!("bob"), json!("fix later")),
+                (json!("XXX"), json!(null), json!("odd;")),
+                (json!("HACK"), json!("ann"), json!("fine")),
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_formats_captured_in_memory() {
+        use std::time::Duration;
+        
+        let file_matches = vec![FileMatch::new(
+            PathBuf::from("src/a.rs"),
+            vec![LineMatch::new(
+                2,
+                r#"let s = "<x>";"#.to_string(),
+                vec![Match { start: 8, end: 13, text: r#""<x>""#.to_string(), captures: Vec::new() }],
+            )],
+        )];
+        let stats = SearchStats::new(3, 1, 1, 1, Duration::from_millis(1));
+        let with_output = |output: OutputFormat| Cli { output, line_numbers: true, ..Default::default() };
+        
+        let text = render(with_output(OutputFormat::Text), &file_matches, &stats);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[..2], ["src/a.rs", r#"2:let s = "<x>";"#]);
+        assert!(lines[2].starts_with("Searched 3 files in "));
+        
+        let json: serde_json::Value = serde_json::from_str(&render(with_output(OutputFormat::Json), &file_matches, &stats)).unwrap();
+        assert_eq!(json["files"][0]["path"], "src/a.rs");
+        assert_eq!(json["files"][0]["lines"][0]["line_number"], 2);
+        assert_eq!(json["files"][0]["lines"][0]["matches"][0]["text"], r#""<x>""#);
+        
+        assert_eq!(
+            render(with_output(OutputFormat::Csv), &file_matches, &stats),
+            "file,line_number,line_text,match_start,match_end,match_text\n\"src/a.rs\",2,\"let s = \"\"<x>\"\";\",8,13,\"\"\"<x>\"\"\"\n"
+        );
+        
+        assert_eq!(
+            render(with_output(OutputFormat::Xml), &file_matches, &stats),
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<search_results>\n",
+                "  <file path=\"src/a.rs\" total_matches=\"1\">\n",
+                "    <line number=\"2\">\n",
+                "      <text>let s = &quot;&lt;x&gt;&quot;;</text>\n",
+                "      <match start=\"8\" end=\"13\">&quot;&lt;x&gt;&quot;</match>\n",
+                "    </line>\n",
+                "  </file>\n",
+                "</search_results>\n",
+            )
+        );
+    }
+    
+    #[cfg(feature = "yaml-output")]
+    #[test]
+    fn test_yaml_output_mirrors_json() {
+        use std::time::Duration;
+        
+        let file_matches = vec![FileMatch::new(
+            PathBuf::from("src/a.rs"),
+            vec![LineMatch::new(2, "let x = 1;".to_string(), vec![Match { start: 4, end: 5, text: "x".to_string(), captures: Vec::new() }])],
+        )];
+        let stats = SearchStats::new(3, 1, 1, 1, Duration::from_millis(1));
+        let with_output = |output: OutputFormat| Cli { output, ..Default::default() };
+        
+        let yaml: serde_json::Value = serde_yaml::from_str(&render(with_output(OutputFormat::Yaml), &file_matches, &stats)).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&render(with_output(OutputFormat::Json), &file_matches, &stats)).unwrap();
+        assert_eq!(yaml, json);
+        assert_eq!(yaml["files"][0]["lines"][0]["matches"][0]["text"], "x");
+    }
+    
+    #[test]
+    fn test_dedupe_lines() {
+        use std::time::Duration;
+        
+        let line = |number: usize, text: &str| {
+            LineMatch::new(number, text.to_string(), vec![Match { start: 0, end: 5, text: "error".to_string(), captures: Vec::new() }])
+        };
+        let file_matches = vec![
+            FileMatch::new(
+                PathBuf::from("a.log"),
+                vec![line(1, "error: disk full"), line(2, "error: disk full"), line(3, "error: timeout"), line(4, "error: disk full")],
+            ),
+            FileMatch::new(PathBuf::from("b.log"), vec![line(7, "error: disk full")]),
+        ];
+        let stats = SearchStats::new(2, 2, 5, 5, Duration::from_millis(1));
+        let cli = Cli { separate_files: Some(false), rg_compat: true, ..Default::default() };
+        
+        assert_eq!(
+            render(Cli { dedupe_lines: true, ..cli.clone() }, &file_matches, &stats),
+            "a.log\n      3 error: disk full\n      1 error: timeout\nb.log\n      1 error: disk full\n"
+        );
+        assert_eq!(
+            render(Cli { dedupe_global: true, line_numbers: true, ..cli }, &file_matches, &stats),
+            "a.log\n      4 1:error: disk full\n      1 3:error: timeout\n"
+        );
+    }
+    
+    #[cfg(feature = "syntax-highlight")]
+    #[test]
+    fn test_color_syntax_keeps_match_emphasis() {
+        use std::time::Duration;
+        
+        let file_matches = vec![FileMatch::new(
+            PathBuf::from("a.rs"),
+            vec![LineMatch::new(1, "fn main() {}".to_string(), vec![Match { start: 3, end: 7, text: "main".to_string(), captures: Vec::new() }])],
+        )];
+        let stats = SearchStats::new(1, 1, 1, 1, Duration::from_millis(1));
+        let buffer = SharedBuffer::default();
+        let cli = Cli { color: ColorChoice::Always, color_syntax: true, rg_compat: true, ..Default::default() };
+        let mut formatter = OutputFormatter::with_writer(cli, termcolor::Ansi::new(buffer.clone()));
+        formatter.print_results(&file_matches, &stats).unwrap();
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        
+        let escape = regex::Regex::new(r"\x1b\[[0-9;]*m").unwrap();
+        let line = output.lines().nth(1).unwrap();
+        assert_eq!(escape.replace_all(line, ""), "fn main() {}");
+        // The keyword gets a syntax color and the match keeps it under bold and underline
+        let before_match = regex::Regex::new(r"((?:\x1b\[[0-9;]*m)+)main").unwrap().captures(line).unwrap();
+        assert!(before_match[1].contains("\x1b[1m") && before_match[1].contains("\x1b[4m"), "{:?}", line);
+        assert!(before_match[1].contains("38;2;"), "{:?}", line);
+        assert!(line.starts_with("\x1b[0m\x1b[38;2;"), "{:?}", line);
+        
+        // A stale span past the end of the line or inside a character is not highlighted
+        let file_matches = vec![FileMatch::new(
+            PathBuf::from("a.rs"),
+            vec![LineMatch::new(
+                1,
+                "let é = 1;".to_string(),
+                vec![
+                    Match { start: 5, end: 6, text: "é".to_string(), captures: Vec::new() },
+                    Match { start: 9, end: 40, text: "1".to_string(), captures: Vec::new() },
+                ],
+            )],
+        )];
+        let buffer = SharedBuffer::default();
+        let cli = Cli { color: ColorChoice::Always, color_syntax: true, rg_compat: true, ..Default::default() };
+        let mut formatter = OutputFormatter::with_writer(cli, termcolor::Ansi::new(buffer.clone()));
+        formatter.print_results(&file_matches, &stats).unwrap();
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let line = output.lines().nth(1).unwrap();
+        assert_eq!(escape.replace_all(line, ""), "let é = 1;");
+        assert!(!line.contains("\x1b[4m"), "{:?}", line);
+    }
+}
@@ -1,15 +1,42 @@
 use crate::CodeGrepResult;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
 use std::path::Path;
 
-#[derive(Debug, Clone)]
+/// Bytes from the start of a file that `detect_language` looks for content markers in
+const LANGUAGE_SNIFF_LEN: u64 = 512;
+
+/// Confidence each kind of evidence adds in `detect_language`; a language can collect all three
+const EXTENSION_CONFIDENCE: f32 = 0.6;
+const SHEBANG_CONFIDENCE: f32 = 0.3;
+const CONTENT_CONFIDENCE: f32 = 0.1;
+
+/// Text near the start of a file that hints at its language
+const CONTENT_MARKERS: [(&str, &str); 10] = [
+    ("<?php", "php"),
+    ("package main", "go"),
+    ("use strict", "perl"),
+    ("use strict", "javascript"),
+    ("fn main(", "rust"),
+    ("import java.", "java"),
+    ("#include <iostream>", "cpp"),
+    ("#include", "c"),
+    ("def __init__(", "python"),
+    ("require_relative", "ruby"),
+];
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ParsedCode {
     pub functions: Vec<FunctionInfo>,
     pub classes: Vec<ClassInfo>,
     pub imports: Vec<ImportInfo>,
     pub comments: Vec<CommentInfo>,
+    pub attributes: Vec<AttributeInfo>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionInfo {
     pub name: String,
     pub start_line: usize,
@@ -17,7 +44,7 @@ pub struct FunctionInfo {
     pub signature: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ClassInfo {
     pub name: String,
     pub start_line: usize,
@@ -25,27 +52,36 @@ pub struct ClassInfo {
     pub methods: Vec<FunctionInfo>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ImportInfo {
     pub line: usize,
     pub module: String,
     pub items: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CommentInfo {
     pub line: usize,
     pub text: String,
     pub comment_type: CommentType,
 }
 
-#[derive(Debug, Clone)]
+/// Rust の属性（`#[derive(Debug)]` なら name は `derive`、args は `Debug`）
+#[derive(Debug, Clone, Serialize)]
+pub struct AttributeInfo {
+    pub line: usize,
+    pub name: String,
+    pub args: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub enum CommentType {
     SingleLine,
     MultiLine,
     Documentation,
 }
 
+#[derive(Default)]
 pub struct CodeParser;
 
 impl CodeParser {
@@ -53,12 +89,49 @@ impl CodeParser {
         Self
     }
     
+    pub fn parse_file(&self, path: &Path) -> CodeGrepResult<ParsedCode> {
+        let content = fs::read_to_string(path)?;
+        self.parse(&content, path)
+    }
+    
+    /// Candidate languages of `path` with a confidence up to 1.0, most likely first. The file
+    /// extension weighs most, then the interpreter of a shebang line, then markers such as
+    /// `<?php` or `package main` in the first 512 bytes. A file that cannot be read is judged
+    /// by its extension alone.
+    pub fn detect_language(&self, path: &Path) -> Vec<(String, f32)> {
+        let mut scores: HashMap<&str, f32> = HashMap::new();
+        if let Some(language) = path.extension().and_then(|e| e.to_str()).and_then(extension_language) {
+            *scores.entry(language).or_default() += EXTENSION_CONFIDENCE;
+        }
+        
+        let mut head = Vec::new();
+        if let Ok(file) = fs::File::open(path) {
+            let _ = file.take(LANGUAGE_SNIFF_LEN).read_to_end(&mut head);
+        }
+        let head = String::from_utf8_lossy(&head);
+        if let Some(language) = head.lines().next().and_then(shebang_language) {
+            *scores.entry(language).or_default() += SHEBANG_CONFIDENCE;
+        }
+        for (marker, language) in CONTENT_MARKERS {
+            if head.contains(marker) {
+                *scores.entry(language).or_default() += CONTENT_CONFIDENCE;
+            }
+        }
+        
+        let mut candidates: Vec<(String, f32)> = scores
+            .into_iter()
+            .map(|(language, score)| (language.to_string(), score.min(1.0)))
+            .collect();
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        candidates
+    }
+    
     pub fn parse(&self, content: &str, path: &Path) -> CodeGrepResult<ParsedCode> {
         let extension = path.extension()
             .and_then(|e| e.to_str())
             .unwrap_or("");
         
-        match extension {
+        let mut parsed = match extension {
             "rs" => self.parse_rust(content),
             "go" => self.parse_go(content),
             "js" | "ts" | "jsx" | "tsx" => self.parse_javascript(content),
@@ -66,7 +139,19 @@ impl CodeParser {
             "java" => self.parse_java(content),
             "c" | "cpp" | "cxx" | "cc" | "h" | "hpp" => self.parse_c_cpp(content),
             _ => self.parse_generic(content),
+        }?;
+        
+        // The per-language parsers only see definition lines; find where each block ends
+        let lines: Vec<&str> = content.lines().collect();
+        let indent_based = extension == "py";
+        for func in &mut parsed.functions {
+            func.end_line = block_end(&lines, func.start_line, indent_based);
+        }
+        for class in &mut parsed.classes {
+            class.end_line = block_end(&lines, class.start_line, indent_based);
         }
+        
+        Ok(parsed)
     }
     
     fn parse_rust(&self, content: &str) -> CodeGrepResult<ParsedCode> {
@@ -75,11 +160,17 @@ impl CodeParser {
         let mut classes = Vec::new();
         let mut imports = Vec::new();
         let mut comments = Vec::new();
+        let mut attributes = Vec::new();
         
         for (line_num, line) in lines.iter().enumerate() {
             let trimmed = line.trim();
             let line_index = line_num + 1;
             
+            // Parse attributes
+            if trimmed.starts_with("#[") || trimmed.starts_with("#![") {
+                attributes.extend(self.extract_rust_attributes(trimmed, line_index));
+            }
+            
             // Parse functions
             if trimmed.starts_with("fn ") || trimmed.contains(" fn ") {
                 if let Some(func_info) = self.extract_rust_function(trimmed, line_index) {
@@ -124,6 +215,7 @@ impl CodeParser {
             classes,
             imports,
             comments,
+            attributes,
         })
     }
     
@@ -180,6 +272,7 @@ impl CodeParser {
             classes,
             imports,
             comments,
+            attributes: Vec::new(),
         })
     }
     
@@ -239,6 +332,7 @@ impl CodeParser {
             classes,
             imports,
             comments,
+            attributes: Vec::new(),
         })
     }
     
@@ -289,6 +383,7 @@ impl CodeParser {
             classes,
             imports,
             comments,
+            attributes: Vec::new(),
         })
     }
     
@@ -306,8 +401,8 @@ impl CodeParser {
         // Generic parser that looks for common patterns
         let lines: Vec<&str> = content.lines().collect();
         let mut functions = Vec::new();
-        let mut classes = Vec::new();
-        let mut imports = Vec::new();
+        let classes = Vec::new();
+        let imports = Vec::new();
         let mut comments = Vec::new();
         
         for (line_num, line) in lines.iter().enumerate() {
@@ -339,6 +434,7 @@ impl CodeParser {
             classes,
             imports,
             comments,
+            attributes: Vec::new(),
         })
     }
     
@@ -377,8 +473,8 @@ impl CodeParser {
     }
     
     fn extract_rust_import(&self, line: &str, line_num: usize) -> Option<ImportInfo> {
-        if line.starts_with("use ") {
-            let module = line[4..].trim_end_matches(';').trim().to_string();
+        if let Some(rest) = line.strip_prefix("use ") {
+            let module = rest.trim_end_matches(';').trim().to_string();
             Some(ImportInfo {
                 line: line_num,
                 module,
@@ -389,6 +485,42 @@ impl CodeParser {
         }
     }
     
+    /// Attributes written on one line, such as `#[derive(Debug)] #[serde(rename = "x")]`.
+    /// Attributes spanning several lines are cut off at the end of the line.
+    fn extract_rust_attributes(&self, line: &str, line_num: usize) -> Vec<AttributeInfo> {
+        let mut attributes = Vec::new();
+        let mut rest = line;
+        while let Some(after) = rest.strip_prefix("#[").or_else(|| rest.strip_prefix("#![")) {
+            // Find the bracket that closes this attribute
+            let mut depth = 1;
+            let end = after
+                .char_indices()
+                .find(|&(_, c)| {
+                    match c {
+                        '[' => depth += 1,
+                        ']' => depth -= 1,
+                        _ => {}
+                    }
+                    depth == 0
+                })
+                .map_or(after.len(), |(index, _)| index);
+            let body = after[..end].trim();
+            
+            let name_end = body.find(['(', '=', ' ']).unwrap_or(body.len());
+            let name = body[..name_end].trim().to_string();
+            let args = body[name_end..].trim();
+            let args = match args.strip_prefix('(') {
+                Some(inner) => inner.strip_suffix(')').unwrap_or(inner),
+                None => args.strip_prefix('=').unwrap_or(args),
+            };
+            if !name.is_empty() {
+                attributes.push(AttributeInfo { line: line_num, name, args: args.trim().to_string() });
+            }
+            rest = after.get(end + 1..).unwrap_or("").trim_start();
+        }
+        attributes
+    }
+    
     // Similar extraction methods for other languages...
     fn extract_go_function(&self, line: &str, line_num: usize) -> Option<FunctionInfo> {
         // Simplified Go function extraction
@@ -428,8 +560,8 @@ impl CodeParser {
     }
     
     fn extract_go_import(&self, line: &str, line_num: usize) -> Option<ImportInfo> {
-        if line.starts_with("import ") {
-            let module = line[7..].trim().trim_matches('"').to_string();
+        if let Some(rest) = line.strip_prefix("import ") {
+            let module = rest.trim().trim_matches('"').to_string();
             Some(ImportInfo {
                 line: line_num,
                 module,
@@ -473,8 +605,8 @@ impl CodeParser {
     }
     
     fn extract_js_import(&self, line: &str, line_num: usize) -> Option<ImportInfo> {
-        if line.starts_with("import ") {
-            let module = line[7..].trim().to_string();
+        if let Some(rest) = line.strip_prefix("import ") {
+            let module = rest.trim().to_string();
             Some(ImportInfo {
                 line: line_num,
                 module,
@@ -517,8 +649,8 @@ impl CodeParser {
     }
     
     fn extract_python_import(&self, line: &str, line_num: usize) -> Option<ImportInfo> {
-        if line.starts_with("import ") {
-            let module = line[7..].trim().to_string();
+        if let Some(rest) = line.strip_prefix("import ") {
+            let module = rest.trim().to_string();
             Some(ImportInfo {
                 line: line_num,
                 module,
@@ -545,6 +677,81 @@ impl CodeParser {
     }
 }
 
+/// Language of a file extension, as used for parsing and for labelling code in the output
+pub fn extension_language(extension: &str) -> Option<&'static str> {
+    let language = match extension {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "jsx" | "mjs" | "cjs" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "go" => "go",
+        "java" => "java",
+        "c" | "h" => "c",
+        "cpp" | "cxx" | "cc" | "hpp" => "cpp",
+        "rb" => "ruby",
+        "sh" | "bash" => "bash",
+        "php" => "php",
+        "pl" | "pm" => "perl",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Language of the interpreter a `#!` line runs, e.g. `#!/usr/bin/env python3`
+fn shebang_language(first_line: &str) -> Option<&'static str> {
+    let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    // python3.12 and python run the same language
+    let language = match program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "python" => "python",
+        "node" | "deno" => "javascript",
+        "sh" | "bash" | "zsh" | "dash" => "bash",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Last line (1-based) of the block starting at `start_line`, by indentation or brace balance
+fn block_end(lines: &[&str], start_line: usize, indent_based: bool) -> usize {
+    let start = start_line - 1;
+    
+    if indent_based {
+        let indent = |line: &str| line.len() - line.trim_start().len();
+        let base_indent = indent(lines[start]);
+        let mut end = start;
+        for (index, line) in lines.iter().enumerate().skip(start + 1) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if indent(line) <= base_indent {
+                break;
+            }
+            end = index;
+        }
+        return end + 1;
+    }
+    
+    let mut depth = 0i32;
+    let mut opened = false;
+    for (index, line) in lines.iter().enumerate().skip(start) {
+        let opens = line.matches('{').count() as i32;
+        depth += opens - line.matches('}').count() as i32;
+        opened |= opens > 0;
+        if opened && depth <= 0 {
+            return index + 1;
+        }
+    }
+    
+    // Declarations without a body end where they start
+    if opened { lines.len() } else { start_line }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -571,4 +778,86 @@ mod tests {
         assert_eq!(parsed.functions.len(), 1);
         assert_eq!(parsed.functions[0].name, "hello_world");
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_parsed_code_json() {
+        let parser = CodeParser::new();
+        let content = "use std::fs;\n\nfn main() {\n}\n\npub fn helper(x: u32) -> u32 {\n    x\n}";
+        let path = PathBuf::from("lib.rs");
+        
+        let parsed = parser.parse(content, &path).unwrap();
+        let json = serde_json::to_value(&parsed).unwrap();
+        
+        let names: Vec<_> = json["functions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["main", "helper"]);
+        assert_eq!(json["functions"][1]["start_line"], 6);
+        assert_eq!(json["imports"][0]["module"], "std::fs");
+    }
+    
+    #[test]
+    fn test_function_end_lines() {
+        let parser = CodeParser::new();
+        
+        let content = "fn main() {\n    if true {\n    }\n}\n\nfn helper() {}";
+        let parsed = parser.parse(content, Path::new("test.rs")).unwrap();
+        let ranges: Vec<_> = parsed.functions.iter().map(|f| (f.start_line, f.end_line)).collect();
+        assert_eq!(ranges, vec![(1, 4), (6, 6)]);
+        
+        let content = "def outer():\n    x = 1\n\n    return x\n\nprint(outer())";
+        let parsed = parser.parse(content, Path::new("test.py")).unwrap();
+        assert_eq!((parsed.functions[0].start_line, parsed.functions[0].end_line), (1, 4));
+    }
+    
+    #[test]
+    fn test_detect_language() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let parser = CodeParser::new();
+        let write = |name: &str, content: &str| {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            path
+        };
+        
+        let script = write("deploy", "#!/usr/bin/env python3\nimport sys\n");
+        assert_eq!(parser.detect_language(&script), vec![("python".to_string(), SHEBANG_CONFIDENCE)]);
+        
+        let main_go = write("main.go", "package main\n\nfunc main() {}\n");
+        assert_eq!(parser.detect_language(&main_go)[0], ("go".to_string(), EXTENSION_CONFIDENCE + CONTENT_CONFIDENCE));
+        
+        // `use strict` could be either, so both are listed behind the shebang's pick
+        let tool = write("tool", "#!/usr/bin/perl -w\nuse strict;\n");
+        let languages: Vec<String> = parser.detect_language(&tool).into_iter().map(|(language, _)| language).collect();
+        assert_eq!(languages, ["perl", "javascript"]);
+        
+        assert_eq!(parser.detect_language(Path::new("missing.rb")), vec![("ruby".to_string(), EXTENSION_CONFIDENCE)]);
+        assert!(parser.detect_language(&write("notes", "just text")).is_empty());
+    }
+    
+    #[test]
+    fn test_rust_attributes() {
+        let parser = CodeParser::new();
+        let content = "#![allow(dead_code)]\n#[derive(Debug, Clone)] #[serde(rename = \"x\")]\nstruct A;\n\n#[tokio::main]\nasync fn main() {}\n#[doc = \"text\"]";
+        let parsed = parser.parse(content, Path::new("lib.rs")).unwrap();
+        
+        let attributes: Vec<_> = parsed
+            .attributes
+            .iter()
+            .map(|attr| (attr.line, attr.name.as_str(), attr.args.as_str()))
+            .collect();
+        assert_eq!(
+            attributes,
+            vec![
+                (1, "allow", "dead_code"),
+                (2, "derive", "Debug, Clone"),
+                (2, "serde", "rename = \"x\""),
+                (5, "tokio::main", ""),
+                (7, "doc", "\"text\""),
+            ]
+        );
+    }
+}
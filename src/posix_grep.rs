@@ -0,0 +1,201 @@
+use crate::{Cli, CodeGrepError, CodeGrepResult, FileWalker, SearchEngine};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Bytes checked for a NUL to decide that a file is binary, as grep does
+const BINARY_CHECK_LEN: usize = 8192;
+
+/// Name shown for standard input in `file:` prefixes
+const STDIN_LABEL: &str = "(standard input)";
+
+/// Options of `cg grep`, named after the POSIX grep flags `-c`, `-n`, `-v`, `-q` and `-r`
+#[derive(Debug, Clone, Default)]
+pub struct PosixGrepOptions {
+    pub pattern: String,
+    pub files: Vec<PathBuf>,
+    pub count: bool,
+    pub line_number: bool,
+    pub invert_match: bool,
+    pub quiet: bool,
+    pub recursive: bool,
+}
+
+/// `cg grep`: behaves like POSIX grep with an extended regex, so `grep` can be aliased to it.
+///
+/// Unlike a normal search, files named on the command line are read as they are: no
+/// .gitignore, hidden-file, binary or file type filtering, and no summary line. Lines are
+/// written as `[file:][line:]text`, with the file prefix only when several files (or `-r`)
+/// are searched. Without files standard input is read; `-r` without files searches `.`.
+/// Each file's text is matched by a `SearchEngine`, and `-v` selects the lines it did not match.
+/// Returns whether any line was selected. Unreadable files are reported on `err` as grep does
+/// and turn into an error at the end, except with `-q` once something matched.
+pub fn posix_grep(
+    options: &PosixGrepOptions,
+    stdin: &mut dyn Read,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> CodeGrepResult<bool> {
+    let engine = SearchEngine::new(Cli {
+        pattern: Some(options.pattern.clone()),
+        regex: true,
+        case_sensitive: true,
+        ..Default::default()
+    })?;
+
+    let mut failures = 0;
+    let files = grep_targets(options, err, &mut failures)?;
+    let with_file_names = options.recursive || files.len() > 1;
+    let mut selected_any = false;
+
+    for file in &files {
+        let (label, content) = match file {
+            None => {
+                let mut content = Vec::new();
+                stdin.read_to_end(&mut content)?;
+                (STDIN_LABEL.to_string(), content)
+            }
+            Some(path) => match fs::read(path) {
+                Ok(content) => (path.display().to_string(), content),
+                Err(e) => {
+                    writeln!(err, "cg grep: {}: {}", path.display(), e)?;
+                    failures += 1;
+                    continue;
+                }
+            },
+        };
+
+        let binary = content[..content.len().min(BINARY_CHECK_LEN)].contains(&0);
+        let text = String::from_utf8_lossy(&content).into_owned();
+        let file_match = engine.search_text(Path::new(&label), text.clone())?;
+        let matched: HashSet<usize> = file_match.line_matches.iter().map(|line_match| line_match.line_number).collect();
+        let prefix = if with_file_names { format!("{}:", label) } else { String::new() };
+        let mut selected = 0;
+
+        for (index, line) in text.lines().enumerate() {
+            if matched.contains(&(index + 1)) == options.invert_match {
+                continue;
+            }
+            selected += 1;
+            if options.quiet {
+                return Ok(true);
+            }
+            if options.count || binary {
+                continue;
+            }
+            if options.line_number {
+                writeln!(out, "{}{}:{}", prefix, index + 1, line)?;
+            } else {
+                writeln!(out, "{}{}", prefix, line)?;
+            }
+        }
+
+        if options.count {
+            writeln!(out, "{}{}", prefix, selected)?;
+        } else if binary && selected > 0 {
+            writeln!(out, "Binary file {} matches", label)?;
+        }
+        selected_any |= selected > 0;
+    }
+
+    if failures > 0 {
+        return Err(CodeGrepError::Search(format!(
+            "{} file{} could not be searched",
+            failures,
+            if failures == 1 { "" } else { "s" }
+        )));
+    }
+    Ok(selected_any)
+}
+
+/// Files to search in order; `None` stands for standard input. Directories are walked with `-r`
+/// and reported as errors otherwise.
+fn grep_targets(
+    options: &PosixGrepOptions,
+    err: &mut dyn Write,
+    failures: &mut usize,
+) -> CodeGrepResult<Vec<Option<PathBuf>>> {
+    let mut roots = options.files.clone();
+    if roots.is_empty() {
+        if !options.recursive {
+            return Ok(vec![None]);
+        }
+        roots.push(PathBuf::from("."));
+    }
+
+    let mut targets = Vec::new();
+    for root in roots {
+        if root == Path::new("-") {
+            targets.push(None);
+        } else if !root.is_dir() {
+            targets.push(Some(root));
+        } else if options.recursive {
+            let walk_cli = Cli {
+                paths: vec![root],
+                hidden: true,
+                respect_gitignore: false,
+                ..Default::default()
+            };
+            let mut files: Vec<PathBuf> = FileWalker::new(&walk_cli).walk().into_iter().collect::<CodeGrepResult<_>>()?;
+            files.sort();
+            targets.extend(files.into_iter().map(Some));
+        } else {
+            writeln!(err, "cg grep: {}: Is a directory", root.display())?;
+            *failures += 1;
+        }
+    }
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grep(options: &PosixGrepOptions, stdin: &str) -> (CodeGrepResult<bool>, String, String) {
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let result = posix_grep(options, &mut stdin.as_bytes(), &mut out, &mut err);
+        (result, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+    }
+
+    #[test]
+    fn test_posix_grep_flags() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let a = temp_dir.path().join("a.txt");
+        let b = temp_dir.path().join("sub/b.txt");
+        fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        fs::write(&a, "foo\nbar\nfood\n").unwrap();
+        fs::write(&b, "Foo\nfoo\n").unwrap();
+        let options = |pattern: &str, files: Vec<PathBuf>| PosixGrepOptions {
+            pattern: pattern.to_string(),
+            files,
+            ..Default::default()
+        };
+
+        let (result, out, _) = grep(&PosixGrepOptions { line_number: true, ..options("fo+", vec![a.clone()]) }, "");
+        assert!(result.unwrap());
+        assert_eq!(out, "1:foo\n3:food\n");
+
+        let (_, out, _) = grep(&PosixGrepOptions { invert_match: true, ..options("foo", vec![]) }, "foo\nbaz\n");
+        assert_eq!(out, "baz\n");
+
+        let (_, out, _) = grep(&PosixGrepOptions { count: true, ..options("foo", vec![a.clone(), b.clone()]) }, "");
+        assert_eq!(out, format!("{}:2\n{}:1\n", a.display(), b.display()));
+
+        let (result, out, _) = grep(&PosixGrepOptions { quiet: true, ..options("bar", vec![a.clone()]) }, "");
+        assert!(result.unwrap());
+        assert_eq!(out, "");
+
+        let (_, out, _) = grep(&PosixGrepOptions { recursive: true, ..options("^Foo", vec![temp_dir.path().to_path_buf()]) }, "");
+        assert_eq!(out, format!("{}:Foo\n", b.display()));
+
+        let (result, out, err) = grep(&options("foo", vec![temp_dir.path().to_path_buf()]), "");
+        assert!(result.is_err());
+        assert_eq!(out, "");
+        assert!(err.ends_with("Is a directory\n"));
+
+        let (result, _, _) = grep(&options("nothing", vec![a]), "");
+        assert!(!result.unwrap());
+    }
+}
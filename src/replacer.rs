@@ -1,11 +1,45 @@
-use crate::{Cli, CodeGrepResult, FileMatch, PatternMatcher};
+use crate::{strip_bom, Cli, CodeGrepError, CodeGrepResult, FileMatch, Match, PatternMatcher, UTF8_BOM};
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, BufRead, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest a single `--replace-cmd` invocation may run before it is killed
+const REPLACE_CMD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Files larger than this are rewritten line by line instead of being held in memory with their new content
+const STREAMING_REPLACE_THRESHOLD: u64 = 16 * 1024 * 1024;
 
 pub struct Replacer {
     pattern_matcher: PatternMatcher,
     replacement: String,
     cli: Cli,
+    streaming_threshold: u64,
+}
+
+/// What `batch_replace_files` changed (or, without `--write`, would change)
+#[derive(Debug, Clone, Default)]
+pub struct BatchReplacement {
+    /// Files replaced in memory
+    pub results: Vec<ReplacementResult>,
+    /// Files too large to hold in memory, rewritten by streaming, with the replacements made in each
+    pub streamed: Vec<(PathBuf, usize)>,
+}
+
+impl BatchReplacement {
+    /// Number of files with at least one replacement
+    pub fn files_changed(&self) -> usize {
+        self.results.iter().filter(|result| result.replacements_made > 0).count()
+            + self.streamed.iter().filter(|(_, replacements)| *replacements > 0).count()
+    }
+    
+    pub fn total_replacements(&self) -> usize {
+        self.results.iter().map(|result| result.replacements_made).sum::<usize>()
+            + self.streamed.iter().map(|(_, replacements)| replacements).sum::<usize>()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -23,53 +57,40 @@ impl Replacer {
             pattern_matcher,
             replacement,
             cli,
+            streaming_threshold: STREAMING_REPLACE_THRESHOLD,
         }
     }
     
     pub fn replace_in_file(&self, file_match: &FileMatch) -> CodeGrepResult<Option<ReplacementResult>> {
         let original_content = fs::read_to_string(&file_match.path)?;
-        let mut new_content;
         let mut replacements_made = 0;
         let mut lines_affected = Vec::new();
         
-        // Process line by line to maintain line structure
-        let lines: Vec<&str> = original_content.lines().collect();
-        let mut new_lines = Vec::new();
+        // Process line by line to maintain line structure; line numbers from the search exclude the BOM.
+        // Line endings (`\n` or `\r\n`, and whether the last line has one) are kept byte for byte.
+        let content = strip_bom(&original_content);
+        let has_bom = content.len() != original_content.len();
+        let mut new_content = String::with_capacity(content.len());
+        if has_bom && self.cli.keep_bom {
+            new_content.push(UTF8_BOM);
+        }
+        let scope = LineScope { range: self.cli.effective_line_range(content) };
         
-        for (line_num, line) in lines.iter().enumerate() {
+        for (line_num, raw_line) in content.split_inclusive('\n').enumerate() {
             let line_index = line_num + 1;
-            let matches = self.pattern_matcher.find_matches(line);
-            
-            if !matches.is_empty() {
-                // Perform replacements in this line
-                let mut new_line = line.to_string();
-                let mut offset = 0i32;
-                
-                for match_info in &matches {
-                    let start = (match_info.start as i32 + offset) as usize;
-                    let end = (match_info.end as i32 + offset) as usize;
-                    
-                    // Handle regex capture groups if using regex
-                    let replacement_text = self.process_replacement(&match_info.text, line);
-                    
-                    // Replace the match
-                    new_line.replace_range(start..end, &replacement_text);
-                    
-                    // Update offset for subsequent replacements in the same line
-                    offset += replacement_text.len() as i32 - match_info.text.len() as i32;
-                    
-                    replacements_made += 1;
+            let (line, ending) = split_line_ending(raw_line);
+            match self.replace_line(file_match, line_index, line, &scope) {
+                Some((new_line, line_replacements)) => {
+                    replacements_made += line_replacements;
+                    lines_affected.push(line_index);
+                    new_content.push_str(&new_line);
+                    new_content.push_str(ending);
                 }
-                
-                lines_affected.push(line_index);
-                new_lines.push(new_line);
-            } else {
-                new_lines.push(line.to_string());
+                None => new_content.push_str(raw_line),
             }
         }
         
         if replacements_made > 0 {
-            new_content = new_lines.join("\n");
             
             Ok(Some(ReplacementResult {
                 file_path: file_match.path.display().to_string(),
@@ -83,6 +104,147 @@ impl Replacer {
         }
     }
     
+    /// Whether `file_match` is rewritten by `stream_replace_file` instead of being held in memory.
+    /// Only plain in-place writes of files above `STREAMING_REPLACE_THRESHOLD` stream; previews,
+    /// prompts, patches and idempotency checks need the full old and new content.
+    pub fn streams_file(&self, file_match: &FileMatch) -> bool {
+        let cli = &self.cli;
+        cli.writes_in_place()
+            && !(cli.interactive || cli.preview || cli.count_replacements || cli.output_as_patch || cli.verify_idempotent)
+            && cli.tail.is_none()
+            && fs::metadata(&file_match.path).is_ok_and(|metadata| metadata.len() > self.streaming_threshold)
+    }
+    
+    /// Rewrites the file line by line through a temporary file next to it, so memory use here
+    /// follows the longest line rather than the file size (the search that produced `file_match`
+    /// still read the whole file, and `--replace-in-place-backup` copies it). Line endings are kept as they are. The file is only replaced
+    /// when something changed; returns the number of replacements made.
+    pub fn stream_replace_file(&self, file_match: &FileMatch) -> CodeGrepResult<usize> {
+        let path = file_match.path.as_path();
+        let mut reader = io::BufReader::new(fs::File::open(path)?);
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let mut temp_file = tempfile::NamedTempFile::new_in(dir)?;
+        // --tail is never streamed, so the line count is not needed
+        let scope = LineScope { range: self.cli.effective_line_range("") };
+        let mut replacements_made = 0;
+        
+        {
+            let mut writer = io::BufWriter::new(temp_file.as_file_mut());
+            let mut buffer = String::new();
+            let mut line_index = 0;
+            while reader.read_line(&mut buffer)? > 0 {
+                line_index += 1;
+                let mut line = buffer.as_str();
+                if line_index == 1 {
+                    let content = strip_bom(line);
+                    if content.len() != line.len() && self.cli.keep_bom {
+                        write!(writer, "{}", UTF8_BOM)?;
+                    }
+                    line = content;
+                }
+                let (text, ending) = split_line_ending(line);
+                
+                match self.replace_line(file_match, line_index, text, &scope) {
+                    Some((new_line, line_replacements)) => {
+                        replacements_made += line_replacements;
+                        writer.write_all(new_line.as_bytes())?;
+                        writer.write_all(ending.as_bytes())?;
+                    }
+                    None => writer.write_all(line.as_bytes())?,
+                }
+                buffer.clear();
+            }
+            writer.flush()?;
+        }
+        
+        if replacements_made == 0 {
+            return Ok(0);
+        }
+        temp_file.as_file().sync_all()?;
+        fs::set_permissions(temp_file.path(), fs::metadata(path)?.permissions())?;
+        match self.cli.replace_in_place_backup {
+            Some(ref extension) => persist_with_backup(temp_file, path, extension)?,
+            None => {
+                temp_file.persist(path).map_err(|e| e.error)?;
+            }
+        }
+        Ok(replacements_made)
+    }
+    
+    /// Replaces the matches on one line, returning the new line and the number of replacements,
+    /// or `None` when the line is left as it is
+    fn replace_line(
+        &self,
+        file_match: &FileMatch,
+        line_index: usize,
+        line: &str,
+        scope: &LineScope,
+    ) -> Option<(String, usize)> {
+        // Only touch lines that the search actually reported; its line numbers are those of the
+        // file under every filter (`--comments-only`, `--in-function`, `--in-class`, `--line-regex`, ...)
+        let eligible = scope.contains(line_index)
+            && file_match.line_matches.iter().any(|lm| lm.line_number == line_index);
+        if !eligible {
+            return None;
+        }
+        
+        // Perform replacements in this line
+        let mut new_line = line.to_string();
+        let mut offset = 0i32;
+        let mut line_replacements = 0;
+        
+        for match_info in &self.pattern_matcher.find_matches(line) {
+            let start = (match_info.start as i32 + offset) as usize;
+            let end = (match_info.end as i32 + offset) as usize;
+            
+            let replacement_text = if let Some(ref command) = self.cli.replace_cmd {
+                // A failing command leaves this match as it is
+                match self.run_replace_cmd(command, file_match, line_index, line, match_info) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        eprintln!(
+                            "Warning: {}:{}: --replace-cmd failed for '{}': {}",
+                            file_match.path.display(),
+                            line_index,
+                            match_info.text,
+                            e
+                        );
+                        continue;
+                    }
+                }
+            } else {
+                // Handle regex capture groups if using regex
+                self.process_replacement(match_info.as_str(line), line)
+            };
+            
+            // Replace the match
+            new_line.replace_range(start..end, &replacement_text);
+            
+            // Update offset for subsequent replacements in the same line
+            offset += replacement_text.len() as i32 - match_info.len() as i32;
+            
+            line_replacements += 1;
+        }
+        
+        (line_replacements > 0).then_some((new_line, line_replacements))
+    }
+    
+    /// Lines (1-based) where the pattern still matches after the replacement,
+    /// meaning a second run would change them again
+    pub fn non_idempotent_lines(&self, result: &ReplacementResult) -> Vec<usize> {
+        let new_lines: Vec<&str> = result.new_content.lines().collect();
+        
+        result.lines_affected
+            .iter()
+            .copied()
+            .filter(|&line_index| {
+                new_lines
+                    .get(line_index - 1)
+                    .is_some_and(|line| self.pattern_matcher.is_match(line))
+            })
+            .collect()
+    }
+    
     pub fn preview_replacement(&self, result: &ReplacementResult) -> String {
         let mut output = String::new();
         
@@ -107,55 +269,254 @@ impl Replacer {
         output
     }
     
+    /// Previews for all results, truncated to `--preview-limit` files when set
+    pub fn preview_replacements(&self, results: &[ReplacementResult]) -> String {
+        let limit = self.cli.preview_limit.unwrap_or(results.len());
+        let mut output = String::new();
+        
+        for result in results.iter().take(limit) {
+            output.push_str(&self.preview_replacement(result));
+            output.push('\n');
+        }
+        
+        if results.len() > limit {
+            output.push_str(&format!("... and {} more files\n", results.len() - limit));
+        }
+        
+        output
+    }
+    
     pub fn write_replacement(&self, result: &ReplacementResult) -> CodeGrepResult<()> {
-        fs::write(&result.file_path, &result.new_content)?;
-        Ok(())
+        match self.cli.replace_in_place_backup {
+            Some(ref extension) => write_with_backup(Path::new(&result.file_path), &result.new_content, extension),
+            None => {
+                fs::write(&result.file_path, &result.new_content)?;
+                Ok(())
+            }
+        }
     }
     
     pub fn interactive_replacement(&self, results: &[ReplacementResult]) -> CodeGrepResult<Vec<ReplacementResult>> {
-        let mut confirmed_results = Vec::new();
+        let stdin = io::stdin();
+        self.interactive_replacement_with(results, &mut stdin.lock(), &mut io::stdout())
+    }
+    
+    fn interactive_replacement_with<R: BufRead, W: Write>(
+        &self,
+        results: &[ReplacementResult],
+        input_reader: &mut R,
+        out: &mut W,
+    ) -> CodeGrepResult<Vec<ReplacementResult>> {
+        // Confirmed results with their index, so `u` can return to the last one
+        let mut confirmed: Vec<(usize, ReplacementResult)> = Vec::new();
+        let into_results = |confirmed: Vec<(usize, ReplacementResult)>| confirmed.into_iter().map(|(_, result)| result).collect();
+        
+        // Show the whole scope up front so large batches hold no surprises
+        if !results.is_empty() {
+            let total_replacements: usize = results.iter().map(|r| r.replacements_made).sum();
+            writeln!(
+                out,
+                "About to review {} replacement{} across {} file{}",
+                total_replacements,
+                if total_replacements == 1 { "" } else { "s" },
+                results.len(),
+                if results.len() == 1 { "" } else { "s" }
+            )?;
+        }
         
-        for result in results {
-            println!("{}", self.preview_replacement(result));
+        let mut cursor = 0;
+        while let Some(result) = results.get(cursor) {
+            writeln!(out, "{}", self.preview_replacement(result))?;
             
             loop {
-                print!("Apply this replacement? [y/n/a/q]: ");
-                io::stdout().flush().unwrap();
+                write!(out, "Apply this replacement? [y/n/a/d/u/q]: ")?;
+                out.flush()?;
                 
                 let mut input = String::new();
-                io::stdin().read_line(&mut input)?;
+                if input_reader.read_line(&mut input)? == 0 {
+                    // End of input: treat like quit
+                    return Ok(into_results(confirmed));
+                }
                 let input = input.trim().to_lowercase();
                 
                 match input.as_str() {
                     "y" | "yes" => {
-                        confirmed_results.push(result.clone());
+                        confirmed.push((cursor, result.clone()));
+                        cursor += 1;
                         break;
                     }
                     "n" | "no" => {
-                        println!("Skipped.");
+                        writeln!(out, "Skipped.")?;
+                        cursor += 1;
                         break;
                     }
                     "a" | "all" => {
                         // Apply this and all remaining
-                        confirmed_results.push(result.clone());
-                        for remaining_result in &results[confirmed_results.len()..] {
-                            confirmed_results.push(remaining_result.clone());
+                        confirmed.extend(results[cursor..].iter().cloned().enumerate().map(|(i, r)| (cursor + i, r)));
+                        return Ok(into_results(confirmed));
+                    }
+                    "d" | "diff" => {
+                        writeln!(out, "{}", self.unified_diff(result))?;
+                        continue;
+                    }
+                    "u" | "undo" => {
+                        // Take back the last confirmation and ask about that file again
+                        match confirmed.pop() {
+                            Some((index, undone)) => {
+                                writeln!(out, "Undid {}.", undone.file_path)?;
+                                cursor = index;
+                                break;
+                            }
+                            None => {
+                                writeln!(out, "Nothing to undo.")?;
+                                continue;
+                            }
                         }
-                        return Ok(confirmed_results);
                     }
                     "q" | "quit" => {
-                        println!("Aborted.");
-                        return Ok(confirmed_results);
+                        writeln!(out, "Aborted.")?;
+                        return Ok(into_results(confirmed));
                     }
                     _ => {
-                        println!("Please enter y/n/a/q");
+                        writeln!(out, "Please enter y/n/a/d/u/q")?;
                         continue;
                     }
                 }
             }
         }
         
-        Ok(confirmed_results)
+        Ok(into_results(confirmed))
+    }
+    
+    /// Unified diff of the whole file with three lines of context around each change
+    pub fn unified_diff(&self, result: &ReplacementResult) -> String {
+        const CONTEXT: usize = 3;
+        
+        let original_lines: Vec<&str> = result.original_content.lines().collect();
+        let new_lines: Vec<&str> = result.new_content.lines().collect();
+        
+        let path = patch_path(&result.file_path);
+        let mut output = String::new();
+        output.push_str(&format!("--- a/{}\n", path));
+        output.push_str(&format!("+++ b/{}\n", path));
+        
+        // Replacements that introduce newlines shift every following line, so show one hunk
+        if original_lines.len() != new_lines.len() {
+            output.push_str(&format!("@@ -1,{} +1,{} @@\n", original_lines.len(), new_lines.len()));
+            for line in &original_lines {
+                output.push_str(&format!("-{}\n", line));
+            }
+            for line in &new_lines {
+                output.push_str(&format!("+{}\n", line));
+            }
+            return output;
+        }
+        
+        // Group changed lines whose context windows touch into hunks (0-based, inclusive)
+        let mut hunks: Vec<(usize, usize)> = Vec::new();
+        for &line_num in &result.lines_affected {
+            let start = (line_num - 1).saturating_sub(CONTEXT);
+            let end = (line_num - 1 + CONTEXT).min(original_lines.len().saturating_sub(1));
+            match hunks.last_mut() {
+                Some(last) if start <= last.1 + 1 => last.1 = end,
+                _ => hunks.push((start, end)),
+            }
+        }
+        
+        for (start, end) in hunks {
+            let len = end - start + 1;
+            output.push_str(&format!("@@ -{},{} +{},{} @@\n", start + 1, len, start + 1, len));
+            for index in start..=end {
+                if original_lines[index] == new_lines[index] {
+                    output.push_str(&format!(" {}\n", original_lines[index]));
+                } else {
+                    output.push_str(&format!("-{}\n", original_lines[index]));
+                    output.push_str(&format!("+{}\n", new_lines[index]));
+                }
+            }
+        }
+        
+        output
+    }
+    
+    /// `--output-as-patch`: the unified diffs of all results, one after another, as a single
+    /// patch that applies with `patch -p1` or `git apply`
+    pub fn as_unified_patch(&self, results: &[ReplacementResult]) -> String {
+        results.iter().map(|result| self.unified_diff(result)).collect()
+    }
+    
+    /// Run `--replace-cmd` for one match: the matched text goes to stdin, captures and position to
+    /// `CG_*` environment variables, and stdout (minus a trailing newline) becomes the replacement
+    fn run_replace_cmd(
+        &self,
+        command: &str,
+        file_match: &FileMatch,
+        line_index: usize,
+        line: &str,
+        match_info: &Match,
+    ) -> CodeGrepResult<String> {
+        let mut process = if cfg!(windows) {
+            let mut process = Command::new("cmd");
+            process.arg("/C").arg(command);
+            process
+        } else {
+            let mut process = Command::new("sh");
+            process.arg("-c").arg(command);
+            process
+        };
+        
+        process
+            .env("CG_0", &match_info.text)
+            .env("CG_LINE", line_index.to_string())
+            .env("CG_FILE", &file_match.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        for (index, group) in self.pattern_matcher.capture_groups(line, match_info.start).iter().enumerate() {
+            process.env(format!("CG_{}", index + 1), group.as_deref().unwrap_or(""));
+        }
+        
+        let mut child = process.spawn()?;
+        
+        // Read stdout on another thread so a large output cannot block the child
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let reader = thread::spawn(move || {
+            let mut output = Vec::new();
+            stdout.read_to_end(&mut output).map(|_| output)
+        });
+        
+        if let Some(mut stdin) = child.stdin.take() {
+            // The command may exit without reading its input
+            let _ = stdin.write_all(match_info.text.as_bytes());
+        }
+        
+        let deadline = Instant::now() + REPLACE_CMD_TIMEOUT;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(CodeGrepError::Search(format!(
+                    "command timed out after {}s",
+                    REPLACE_CMD_TIMEOUT.as_secs()
+                )));
+            }
+            thread::sleep(Duration::from_millis(5));
+        };
+        
+        let output = reader
+            .join()
+            .map_err(|_| CodeGrepError::Search("failed to read command output".to_string()))??;
+        if !status.success() {
+            return Err(CodeGrepError::Search(format!("command exited with {}", status)));
+        }
+        
+        let output = String::from_utf8(output)
+            .map_err(|_| CodeGrepError::Search("command output is not valid UTF-8".to_string()))?;
+        let output = output.strip_suffix('\n').map(|o| o.strip_suffix('\r').unwrap_or(o)).unwrap_or(&output);
+        Ok(output.to_string())
     }
     
     fn process_replacement(&self, matched_text: &str, _full_line: &str) -> String {
@@ -171,68 +532,233 @@ impl Replacer {
         // For regex replacements, we'd need to handle capture groups here
         // This is a simplified implementation
         
+        if self.cli.preserve_case {
+            replacement = apply_case(matched_text, &replacement);
+        }
+        
         replacement
     }
 }
 
+/// Path as it appears after `a/` and `b/` in diff headers: no leading `./` or `/`,
+/// so `patch -p1` finds the file relative to the current directory
+fn patch_path(file_path: &str) -> &str {
+    let mut path = file_path;
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+    path.trim_start_matches('/')
+}
+
+/// Splits a line read with its terminator into the text and the `\n` / `\r\n` ending (if any)
+fn split_line_ending(line: &str) -> (&str, &str) {
+    let text = line.strip_suffix('\n').unwrap_or(line);
+    let text = text.strip_suffix('\r').unwrap_or(text);
+    line.split_at(text.len())
+}
+
+/// Lines a replacement may touch within one file
+struct LineScope {
+    /// `--range`, `--head` and `--tail`
+    range: Option<RangeInclusive<usize>>,
+}
+
+impl LineScope {
+    fn contains(&self, line_index: usize) -> bool {
+        self.range.as_ref().is_none_or(|range| range.contains(&line_index))
+    }
+}
+
+/// Replace `path` with `content` via a temporary file renamed over it, after copying the
+/// original to `<path>.<extension>`. A failed backup leaves the original untouched; a failed
+/// rename restores the original from the backup.
+fn write_with_backup(path: &Path, content: &str, extension: &str) -> CodeGrepResult<()> {
+    // The temporary file lives next to the original so the rename stays on one filesystem
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut temp_file = tempfile::NamedTempFile::new_in(dir)?;
+    temp_file.write_all(content.as_bytes())?;
+    temp_file.as_file().sync_all()?;
+    fs::set_permissions(temp_file.path(), fs::metadata(path)?.permissions())?;
+    persist_with_backup(temp_file, path, extension)
+}
+
+/// Renames the already written `temp_file` over `path` after backing the original up
+fn persist_with_backup(temp_file: tempfile::NamedTempFile, path: &Path, extension: &str) -> CodeGrepResult<()> {
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(".");
+    backup_path.push(extension.trim_start_matches('.'));
+    let backup_path = PathBuf::from(backup_path);
+    
+    fs::copy(path, &backup_path).map_err(|e| {
+        CodeGrepError::Search(format!(
+            "{}: could not create backup {}: {}; file left unchanged",
+            path.display(),
+            backup_path.display(),
+            e
+        ))
+    })?;
+    
+    if let Err(e) = temp_file.persist(path) {
+        let restored = match fs::copy(&backup_path, path) {
+            Ok(_) => "original restored from backup".to_string(),
+            Err(restore_error) => format!("restoring from {} also failed: {}", backup_path.display(), restore_error),
+        };
+        return Err(CodeGrepError::Search(format!(
+            "{}: could not replace file: {}; {}",
+            path.display(),
+            e.error,
+            restored
+        )));
+    }
+    
+    Ok(())
+}
+
+/// Apply the casing style of `original` (lower, UPPER or Title) to `replacement`
+fn apply_case(original: &str, replacement: &str) -> String {
+    let letters: Vec<char> = original.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.is_empty() {
+        return replacement.to_string();
+    }
+    
+    if letters.iter().all(|c| c.is_lowercase()) {
+        replacement.to_lowercase()
+    } else if letters.iter().all(|c| c.is_uppercase()) {
+        replacement.to_uppercase()
+    } else if letters[0].is_uppercase() && letters[1..].iter().all(|c| c.is_lowercase()) {
+        let mut chars = replacement.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars.flat_map(|c| c.to_lowercase())).collect(),
+            None => String::new(),
+        }
+    } else {
+        replacement.to_string()
+    }
+}
+
 pub fn batch_replace_files(
     file_matches: &[FileMatch],
     pattern_matcher: &PatternMatcher,
     replacement: &str,
     cli: &Cli,
-) -> CodeGrepResult<Vec<ReplacementResult>> {
+) -> CodeGrepResult<BatchReplacement> {
     let replacer = Replacer::new(
         pattern_matcher.clone(),
         replacement.to_string(),
         cli.clone(),
     );
-    
+    replace_with(&replacer, file_matches, cli)
+}
+
+fn replace_with(replacer: &Replacer, file_matches: &[FileMatch], cli: &Cli) -> CodeGrepResult<BatchReplacement> {
+    // Huge files are rewritten while writing below and reported with their counts only
+    let (streamed, in_memory): (Vec<&FileMatch>, Vec<&FileMatch>) =
+        file_matches.iter().partition(|file_match| replacer.streams_file(file_match));
     let mut all_results = Vec::new();
     
-    for file_match in file_matches {
+    for file_match in in_memory {
         if let Some(result) = replacer.replace_in_file(file_match)? {
             all_results.push(result);
         }
     }
     
+    if cli.verify_idempotent {
+        for result in &all_results {
+            let lines = replacer.non_idempotent_lines(result);
+            if !lines.is_empty() {
+                let lines: Vec<String> = lines.iter().map(|n| n.to_string()).collect();
+                eprintln!(
+                    "Warning: {}: pattern still matches after replacement on line(s) {}; replacing again would change them further",
+                    result.file_path,
+                    lines.join(", ")
+                );
+            }
+        }
+    }
+    
+    if cli.count_replacements {
+        // Dry run: report the totals only, without previews or writes
+        let total_replacements: usize = all_results.iter().map(|r| r.replacements_made).sum();
+        println!(
+            "Would make {} replacements in {} files",
+            total_replacements,
+            all_results.len()
+        );
+        return Ok(BatchReplacement { results: all_results, streamed: Vec::new() });
+    }
+    
+    if cli.output_as_patch {
+        print!("{}", replacer.as_unified_patch(&all_results));
+        return Ok(BatchReplacement { results: all_results, streamed: Vec::new() });
+    }
+    
     if cli.interactive {
         let confirmed_results = replacer.interactive_replacement(&all_results)?;
         
-        if cli.write {
-            for result in &confirmed_results {
-                replacer.write_replacement(result)?;
-                println!("Updated: {}", result.file_path);
-            }
+        if cli.writes_in_place() {
+            write_replacements(replacer, &confirmed_results, &[])?;
         }
         
-        Ok(confirmed_results)
+        Ok(BatchReplacement { results: confirmed_results, streamed: Vec::new() })
     } else if cli.preview {
         // Just show previews, don't write
-        for result in &all_results {
-            println!("{}", replacer.preview_replacement(result));
-        }
-        Ok(all_results)
-    } else if cli.write {
+        print!("{}", replacer.preview_replacements(&all_results));
+        Ok(BatchReplacement { results: all_results, streamed: Vec::new() })
+    } else if cli.writes_in_place() {
         // Write all replacements without confirmation
-        for result in &all_results {
-            replacer.write_replacement(result)?;
-            println!("Updated: {}", result.file_path);
-        }
-        Ok(all_results)
+        let streamed = write_replacements(replacer, &all_results, &streamed)?;
+        Ok(BatchReplacement { results: all_results, streamed })
     } else {
         // Default: show preview without writing
-        for result in &all_results {
-            println!("{}", replacer.preview_replacement(result));
+        print!("{}", replacer.preview_replacements(&all_results));
+        Ok(BatchReplacement { results: all_results, streamed: Vec::new() })
+    }
+}
+
+/// Write every result even when some fail, so one bad file does not leave the rest unprocessed.
+/// `streamed` files are rewritten in place by `Replacer::stream_replace_file` at this point.
+/// Failures are reported per file on stderr and summed up in the returned error; on success the
+/// replacements made in each streamed file are returned.
+fn write_replacements(
+    replacer: &Replacer,
+    results: &[ReplacementResult],
+    streamed: &[&FileMatch],
+) -> CodeGrepResult<Vec<(PathBuf, usize)>> {
+    let report = |path: &str, outcome: &CodeGrepResult<usize>| match outcome {
+        Ok(0) => {}
+        Ok(_) => println!("Updated: {}", path),
+        Err(e) => eprintln!("Error: {}: failed to write replacement: {}", path, e),
+    };
+    let mut failures = 0;
+    for result in results {
+        let outcome = replacer.write_replacement(result).map(|()| 1);
+        report(&result.file_path, &outcome);
+        failures += outcome.is_err() as usize;
+    }
+    let mut streamed_counts = Vec::new();
+    for file_match in streamed {
+        let outcome = replacer.stream_replace_file(file_match);
+        report(&file_match.path.display().to_string(), &outcome);
+        match outcome {
+            Ok(replacements) => streamed_counts.push((file_match.path.clone(), replacements)),
+            Err(_) => failures += 1,
         }
-        Ok(all_results)
     }
+    
+    if failures > 0 {
+        return Err(CodeGrepError::Search(format!(
+            "{} file{} failed to write",
+            failures,
+            if failures == 1 { "" } else { "s" }
+        )));
+    }
+    Ok(streamed_counts)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{LineMatch, Match, PatternMatcher};
-    use std::path::PathBuf;
     use tempfile::NamedTempFile;
     use std::io::Write;
     
@@ -252,10 +778,17 @@ mod tests {
                         start: 0,
                         end: 5,
                         text: "Hello".to_string(),
+                        captures: Vec::new(),
                     }],
+                    occurrences: 1,
                 },
             ],
             total_matches: 1,
+            multiline_matches: vec![],
+            source: None,
+            functions: Vec::new(),
+            ast_paths: Vec::new(),
+            counted_lines: 0,
         };
         
         let cli = Cli::default();
@@ -269,4 +802,469 @@ mod tests {
         assert_eq!(result.replacements_made, 1);
         assert!(result.new_content.contains("Hi world"));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_replacement_preserve_case() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file.as_file().write_all(b"Foo foo FOO").unwrap();
+        
+        let file_match = FileMatch {
+            path: temp_file.path().to_path_buf(),
+            line_matches: vec![
+                LineMatch {
+                    line_number: 1,
+                    line_text: "Foo foo FOO".to_string(),
+                    matches: vec![],
+                    occurrences: 1,
+                },
+            ],
+            total_matches: 3,
+            multiline_matches: vec![],
+            source: None,
+            functions: Vec::new(),
+            ast_paths: Vec::new(),
+            counted_lines: 0,
+        };
+        
+        let cli = Cli { preserve_case: true, ..Default::default() };
+        let pattern_matcher = PatternMatcher::Literal("foo".to_string());
+        let replacer = Replacer::new(pattern_matcher, "bar".to_string(), cli);
+        
+        let result = replacer.replace_in_file(&file_match).unwrap().unwrap();
+        assert_eq!(result.replacements_made, 3);
+        assert_eq!(result.new_content, "Bar bar BAR");
+    }
+    
+    #[test]
+    fn test_apply_case() {
+        assert_eq!(apply_case("Foo", "bar"), "Bar");
+        assert_eq!(apply_case("foo", "Bar"), "bar");
+        assert_eq!(apply_case("FOO", "bar"), "BAR");
+        assert_eq!(apply_case("fOo", "bar"), "bar");
+    }
+    
+    #[test]
+    fn test_count_replacements_does_not_write() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let content = "Hello world\nHello again";
+        temp_file.as_file().write_all(content.as_bytes()).unwrap();
+        
+        let line_matches = [1, 2]
+            .iter()
+            .map(|&line_number| LineMatch {
+                line_number,
+                line_text: String::new(),
+                matches: vec![],
+                occurrences: 1,
+            })
+            .collect();
+        let file_match = FileMatch {
+            path: temp_file.path().to_path_buf(),
+            line_matches,
+            total_matches: 2,
+            multiline_matches: vec![],
+            source: None,
+            functions: Vec::new(),
+            ast_paths: Vec::new(),
+            counted_lines: 0,
+        };
+        
+        let cli = Cli { count_replacements: true, write: true, ..Default::default() };
+        let pattern_matcher = PatternMatcher::Literal("Hello".to_string());
+        let batch = batch_replace_files(&[file_match], &pattern_matcher, "Hi", &cli).unwrap();
+        
+        assert_eq!(batch.results.len(), 1);
+        assert_eq!(batch.results[0].replacements_made, 2);
+        assert_eq!(fs::read_to_string(temp_file.path()).unwrap(), content);
+    }
+    
+    #[test]
+    fn test_preview_limit() {
+        let results: Vec<ReplacementResult> = (1..=3)
+            .map(|i| ReplacementResult {
+                file_path: format!("file{}.txt", i),
+                original_content: "Hello".to_string(),
+                new_content: "Hi".to_string(),
+                replacements_made: 1,
+                lines_affected: vec![1],
+            })
+            .collect();
+        
+        let cli = Cli { preview_limit: Some(1), ..Default::default() };
+        let replacer = Replacer::new(PatternMatcher::Literal("Hello".to_string()), "Hi".to_string(), cli);
+        
+        let output = replacer.preview_replacements(&results);
+        assert!(output.contains("File: file1.txt"));
+        assert!(!output.contains("File: file2.txt"));
+        assert!(output.ends_with("... and 2 more files\n"));
+    }
+    
+    fn replacement_result(name: &str) -> ReplacementResult {
+        ReplacementResult {
+            file_path: name.to_string(),
+            original_content: "a\nb\nHello\nc".to_string(),
+            new_content: "a\nb\nHi\nc".to_string(),
+            replacements_made: 1,
+            lines_affected: vec![3],
+        }
+    }
+    
+    #[test]
+    fn test_interactive_all_after_skip() {
+        let results: Vec<_> = ["one", "two", "three"].iter().map(|n| replacement_result(n)).collect();
+        let replacer = Replacer::new(PatternMatcher::Literal("Hello".to_string()), "Hi".to_string(), Cli::default());
+        
+        let mut input = io::Cursor::new("n\nd\na\n");
+        let confirmed = replacer.interactive_replacement_with(&results, &mut input, &mut io::sink()).unwrap();
+        
+        let paths: Vec<_> = confirmed.iter().map(|r| r.file_path.as_str()).collect();
+        assert_eq!(paths, vec!["two", "three"]);
+    }
+    
+    #[test]
+    fn test_interactive_undo_reprompts_last_confirmed() {
+        let results: Vec<_> = ["one", "two"].iter().map(|n| replacement_result(n)).collect();
+        let replacer = Replacer::new(PatternMatcher::Literal("Hello".to_string()), "Hi".to_string(), Cli::default());
+        
+        let mut output = Vec::new();
+        let mut input = io::Cursor::new("y\nu\nn\ny\n");
+        let confirmed = replacer.interactive_replacement_with(&results, &mut input, &mut output).unwrap();
+        
+        let paths: Vec<_> = confirmed.iter().map(|r| r.file_path.as_str()).collect();
+        assert_eq!(paths, vec!["two"]);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Undid one."));
+        assert_eq!(output.matches("File: one\n").count(), 2);
+    }
+    
+    #[test]
+    fn test_interactive_summary_precedes_prompts() {
+        let mut results: Vec<_> = ["one", "two"].iter().map(|n| replacement_result(n)).collect();
+        results[1].replacements_made = 2;
+        let replacer = Replacer::new(PatternMatcher::Literal("Hello".to_string()), "Hi".to_string(), Cli::default());
+        
+        let mut output = Vec::new();
+        replacer.interactive_replacement_with(&results, &mut io::Cursor::new("y\nq\n"), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        
+        assert!(output.starts_with("About to review 3 replacements across 2 files\n"));
+        assert_eq!(output.matches("About to review").count(), 1);
+        assert!(output.contains("Apply this replacement?"));
+    }
+    
+    #[test]
+    fn test_unified_diff() {
+        let replacer = Replacer::new(PatternMatcher::Literal("Hello".to_string()), "Hi".to_string(), Cli::default());
+        let diff = replacer.unified_diff(&replacement_result("file.txt"));
+        assert_eq!(diff, "--- a/file.txt\n+++ b/file.txt\n@@ -1,4 +1,4 @@\n a\n b\n-Hello\n+Hi\n c\n");
+    }
+    
+    #[test]
+    fn test_as_unified_patch() {
+        let replacer = Replacer::new(PatternMatcher::Literal("Hello".to_string()), "Hi".to_string(), Cli::default());
+        let results = vec![replacement_result("./src/one.txt"), replacement_result("two.txt")];
+        
+        let patch = replacer.as_unified_patch(&results);
+        let headers: Vec<&str> = patch.lines().filter(|line| line.starts_with("---") || line.starts_with("+++")).collect();
+        assert_eq!(headers, vec!["--- a/src/one.txt", "+++ b/src/one.txt", "--- a/two.txt", "+++ b/two.txt"]);
+        assert_eq!(patch.matches("@@ -1,4 +1,4 @@").count(), 2);
+        assert!(replacer.as_unified_patch(&[]).is_empty());
+    }
+    
+    #[test]
+    fn test_verify_idempotent() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file.as_file().write_all(b"foo()\nbar()\n").unwrap();
+        
+        let file_match = FileMatch::new(
+            temp_file.path().to_path_buf(),
+            vec![LineMatch {
+                line_number: 1,
+                line_text: "foo()".to_string(),
+                matches: vec![Match { start: 0, end: 3, text: "foo".to_string(), captures: Vec::new() }],
+                occurrences: 1,
+            }],
+        );
+        let cli = Cli { verify_idempotent: true, ..Default::default() };
+        
+        // "foo" -> "foobar" re-introduces the pattern
+        let replacer = Replacer::new(PatternMatcher::Literal("foo".to_string()), "foobar".to_string(), cli.clone());
+        let result = replacer.replace_in_file(&file_match).unwrap().unwrap();
+        assert_eq!(replacer.non_idempotent_lines(&result), vec![1]);
+        
+        let replacer = Replacer::new(PatternMatcher::Literal("foo".to_string()), "baz".to_string(), cli);
+        let result = replacer.replace_in_file(&file_match).unwrap().unwrap();
+        assert!(replacer.non_idempotent_lines(&result).is_empty());
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_cmd() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file.as_file().write_all(b"let hello = world;\n").unwrap();
+        
+        let file_match = FileMatch::new(
+            temp_file.path().to_path_buf(),
+            vec![LineMatch {
+                line_number: 1,
+                line_text: "let hello = world;".to_string(),
+                matches: vec![Match { start: 4, end: 9, text: "hello".to_string(), captures: Vec::new() }],
+                occurrences: 1,
+            }],
+        );
+        let matcher = PatternMatcher::Basic(regex::Regex::new(r"h(el)lo|w(or)ld").unwrap());
+        
+        let reverse = Cli { replace_cmd: Some("rev".to_string()), ..Default::default() };
+        let replacer = Replacer::new(matcher.clone(), String::new(), reverse);
+        let result = replacer.replace_in_file(&file_match).unwrap().unwrap();
+        assert_eq!(result.new_content, "let olleh = dlrow;\n");
+        assert_eq!(result.replacements_made, 2);
+        
+        // Captures and the line number are passed as environment variables
+        let env = Cli { replace_cmd: Some("printf '%s@%s' \"$CG_1$CG_2\" \"$CG_LINE\"".to_string()), ..Default::default() };
+        let replacer = Replacer::new(matcher.clone(), String::new(), env);
+        let result = replacer.replace_in_file(&file_match).unwrap().unwrap();
+        assert_eq!(result.new_content, "let el@1 = or@1;\n");
+        
+        // A failing command leaves the matches untouched
+        let failing = Cli { replace_cmd: Some("exit 3".to_string()), ..Default::default() };
+        let replacer = Replacer::new(matcher, String::new(), failing);
+        assert!(replacer.replace_in_file(&file_match).unwrap().is_none());
+    }
+    
+    #[test]
+    fn test_replacement_keeps_bom_on_request() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file.as_file().write_all("\u{feff}Hello world".as_bytes()).unwrap();
+        
+        let file_match = FileMatch::new(
+            temp_file.path().to_path_buf(),
+            vec![LineMatch::new(1, "Hello world".to_string(), vec![Match { start: 0, end: 5, text: "Hello".to_string(), captures: Vec::new() }])],
+        );
+        let matcher = PatternMatcher::Basic(regex::Regex::new("^Hello").unwrap());
+        
+        let replacer = Replacer::new(matcher.clone(), "Hi".to_string(), Cli::default());
+        let result = replacer.replace_in_file(&file_match).unwrap().unwrap();
+        assert_eq!(result.new_content, "Hi world");
+        
+        let keep = Cli { keep_bom: true, ..Default::default() };
+        let replacer = Replacer::new(matcher, "Hi".to_string(), keep);
+        let result = replacer.replace_in_file(&file_match).unwrap().unwrap();
+        assert_eq!(result.new_content, "\u{feff}Hi world");
+    }
+    
+    #[test]
+    fn test_replace_limited_to_comment_lines() {
+        let mut temp_file = tempfile::Builder::new().suffix(".rs").tempfile().unwrap();
+        let content = "// Copyright 2023 Example\nconst YEAR: u32 = 2023;\n/* Updated 2023 */\n";
+        temp_file.write_all(content.as_bytes()).unwrap();
+        
+        let cli = Cli {
+            pattern: Some("2023".to_string()),
+            paths: vec![temp_file.path().to_path_buf()],
+            comments_only: true,
+            ..Default::default()
+        };
+        let (file_matches, _) = crate::SearchEngine::new(cli.clone()).unwrap().search().unwrap();
+        let pattern_matcher = PatternMatcher::Literal("2023".to_string());
+        let replacer = Replacer::new(pattern_matcher, "2024".to_string(), cli);
+        
+        let result = replacer.replace_in_file(&file_matches[0]).unwrap().unwrap();
+        assert_eq!(result.replacements_made, 2);
+        assert_eq!(result.lines_affected, vec![1, 3]);
+        assert_eq!(
+            result.new_content,
+            "// Copyright 2024 Example\nconst YEAR: u32 = 2023;\n/* Updated 2024 */\n"
+        );
+    }
+    
+    #[test]
+    fn test_replace_limited_to_function() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("lib.rs");
+        fs::write(&path, "fn helper() {\n    let x = 1;\n}\n\nfn check() {\n    let y = 2;\n}\n").unwrap();
+        
+        let cli = Cli {
+            pattern: Some("let".to_string()),
+            paths: vec![path.clone()],
+            in_function: Some("check".to_string()),
+            ..Default::default()
+        };
+        let (file_matches, _) = crate::SearchEngine::new(cli.clone()).unwrap().search().unwrap();
+        let replacer = Replacer::new(PatternMatcher::Literal("let".to_string()), "LET".to_string(), cli);
+        
+        let result = replacer.replace_in_file(&file_matches[0]).unwrap().unwrap();
+        assert_eq!(result.lines_affected, vec![6]);
+        assert_eq!(result.new_content, "fn helper() {\n    let x = 1;\n}\n\nfn check() {\n    LET y = 2;\n}\n");
+    }
+    
+    #[test]
+    fn test_write_with_backup() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("main.rs");
+        fs::write(&path, "let x = 1;").unwrap();
+        
+        write_with_backup(&path, "let y = 1;", ".bak").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "let y = 1;");
+        assert_eq!(fs::read_to_string(temp_dir.path().join("main.rs.bak")).unwrap(), "let x = 1;");
+        
+        // The backup cannot be created inside a missing directory, so the original stays as it was
+        assert!(write_with_backup(&path, "let z = 1;", "missing/bak").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "let y = 1;");
+        let leftovers = fs::read_dir(temp_dir.path()).unwrap().count();
+        assert_eq!(leftovers, 2);
+    }
+    
+    #[test]
+    fn test_replace_limited_to_line_range() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        let content = (1..=10)
+            .map(|n| if n == 2 || n == 10 { "TODO fix".to_string() } else { format!("line {}", n) })
+            .collect::<Vec<_>>()
+            .join("\n");
+        temp_file.write_all(content.as_bytes()).unwrap();
+        
+        let todo = || vec![Match { start: 0, end: 4, text: "TODO".to_string(), captures: Vec::new() }];
+        let file_match = FileMatch::new(
+            temp_file.path().to_path_buf(),
+            vec![
+                LineMatch::new(2, "TODO fix".to_string(), todo()),
+                LineMatch::new(10, "TODO fix".to_string(), todo()),
+            ],
+        );
+        
+        let cli = Cli {
+            range: Some("1:5".to_string()),
+            ..Default::default()
+        };
+        let replacer = Replacer::new(PatternMatcher::Literal("TODO".to_string()), "DONE".to_string(), cli);
+        
+        let result = replacer.replace_in_file(&file_match).unwrap().unwrap();
+        assert_eq!(result.replacements_made, 1);
+        assert_eq!(result.lines_affected, vec![2]);
+        let new_lines: Vec<&str> = result.new_content.lines().collect();
+        assert_eq!(new_lines[1], "DONE fix");
+        assert_eq!(new_lines[9], "TODO fix");
+    }
+    
+    #[test]
+    fn test_write_replacements_continues_after_failure() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let writable = temp_dir.path().join("ok.txt");
+        fs::write(&writable, "Hello").unwrap();
+        
+        let result_for = |path: &Path| ReplacementResult {
+            file_path: path.display().to_string(),
+            original_content: "Hello".to_string(),
+            new_content: "Hi".to_string(),
+            replacements_made: 1,
+            lines_affected: vec![1],
+        };
+        // The missing directory makes the first write fail
+        let results = vec![result_for(&temp_dir.path().join("missing/bad.txt")), result_for(&writable)];
+        let replacer = Replacer::new(PatternMatcher::Literal("Hello".to_string()), "Hi".to_string(), Cli::default());
+        
+        let error = write_replacements(&replacer, &results, &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Search error: 1 file failed to write");
+        assert_eq!(fs::read_to_string(&writable).unwrap(), "Hi");
+    }
+    
+    #[test]
+    fn test_batch_counts_streamed_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let small = temp_dir.path().join("small.txt");
+        let large = temp_dir.path().join("large.txt");
+        fs::write(&small, "needle\n").unwrap();
+        fs::write(&large, "needle\n".repeat(200)).unwrap();
+        
+        let needle = || vec![Match { start: 0, end: 6, text: "needle".to_string(), captures: Vec::new() }];
+        let file_matches = vec![
+            FileMatch::new(small.clone(), vec![LineMatch::new(1, "needle".to_string(), needle())]),
+            FileMatch::new(
+                large.clone(),
+                (1..=200).map(|n| LineMatch::new(n, "needle".to_string(), needle())).collect(),
+            ),
+        ];
+        let cli = Cli { write: true, ..Default::default() };
+        let mut replacer = Replacer::new(PatternMatcher::Literal("needle".to_string()), "thread".to_string(), cli.clone());
+        replacer.streaming_threshold = 100;
+        
+        let batch = replace_with(&replacer, &file_matches, &cli).unwrap();
+        assert_eq!(batch.results.len(), 1);
+        assert_eq!(batch.streamed, vec![(large.clone(), 200)]);
+        assert_eq!((batch.files_changed(), batch.total_replacements()), (2, 201));
+        assert_eq!(fs::read_to_string(&large).unwrap(), "thread\n".repeat(200));
+    }
+    
+    #[test]
+    fn test_replace_limited_to_class() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("handlers.js");
+        let content = "class A {\n  handle() {\n    return 1;\n  }\n}\n\nclass AB {\n  handle() {\n    return 2;\n  }\n}\n";
+        fs::write(&path, content).unwrap();
+        
+        let cli = Cli {
+            pattern: Some("handle".to_string()),
+            paths: vec![path.clone()],
+            in_class: Some("A".to_string()),
+            ..Default::default()
+        };
+        let (file_matches, _) = crate::SearchEngine::new(cli.clone()).unwrap().search().unwrap();
+        assert_eq!(file_matches.len(), 1);
+        assert_eq!(file_matches[0].total_matches, 1);
+        
+        // The class is matched by its exact name, and reported at its line in the file
+        let ab = Cli { in_class: Some("AB".to_string()), ..cli.clone() };
+        let (ab_matches, _) = crate::SearchEngine::new(ab).unwrap().search().unwrap();
+        let lines: Vec<_> = ab_matches[0].line_matches.iter().map(|lm| lm.line_number).collect();
+        assert_eq!(lines, vec![8]);
+        
+        let replacer = Replacer::new(PatternMatcher::Literal("handle".to_string()), "process".to_string(), cli);
+        let result = replacer.replace_in_file(&file_matches[0]).unwrap().unwrap();
+        assert_eq!(result.lines_affected, vec![2]);
+        assert_eq!(
+            result.new_content,
+            "class A {\n  process() {\n    return 1;\n  }\n}\n\nclass AB {\n  handle() {\n    return 2;\n  }\n}\n"
+        );
+    }
+    
+    #[test]
+    fn test_replacement_preserves_text_after_last_match() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        let content = "let a = old; // keep old here\r\nx = old\r\ny = old  \t// trailing\r\nlast old";
+        temp_file.write_all(content.as_bytes()).unwrap();
+        
+        let old = |start: usize| vec![Match { start, end: start + 3, text: "old".to_string(), captures: Vec::new() }];
+        let file_match = FileMatch::new(
+            temp_file.path().to_path_buf(),
+            vec![
+                LineMatch::new(1, "let a = old; // keep old here".to_string(), old(8)),
+                LineMatch::new(2, "x = old".to_string(), old(4)),
+                LineMatch::new(3, "y = old  \t// trailing".to_string(), old(4)),
+                LineMatch::new(4, "last old".to_string(), old(5)),
+            ],
+        );
+        let cli = Cli { regex: true, ..Default::default() };
+        let matcher = PatternMatcher::Basic(regex::Regex::new(r"old\b").unwrap());
+        let replacer = Replacer::new(matcher, "now".to_string(), cli);
+        
+        let result = replacer.replace_in_file(&file_match).unwrap().unwrap();
+        assert_eq!(
+            result.new_content,
+            "let a = now; // keep now here\r\nx = now\r\ny = now  \t// trailing\r\nlast now"
+        );
+        
+        // An end-of-line anchor matches before the `\r`, which stays in place
+        let anchored = Replacer::new(
+            PatternMatcher::Basic(regex::Regex::new(r"old$").unwrap()),
+            "new".to_string(),
+            Cli { regex: true, ..Default::default() },
+        );
+        let result = anchored.replace_in_file(&file_match).unwrap().unwrap();
+        assert_eq!(result.lines_affected, vec![2, 4]);
+        assert_eq!(
+            result.new_content,
+            "let a = old; // keep old here\r\nx = new\r\ny = old  \t// trailing\r\nlast new"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+
+/// Short ripgrep flags that take a value (`-e PATTERN`, `-ePATTERN`)
+const RG_SHORT_FLAGS_WITH_VALUE: &str = "egtTABCrdjm";
+
+/// ripgrep の引数を code-grep の引数に読み替える（`--rg-compat` / `cg-rg` 用）
+///
+/// ripgrep と同じく正規表現・大文字小文字区別・行番号表示が既定になる。
+/// `-e` が指定された場合は位置引数はすべてパスとして扱う。
+/// 対応表にないフラグはそのまま code-grep に渡すため、値を取る未対応フラグは
+/// `--flag=value` の形で指定する必要がある。
+pub fn translate_rg_args(args: Vec<String>) -> Vec<String> {
+    let mut pending: VecDeque<String> = args.into();
+    let program = pending.pop_front().unwrap_or_else(|| "cg".to_string());
+
+    let mut patterns = Vec::new();
+    let mut positionals = Vec::new();
+    let mut flags: Vec<String> = Vec::new();
+    let mut fixed_strings = false;
+    let mut ignore_case = false;
+    let mut smart_case = false;
+    let mut line_numbers = true;
+    let mut no_heading = false;
+    let mut only_positionals = false;
+
+    while let Some(arg) = pending.pop_front() {
+        if only_positionals {
+            positionals.push(arg);
+            continue;
+        }
+        if arg == "--" {
+            only_positionals = true;
+            continue;
+        }
+
+        // Split bundled short flags (`-in`) and attached values (`-eTODO`)
+        if arg.starts_with('-') && !arg.starts_with("--") && arg.chars().count() > 2 {
+            let mut chars = arg.chars().skip(1);
+            let first = chars.next().unwrap();
+            let rest: String = chars.collect();
+            if RG_SHORT_FLAGS_WITH_VALUE.contains(first) {
+                pending.push_front(rest);
+            } else {
+                pending.push_front(format!("-{}", rest));
+            }
+            pending.push_front(format!("-{}", first));
+            continue;
+        }
+
+        let (name, mut inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || inline_value.take().or_else(|| pending.pop_front()).unwrap_or_default();
+
+        match name.as_str() {
+            "-e" | "--regexp" => patterns.push(value()),
+            "-g" | "--glob" => flags.extend(["--glob".to_string(), value()]),
+            "--type-add" => flags.extend(["--type-add".to_string(), value()]),
+            "-t" | "--type" => flags.extend(["--type".to_string(), value()]),
+            "-A" | "--after-context" => flags.extend(["--after-context".to_string(), value()]),
+            "-B" | "--before-context" => flags.extend(["--before-context".to_string(), value()]),
+            "-C" | "--context" => flags.extend(["--context".to_string(), value()]),
+            "-r" | "--replace" => flags.extend(["--replace".to_string(), value()]),
+            "-d" | "--max-depth" => flags.extend(["--max-depth".to_string(), value()]),
+            "-j" | "--threads" => flags.extend(["--threads".to_string(), value()]),
+            "--color" => {
+                let color = value();
+                flags.extend(["--color".to_string(), if color == "ansi" { "always".to_string() } else { color }]);
+            }
+            "-i" | "--ignore-case" => (ignore_case, smart_case) = (true, false),
+            "-S" | "--smart-case" => (ignore_case, smart_case) = (false, true),
+            "-s" | "--case-sensitive" => (ignore_case, smart_case) = (false, false),
+            "-F" | "--fixed-strings" => fixed_strings = true,
+            "-w" | "--word-regexp" => flags.push("--word-boundary".to_string()),
+            "-l" | "--files-with-matches" => flags.push("--files-only".to_string()),
+            "-c" | "--count" => flags.push("--count-only".to_string()),
+            "-o" | "--only-matching" => flags.push("--only-matching".to_string()),
+            "-n" | "--line-number" => line_numbers = true,
+            "-N" | "--no-line-number" => line_numbers = false,
+            "--heading" => no_heading = false,
+            "--no-heading" => no_heading = true,
+            "-U" | "--multiline" => flags.push("--multiline".to_string()),
+            "-." | "--hidden" => flags.push("--hidden".to_string()),
+            "-L" | "--follow" => flags.push("--include-symlinks-as-files".to_string()),
+            "--rg-compat" => {}
+            _ if arg.starts_with('-') && arg != "-" => flags.push(arg),
+            _ => positionals.push(arg),
+        }
+    }
+
+    // --smart-case ignores case unless a pattern has an uppercase letter
+    if smart_case {
+        let searched: Vec<&String> = if patterns.is_empty() { positionals.iter().take(1).collect() } else { patterns.iter().collect() };
+        ignore_case = !searched.iter().any(|pattern| has_uppercase_literal(pattern, fixed_strings));
+    }
+
+    // Several -e patterns match any of them, like ripgrep
+    let pattern = match patterns.len() {
+        0 => None,
+        1 => patterns.pop(),
+        _ => {
+            let alternatives: Vec<String> = patterns
+                .iter()
+                .map(|p| format!("(?:{})", if fixed_strings { regex::escape(p) } else { p.clone() }))
+                .collect();
+            fixed_strings = false;
+            Some(alternatives.join("|"))
+        }
+    };
+
+    let mut translated = vec![program, "--rg-compat".to_string()];
+    translated.push(if fixed_strings { "--literal" } else { "--regex" }.to_string());
+    if !ignore_case {
+        translated.push("--case-sensitive".to_string());
+    }
+    if line_numbers {
+        translated.push("--line-numbers".to_string());
+    }
+    if no_heading {
+        translated.push("--no-heading".to_string());
+    }
+    translated.extend(flags);
+    translated.push("--".to_string());
+    translated.extend(pattern);
+    translated.extend(positionals);
+    translated
+}
+
+/// Whether `pattern` has an uppercase letter of its own; in a regex the letter after a
+/// backslash is an escape (`\S`, `\W`) rather than text to match
+fn has_uppercase_literal(pattern: &str, fixed_strings: bool) -> bool {
+    if fixed_strings {
+        return pattern.chars().any(char::is_uppercase);
+    }
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translate(args: &str) -> Vec<String> {
+        translate_rg_args(args.split_whitespace().map(String::from).collect())
+    }
+
+    #[test]
+    fn test_translate_rg_args() {
+        assert_eq!(
+            translate("rg -in TODO src"),
+            ["rg", "--rg-compat", "--regex", "--line-numbers", "--", "TODO", "src"]
+        );
+        assert_eq!(
+            translate("rg -e foo -tpy -g !*.min.js --no-heading src"),
+            [
+                "rg", "--rg-compat", "--regex", "--case-sensitive", "--line-numbers", "--no-heading",
+                "--type", "py", "--glob", "!*.min.js", "--", "foo", "src",
+            ]
+        );
+        assert_eq!(
+            translate("rg -F -e a.b -e c --type-add=web:*.html"),
+            [
+                "rg", "--rg-compat", "--regex", "--case-sensitive", "--line-numbers",
+                "--type-add", "web:*.html", "--", r"(?:a\.b)|(?:c)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_smart_case() {
+        let case_sensitive = |args: &str| translate(args).contains(&"--case-sensitive".to_string());
+        assert!(!case_sensitive("rg -S todo src"));
+        assert!(case_sensitive("rg -S TODO src"));
+        assert!(!case_sensitive(r"rg --smart-case \Sfoo"));
+        assert!(case_sensitive("rg -S -e foo -e Bar"));
+        assert!(case_sensitive("rg -S -s todo"));
+        assert!(!case_sensitive("rg -S -i TODO"));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{batch_replace_files, Cli, CodeGrepError, CodeGrepResult, PatternMatcher, SearchEngine};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `cg apply-rules` のルール（ルールファイルは YAML のリスト）
+///
+/// ```yaml
+/// - pattern: old_name
+///   replace: new_name
+///   word_boundary: true
+/// - pattern: "log\\.(debug|trace)"
+///   replace: log.info
+///   regex: true
+///   in_class: Handler
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub pattern: String,
+    pub replace: String,
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub word_boundary: bool,
+    #[serde(default)]
+    pub in_class: Option<String>,
+}
+
+/// Replacements one rule made (or would make without `--write`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleOutcome {
+    pub pattern: String,
+    pub replace: String,
+    pub files: usize,
+    pub replacements: usize,
+}
+
+impl Rule {
+    /// The options of `base` (paths, `--write`, `--preview`, ...) with this rule's search and replacement
+    pub fn cli(&self, base: &Cli) -> Cli {
+        Cli {
+            pattern: Some(self.pattern.clone()),
+            replace: Some(self.replace.clone()),
+            regex: self.regex || base.regex,
+            word_boundary: self.word_boundary || base.word_boundary,
+            in_class: self.in_class.clone().or_else(|| base.in_class.clone()),
+            command: None,
+            ..base.clone()
+        }
+    }
+}
+
+/// Reads the rules in `path`
+pub fn load_rules(path: &Path) -> CodeGrepResult<Vec<Rule>> {
+    let content = fs::read_to_string(path)?;
+    serde_yaml::from_str(&content)
+        .map_err(|e| CodeGrepError::Config(format!("{}: invalid rules file: {}", path.display(), e)))
+}
+
+/// Runs the search and replacement of each rule in order, searching `paths` (or the paths of
+/// `base` when empty). With `--write` each rule sees the files as the previous rules left them.
+pub fn apply_rules(rules: &[Rule], paths: &[PathBuf], base: &Cli) -> CodeGrepResult<Vec<RuleOutcome>> {
+    let mut outcomes = Vec::new();
+    for rule in rules {
+        let mut cli = rule.cli(base);
+        if !paths.is_empty() {
+            cli.paths = paths.to_vec();
+        }
+
+        let (file_matches, _) = SearchEngine::new(cli.clone())?.search()?;
+        let pattern_matcher = PatternMatcher::new(&cli)?;
+        let batch = batch_replace_files(&file_matches, &pattern_matcher, &rule.replace, &cli)?;
+
+        outcomes.push(RuleOutcome {
+            pattern: rule.pattern.clone(),
+            replace: rule.replace.clone(),
+            files: batch.files_changed(),
+            replacements: batch.total_replacements(),
+        });
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_two_rules() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = temp_dir.path().join("lib.rs");
+        let other = temp_dir.path().join("main.rs");
+        fs::write(&source, "fn old_name() {}\nfn old_name_helper() { old_name(); }\n").unwrap();
+        fs::write(&other, "// TODO: v1\nfn main() { old_name(); }\n").unwrap();
+        let rules_file = temp_dir.path().join("rules.yaml");
+        fs::write(
+            &rules_file,
+            "- pattern: old_name\n  replace: new_name\n  word_boundary: true\n- pattern: 'v[0-9]'\n  replace: v2\n  regex: true\n",
+        )
+        .unwrap();
+
+        let rules = load_rules(&rules_file).unwrap();
+        let base = Cli { write: true, case_sensitive: true, ..Default::default() };
+        let outcomes = apply_rules(&rules, &[source.clone(), other.clone()], &base).unwrap();
+
+        let counts: Vec<_> = outcomes.iter().map(|outcome| (outcome.files, outcome.replacements)).collect();
+        assert_eq!(counts, vec![(2, 3), (1, 1)]);
+        assert_eq!(
+            fs::read_to_string(&source).unwrap(),
+            "fn new_name() {}\nfn old_name_helper() { new_name(); }\n"
+        );
+        assert_eq!(fs::read_to_string(&other).unwrap(), "// TODO: v2\nfn main() { new_name(); }\n");
+
+        fs::write(&rules_file, "- pattern: a\n  replacement: b\n").unwrap();
+        assert!(matches!(load_rules(&rules_file), Err(CodeGrepError::Config(_))));
+    }
+}
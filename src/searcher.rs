@@ -1,19 +1,65 @@
 use crate::{
-    Cli, CodeGrepResult, FileWalker, LineMatch, PatternMatcher,
-    find_in_text,
+    Cli, CodeGrepResult, CodeParser, Config, FileWalker, FunctionInfo, LineMatch, Match, MultilineMatch, ParsedCode,
+    PatternMatcher, ResultSpool, SparseState, SPARSE_STATE_FILE, ast_paths, count_in_text, drop_zero_length_matches,
+    fetch_url, find_in_records, find_in_text, find_in_text_multiline, is_url, json_values, parse_key_path,
+    with_debug_file, yaml_values,
 };
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::collections::HashMap;
 use std::fs;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::time::Instant;
 
-#[derive(Debug, Clone)]
+/// Byte order mark some Windows editors put at the start of UTF-8 files
+pub const UTF8_BOM: char = '\u{feff}';
+
+/// Content without a leading UTF-8 BOM, so offsets and `^` anchors on line 1 are not shifted
+pub fn strip_bom(content: &str) -> &str {
+    content.strip_prefix(UTF8_BOM).unwrap_or(content)
+}
+
+/// The first `count` lines of `content`, including the last one's line ending
+fn first_lines(content: &str, count: usize) -> &str {
+    let end = match count.checked_sub(1) {
+        Some(last) => content.match_indices('\n').nth(last).map_or(content.len(), |(index, _)| index + 1),
+        None => 0,
+    };
+    &content[..end]
+}
+
+/// Number of files searched per parallel batch when spilling is enabled
+const MIN_SPOOL_BATCH_SIZE: usize = 256;
+
+fn spool_batch_size(spill_threshold: usize) -> usize {
+    spill_threshold.max(MIN_SPOOL_BATCH_SIZE)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileMatch {
     pub path: PathBuf,
     pub line_matches: Vec<LineMatch>,
     pub total_matches: usize,
+    /// `--multiline` 検索時のみ設定される
+    #[serde(default)]
+    pub multiline_matches: Vec<MultilineMatch>,
+    /// 検索したテキスト全体（コンテキスト表示が必要な場合のみ保持）
+    #[serde(default)]
+    pub source: Option<Arc<str>>,
+    /// 関数の範囲（`--show-function-boundaries` 時のみ設定される）
+    #[serde(default)]
+    pub functions: Vec<FunctionInfo>,
+    /// 行番号ごとの構文ノードの経路（`--print-ast-path` 時のみ設定される）
+    #[serde(default)]
+    pub ast_paths: Vec<(usize, String)>,
+    /// 件数のみの検索で一致した行数（このとき `line_matches` は空）
+    #[serde(default)]
+    pub counted_lines: usize,
 }
 
 impl FileMatch {
@@ -23,12 +69,113 @@ impl FileMatch {
             path,
             line_matches,
             total_matches,
+            multiline_matches: Vec::new(),
+            source: None,
+            functions: Vec::new(),
+            ast_paths: Vec::new(),
+            counted_lines: 0,
         }
     }
     
+    /// Result of a counting-only search, from (line number, match count) pairs
+    pub fn counted(path: PathBuf, line_counts: &[(usize, usize)]) -> Self {
+        let mut file_match = Self::new(path, Vec::new());
+        file_match.total_matches = line_counts.iter().map(|(_, count)| count).sum();
+        file_match.counted_lines = line_counts.len();
+        file_match
+    }
+    
+    pub fn with_multiline_matches(mut self, multiline_matches: Vec<MultilineMatch>) -> Self {
+        self.total_matches += multiline_matches.len();
+        self.multiline_matches = multiline_matches;
+        self
+    }
+    
+    pub fn with_source(mut self, source: Arc<str>) -> Self {
+        self.source = Some(source);
+        self
+    }
+    
+    pub fn with_ast_paths(mut self, ast_paths: Vec<(usize, String)>) -> Self {
+        self.ast_paths = ast_paths;
+        self
+    }
+    
+    /// AST path recorded for a matched line
+    pub fn ast_path(&self, line_number: usize) -> Option<&str> {
+        self.ast_paths
+            .iter()
+            .find(|(line, _)| *line == line_number)
+            .map(|(_, path)| path.as_str())
+    }
+    
+    pub fn with_functions(mut self, functions: Vec<FunctionInfo>) -> Self {
+        self.functions = functions;
+        self
+    }
+    
+    /// Innermost function whose range contains the given line
+    pub fn enclosing_function(&self, line_number: usize) -> Option<&FunctionInfo> {
+        self.functions
+            .iter()
+            .filter(|func| func.start_line <= line_number && line_number <= func.end_line)
+            .max_by_key(|func| func.start_line)
+    }
+    
     pub fn has_matches(&self) -> bool {
         self.total_matches > 0
     }
+    
+    /// Number of lines with at least one match, counting every line a multiline match spans
+    pub fn matched_lines(&self) -> usize {
+        self.line_matches.len()
+            + self.counted_lines
+            + self.multiline_matches.iter().map(|m| m.end_line - m.start_line + 1).sum::<usize>()
+    }
+    
+    /// Split the stored source into the same records that line numbers refer to
+    pub fn source_records(&self, separator: Option<&str>) -> Option<Vec<&str>> {
+        let source = self.source.as_deref()?;
+        Some(match separator {
+            Some(separator) if !separator.is_empty() => source.split(separator).collect(),
+            _ => source.lines().collect(),
+        })
+    }
+}
+
+/// `--dedupe-lines`: identical matched lines collapsed into their first occurrence, whose
+/// `occurrences` counts them all. With `global` lines are compared across files too, and a file
+/// whose lines all appeared in earlier files is left out.
+pub fn dedupe_lines(file_matches: &[FileMatch], global: bool) -> Vec<FileMatch> {
+    let mut deduped: Vec<FileMatch> = Vec::with_capacity(file_matches.len());
+    // (index in `deduped`, line index) of the first occurrence of each line text
+    let mut first_seen: HashMap<&str, (usize, usize)> = HashMap::new();
+    
+    for file_match in file_matches {
+        if !global {
+            first_seen.clear();
+        }
+        let file_index = deduped.len();
+        let mut line_matches: Vec<LineMatch> = Vec::new();
+        for line_match in &file_match.line_matches {
+            match first_seen.get(line_match.line_text.as_str()) {
+                Some(&(file, line)) if file == file_index => line_matches[line].occurrences += line_match.occurrences,
+                Some(&(file, line)) => deduped[file].line_matches[line].occurrences += line_match.occurrences,
+                None => {
+                    first_seen.insert(&line_match.line_text, (file_index, line_matches.len()));
+                    line_matches.push(line_match.clone());
+                }
+            }
+        }
+        
+        if line_matches.is_empty() && !file_match.line_matches.is_empty() {
+            continue;
+        }
+        let mut file_match = file_match.clone();
+        file_match.line_matches = line_matches;
+        deduped.push(file_match);
+    }
+    deduped
 }
 
 #[derive(Debug)]
@@ -39,6 +186,12 @@ pub struct SearchStats {
     pub total_lines: usize,
     pub elapsed_time: std::time::Duration,
     pub search_rate: f64, // files per second
+    /// Bytes read from searched files, before any filtering
+    pub bytes_searched: u64,
+    /// Lines read from searched files, before any filtering
+    pub lines_searched: usize,
+    pub bytes_per_second: f64,
+    pub lines_per_second: f64,
 }
 
 impl SearchStats {
@@ -62,163 +215,519 @@ impl SearchStats {
             total_lines,
             elapsed_time,
             search_rate,
+            bytes_searched: 0,
+            lines_searched: 0,
+            bytes_per_second: 0.0,
+            lines_per_second: 0.0,
         }
     }
+    
+    /// Record how much content was read and derive the per-second throughput
+    pub fn with_throughput(mut self, bytes_searched: u64, lines_searched: usize) -> Self {
+        let seconds = self.elapsed_time.as_secs_f64();
+        if seconds > 0.0 {
+            self.bytes_per_second = bytes_searched as f64 / seconds;
+            self.lines_per_second = lines_searched as f64 / seconds;
+        }
+        self.bytes_searched = bytes_searched;
+        self.lines_searched = lines_searched;
+        self
+    }
 }
 
 pub struct SearchEngine {
     matcher: PatternMatcher,
+    /// `--fast`: the matcher with its engine timed on the first file searched
+    fast_matcher: OnceLock<PatternMatcher>,
+    line_filter: Option<PatternMatcher>,
     walker: FileWalker,
+    parser: CodeParser,
     cli: Cli,
+    /// Files expected to be searched: an estimate before the walk, the exact count after it.
+    /// Only kept once `file_count_estimate` has been asked for, since estimating reads directories.
+    file_count_estimate: OnceLock<Arc<AtomicUsize>>,
+    /// Results of the previous run, with `--sparse`
+    sparse: Option<SparseState>,
 }
 
 impl SearchEngine {
     pub fn new(cli: Cli) -> CodeGrepResult<Self> {
+        Self::with_config(cli, &Config::default())
+    }
+    
+    /// Like `new`, taking the settings the config file adds to the command line
+    pub fn with_config(cli: Cli, config: &Config) -> CodeGrepResult<Self> {
+        cli.validate()?;
         let matcher = PatternMatcher::new(&cli)?;
-        let walker = FileWalker::new(&cli);
+        let line_filter = PatternMatcher::line_filter(&cli)?;
+        let walker = FileWalker::with_config(&cli, config);
+        let sparse = cli
+            .sparse
+            .then(|| SparseState::load(Path::new(SPARSE_STATE_FILE), &cli));
         
         Ok(Self {
             matcher,
+            fast_matcher: OnceLock::new(),
+            line_filter,
             walker,
+            parser: CodeParser::new(),
             cli,
+            file_count_estimate: OnceLock::new(),
+            sparse,
         })
     }
     
+    /// Shared count of the files this search is expected to cover, e.g. for a progress bar
+    pub fn file_count_estimate(&self) -> Arc<AtomicUsize> {
+        Arc::clone(self.file_count_estimate.get_or_init(Default::default))
+    }
+    
+    /// Writes the `--debug-matcher-log` once the search is done (only with the `debug-matcher` feature)
+    pub fn write_debug_matcher_log(&self) -> CodeGrepResult<()> {
+        #[cfg(feature = "debug-matcher")]
+        if let Some(ref path) = self.cli.debug_matcher_log {
+            self.matcher().write_debug_log(path)?;
+        }
+        Ok(())
+    }
+    
     pub fn search(&self) -> CodeGrepResult<(Vec<FileMatch>, SearchStats)> {
+        let file_paths = self.collect_file_paths()?;
+        self.search_paths(&file_paths)
+    }
+    
+    /// Search exactly the given files, in the given order, without walking any directories.
+    /// URLs are fetched when `--search-network` is set.
+    pub fn search_paths(&self, paths: &[PathBuf]) -> CodeGrepResult<(Vec<FileMatch>, SearchStats)> {
+        let (spool, stats) = self.search_paths_spooled(paths, usize::MAX)?;
+        Ok((spool.into_vec()?, stats))
+    }
+    
+    /// Search while keeping at most about `spill_threshold` results in memory.
+    /// Older results are spilled to a temporary file and streamed back in path order.
+    pub fn search_spooled(&self, spill_threshold: usize) -> CodeGrepResult<(ResultSpool, SearchStats)> {
+        let file_paths = self.collect_file_paths()?;
+        self.search_paths_spooled(&file_paths, spill_threshold)
+    }
+    
+    /// Search text that was already read, such as standard input, under the name `path`
+    pub fn search_text(&self, path: &Path, content: String) -> CodeGrepResult<FileMatch> {
+        with_debug_file(path, || self.search_content(path, content, path))
+    }
+    
+    /// Publish the walker's estimate before walking, if anyone is following the search's progress
+    fn store_file_count_estimate(&self, remote_urls: usize) {
+        if let Some(estimate) = self.file_count_estimate.get() {
+            estimate.store(self.walker.estimate_file_count() + remote_urls, Ordering::Relaxed);
+        }
+    }
+    
+    /// Files the search covers: walked (or git-tracked) local files in path order, then URLs,
+    /// cut off after `--limit-files`
+    fn collect_file_paths(&self) -> CodeGrepResult<Vec<PathBuf>> {
+        // Remote files are fetched separately from the local walk
+        let remote_urls: Vec<PathBuf> = if self.cli.search_network {
+            self.cli.paths.iter().filter(|p| is_url(p)).cloned().collect()
+        } else {
+            Vec::new()
+        };
+        let has_local_paths = remote_urls.is_empty() || remote_urls.len() < self.cli.paths.len();
+        
+        // Collect all file paths first, sorted so results come out in a consistent order
+        let mut file_paths = if has_local_paths {
+            self.store_file_count_estimate(remote_urls.len());
+            self.walker.included_files(&self.cli)?
+        } else {
+            Vec::new()
+        };
+        file_paths.extend(remote_urls);
+        if let Some(limit) = self.cli.limit_files {
+            file_paths.truncate(limit);
+        }
+        if let Some(estimate) = self.file_count_estimate.get() {
+            estimate.store(file_paths.len(), Ordering::Relaxed);
+        }
+        
+        Ok(file_paths)
+    }
+    
+    fn search_paths_spooled(&self, paths: &[PathBuf], spill_threshold: usize) -> CodeGrepResult<(ResultSpool, SearchStats)> {
         let start_time = Instant::now();
-        let files_searched = Arc::new(AtomicUsize::new(0));
-        let total_matches = Arc::new(AtomicUsize::new(0));
-        let total_lines = Arc::new(AtomicUsize::new(0));
-        
-        let results: Arc<Mutex<Vec<FileMatch>>> = Arc::new(Mutex::new(Vec::new()));
-        
-        // Collect all file paths first
-        let file_paths: Vec<_> = self.walker
-            .walk()
-            .into_iter()
-            .filter_map(|path_result| path_result.ok())
-            .filter(|path| self.walker.should_include_file(path, &self.cli))
-            .collect();
+        let mut files_searched = 0;
+        let mut total_matches = 0;
+        let mut total_lines = 0;
+        
+        let mut spool = ResultSpool::new(spill_threshold);
         
-        // Search files in parallel
-        file_paths.par_iter().for_each(|path| {
-            if let Ok(file_match) = self.search_file(path) {
-                files_searched.fetch_add(1, Ordering::Relaxed);
+        // Updated from the parallel workers as each file is read
+        let bytes_searched = AtomicU64::new(0);
+        let lines_searched = AtomicUsize::new(0);
+        let record_throughput = |content: &str| {
+            bytes_searched.fetch_add(content.len() as u64, Ordering::Relaxed);
+            lines_searched.fetch_add(content.lines().count(), Ordering::Relaxed);
+        };
+        
+        let (remote_urls, file_paths): (Vec<&PathBuf>, Vec<&PathBuf>) = paths
+            .iter()
+            .partition(|path| self.cli.search_network && is_url(path));
+        
+        // --fast picks its engine by timing them on the first file searched
+        if let (true, Some(first)) = (self.cli.fast, file_paths.first()) {
+            if self.fast_matcher.get().is_none() {
+                let _ = self.fast_matcher.set(PatternMatcher::with_benchmark_sample(&self.cli, first)?);
+            }
+        }
+        
+        // Search files in parallel, one batch at a time so memory stays bounded
+        for batch in file_paths.chunks(spool_batch_size(spill_threshold)) {
+            let batch_results: Vec<Option<FileMatch>> = batch
+                .par_iter()
+                .map(|path| self.search_file_isolated(path, &record_throughput))
+                .collect();
+            
+            for file_match in batch_results.into_iter().flatten() {
+                files_searched += 1;
                 
                 if file_match.has_matches() {
-                    total_matches.fetch_add(file_match.total_matches, Ordering::Relaxed);
-                    total_lines.fetch_add(file_match.line_matches.len(), Ordering::Relaxed);
-                    
-                    let mut results = results.lock().unwrap();
-                    results.push(file_match);
+                    total_matches += file_match.total_matches;
+                    total_lines += file_match.matched_lines();
+                    spool.push(file_match)?;
                 }
             }
-        });
+        }
         
-        let elapsed_time = start_time.elapsed();
-        let files_searched_count = files_searched.load(Ordering::Relaxed);
-        let total_matches_count = total_matches.load(Ordering::Relaxed);
-        let total_lines_count = total_lines.load(Ordering::Relaxed);
+        for url in remote_urls {
+            let file_match = with_debug_file(url, || self.search_url(url, &record_throughput))?;
+            files_searched += 1;
+            
+            if file_match.has_matches() {
+                total_matches += file_match.total_matches;
+                total_lines += file_match.matched_lines();
+                spool.push(file_match)?;
+            }
+        }
         
-        let results = Arc::try_unwrap(results).unwrap().into_inner().unwrap();
-        let mut file_matches = results;
+        let stats = SearchStats::new(
+            files_searched,
+            spool.len(),
+            total_matches,
+            total_lines,
+            start_time.elapsed(),
+        )
+        .with_throughput(bytes_searched.into_inner(), lines_searched.into_inner());
         
-        // Sort results by file path for consistent output
-        file_matches.sort_by(|a, b| a.path.cmp(&b.path));
+        if let Some(ref sparse) = self.sparse {
+            sparse.save()?;
+        }
+        Ok((spool, stats))
+    }
+    
+    /// The matcher searches use: the `--fast` one once its engine has been picked
+    fn matcher(&self) -> &PatternMatcher {
+        self.fast_matcher.get().unwrap_or(&self.matcher)
+    }
+    
+    /// Search one file, turning a panic inside it into a warning so the other files still get searched
+    fn search_file_isolated(&self, path: &Path, record_throughput: &(dyn Fn(&str) + Sync)) -> Option<FileMatch> {
+        // Files are searched in parallel, so the thread shows which file a stuck worker holds
+        if self.cli.verbose > 0 {
+            eprintln!("[{:?}] Searching: {}", std::thread::current().id(), path.display());
+        }
+        isolate_panic(path, || with_debug_file(path, || self.search_file(path, record_throughput)))
+    }
+    
+    fn search_file(&self, path: &Path, record_throughput: &(dyn Fn(&str) + Sync)) -> CodeGrepResult<FileMatch> {
+        // --sparse: a file unchanged since the last run is not read again
+        let modified = match self.sparse {
+            Some(ref sparse) => {
+                let modified = fs::metadata(path)?.modified()?;
+                if let Some(file_match) = sparse.lookup(path, modified) {
+                    // The stored result has no source, so context is read from the unchanged file
+                    if self.cli.has_context() && file_match.has_matches() {
+                        let content = fs::read_to_string(path)?;
+                        let content = content.strip_prefix(UTF8_BOM).unwrap_or(&content);
+                        return Ok(file_match.with_source(Arc::from(content)));
+                    }
+                    return Ok(file_match);
+                }
+                Some(modified)
+            }
+            None => None,
+        };
         
-        let stats = SearchStats::new(
-            files_searched_count,
-            file_matches.len(),
-            total_matches_count,
-            total_lines_count,
-            elapsed_time,
-        );
+        let mut content = fs::read_to_string(path)?;
+        record_throughput(&content);
+        if content.starts_with(UTF8_BOM) {
+            content.drain(..UTF8_BOM.len_utf8());
+        }
+        let file_match = self.search_content(path, content, path)?;
+        
+        if let (Some(sparse), Some(modified)) = (&self.sparse, modified) {
+            sparse.record(path, modified, &file_match);
+        }
+        Ok(file_match)
+    }
+    
+    /// Download a URL and search it as a virtual file named after the URL
+    fn search_url(&self, url: &Path, record_throughput: &(dyn Fn(&str) + Sync)) -> CodeGrepResult<FileMatch> {
+        let content = fetch_url(&url.to_string_lossy())?;
+        record_throughput(&content);
         
-        Ok((file_matches, stats))
+        // `--stdin-filename` overrides the name used for language detection
+        let language_path = self
+            .cli
+            .stdin_filename
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| url.to_path_buf());
+        
+        self.search_content(url, content, &language_path)
     }
     
-    fn search_file(&self, path: &Path) -> CodeGrepResult<FileMatch> {
-        let content = fs::read_to_string(path)?;
+    /// Search already-loaded content; `language_path` decides language-specific filtering
+    fn search_content(&self, path: &Path, content: String, language_path: &Path) -> CodeGrepResult<FileMatch> {
+        // Apply structured search filters if needed. Lines they leave out are blanked rather than
+        // removed, so line numbers stay those of the file; `kept` marks the lines left in.
+        let filtered = if self.cli.is_structured_search() {
+            // Parsed lazily by the filters that need it, and at most once per file
+            let parsed = OnceCell::new();
+            Some(self.apply_structured_filters(&content, language_path, &parsed)?)
+        } else {
+            None
+        };
+        let (filtered_content, kept) = match filtered {
+            Some((ref text, ref kept)) => (text.as_str(), Some(kept)),
+            None => (content.as_str(), None),
+        };
+        let is_kept = |line_number: usize| kept.is_none_or(|kept| kept.get(line_number - 1) == Some(&true));
+        let line_range = self.cli.effective_line_range(&content);
+        
+        // --json-path / --yaml-path: only values at the key path are matched
+        if self.cli.searches_key_paths() {
+            let line_matches = match self.cli.key_path_for(language_path) {
+                Some(key_path) => self.find_in_key_path(filtered_content, language_path, key_path, line_range)?,
+                None => Vec::new(),
+            };
+            let line_matches = line_matches.into_iter().filter(|line_match| is_kept(line_match.line_number)).collect();
+            return Ok(FileMatch::new(path.to_path_buf(), line_matches));
+        }
+        
+        if self.cli.multiline {
+            let mut multiline_matches = self.matcher().find_matches_multiline(filtered_content);
+            if !self.cli.zero_length_matches {
+                multiline_matches.retain(|m| !m.text.is_empty());
+            }
+            if let Some(ref range) = line_range {
+                multiline_matches.retain(|m| range.contains(&m.start_line));
+            }
+            multiline_matches.retain(|m| is_kept(m.start_line));
+            return Ok(FileMatch::new(path.to_path_buf(), Vec::new()).with_multiline_matches(multiline_matches));
+        }
+        
+        // Only the totals are printed, so count spans without copying lines or match text
+        if self.cli.counts_only() {
+            let mut line_counts = count_in_text(
+                filtered_content,
+                self.matcher(),
+                self.line_filter.as_ref(),
+                self.cli.zero_length_matches,
+            );
+            if let Some(ref range) = line_range {
+                line_counts.retain(|(line_number, _)| range.contains(line_number));
+            }
+            line_counts.retain(|&(line_number, _)| is_kept(line_number));
+            return Ok(FileMatch::counted(path.to_path_buf(), &line_counts));
+        }
         
-        // Apply structured search filters if needed
-        let filtered_content = if self.cli.is_structured_search() {
-            self.apply_structured_filters(&content, path)?
+        let line_matches = if let Some(separator) = self.cli.record_separator() {
+            // Numbered by record rather than by line; blanked lines are just empty within a record
+            find_in_records(filtered_content, &separator, self.matcher())
         } else {
-            content
+            let mut line_matches = if self.cli.is_multiline_literal() {
+                find_in_text_multiline(filtered_content, self.matcher())
+            } else {
+                // --head: lines past the limit are not matched at all
+                let searched = match self.cli.head {
+                    Some(head) => first_lines(filtered_content, head),
+                    None => filtered_content,
+                };
+                find_in_text(searched, self.matcher(), self.line_filter.as_ref())
+            };
+            line_matches.retain(|line_match| is_kept(line_match.line_number));
+            line_matches
+        };
+        
+        // Position-only matches (e.g. `^`, lookaheads) are reported only on request
+        let line_matches = if self.cli.zero_length_matches {
+            line_matches
+        } else {
+            drop_zero_length_matches(line_matches)
+        };
+        
+        // Matches outside --range/--head/--tail are dropped; line numbers are unaffected
+        let line_matches = match line_range {
+            Some(range) => line_matches
+                .into_iter()
+                .filter(|line_match| range.contains(&line_match.line_number))
+                .collect(),
+            None => line_matches,
         };
         
-        let line_matches = find_in_text(&filtered_content, &self.matcher);
-        Ok(FileMatch::new(path.to_path_buf(), line_matches))
+        let mut file_match = FileMatch::new(path.to_path_buf(), line_matches);
+        
+        // Function ranges come from the file itself: structured filters blank the lines around a
+        // match, and with them the declarations that enclose it. Line numbers are the same.
+        if self.cli.show_function_boundaries && file_match.has_matches() {
+            let parsed = self.parser.parse(&content, language_path)?;
+            file_match = file_match.with_functions(parsed.functions);
+        }
+        
+        // AST paths likewise come from the file, so they go through the enclosing declarations
+        if self.cli.print_ast_path && file_match.has_matches() {
+            let ast_paths = ast_paths(&content, language_path, &file_match.line_matches)?;
+            file_match = file_match.with_ast_paths(ast_paths);
+        }
+        
+        // Keep the file's text around so context can be printed without re-reading
+        if self.cli.has_context() && file_match.has_matches() {
+            Ok(file_match.with_source(Arc::from(content)))
+        } else {
+            Ok(file_match)
+        }
     }
     
-    fn apply_structured_filters(&self, content: &str, path: &Path) -> CodeGrepResult<String> {
+    /// Match the pattern against the scalar values found at `key_path`, reporting each value's line.
+    /// Values on lines `--line-regex` does not match are skipped.
+    fn find_in_key_path(
+        &self,
+        content: &str,
+        path: &Path,
+        key_path: &str,
+        line_range: Option<RangeInclusive<usize>>,
+    ) -> CodeGrepResult<Vec<LineMatch>> {
+        let key_path = parse_key_path(key_path)?;
+        let values = if path.extension().is_some_and(|ext| ext == "json") {
+            json_values(content)?
+        } else {
+            yaml_values(content)
+        };
+        
+        let lines: Vec<&str> = content.lines().collect();
+        let mut line_matches: Vec<LineMatch> = Vec::new();
+        for value in values.iter().filter(|value| value.path == key_path) {
+            let line = lines[value.line - 1];
+            if self.line_filter.as_ref().is_some_and(|filter| !filter.is_match(line)) {
+                continue;
+            }
+            let matches: Vec<Match> = self
+                .matcher()
+                .find_matches(&line[value.start..value.end])
+                .into_iter()
+                .filter(|m| self.cli.zero_length_matches || !m.is_empty())
+                .map(|m| Match {
+                    start: m.start + value.start,
+                    end: m.end + value.start,
+                    text: m.text,
+                    captures: m.captures,
+                })
+                .collect();
+            if matches.is_empty() {
+                continue;
+            }
+            
+            // Several values can share a line in compact JSON
+            match line_matches.last_mut() {
+                Some(last) if last.line_number == value.line => last.matches.extend(matches),
+                _ => line_matches.push(LineMatch::new(value.line, line.to_string(), matches)),
+            }
+        }
+        
+        if let Some(range) = line_range {
+            line_matches.retain(|line_match| range.contains(&line_match.line_number));
+        }
+        Ok(line_matches)
+    }
+    
+    fn apply_structured_filters(
+        &self,
+        content: &str,
+        path: &Path,
+        parsed: &OnceCell<ParsedCode>,
+    ) -> CodeGrepResult<(String, Vec<bool>)> {
         // Basic structured search implementation
         let mut filtered_lines = Vec::new();
+        let mut kept = Vec::new();
         let lines: Vec<&str> = content.lines().collect();
+        let depths = if self.cli.top_level {
+            nesting_depths(&lines, path)
+        } else {
+            Vec::new()
+        };
+        let class_scopes = match self.cli.in_class {
+            Some(ref class_name) => {
+                class_scopes(self.parsed_code(parsed, content, path)?, &lines, path, class_name, self.cli.top_level)
+            }
+            None => Vec::new(),
+        };
+        let attribute_scopes = match self.cli.in_attribute {
+            Some(ref attribute) => attribute_scopes(self.parsed_code(parsed, content, path)?, attribute),
+            None => Vec::new(),
+        };
         
         for (line_num, line) in lines.iter().enumerate() {
             let mut include_line = true;
             
             // Comments only filter
             if self.cli.comments_only {
-                include_line = self.is_comment_line(line, path);
+                include_line = is_comment_line(line, path);
             }
             
             // Imports only filter
             if self.cli.imports_only {
-                include_line = self.is_import_line(line, path);
+                include_line = is_import_line(line, path);
             }
             
             // Function filter (basic implementation)
             if self.cli.functions {
-                include_line = self.is_function_line(line, path);
+                include_line = self.is_function_line(line, path)
+                    && (!self.cli.top_level || depths[line_num] == 0);
             }
             
             // Specific function filter
             if let Some(ref func_name) = self.cli.in_function {
-                include_line = self.is_in_function(line_num, &lines, func_name, path);
+                let parsed = self.parsed_code(parsed, content, path)?;
+                include_line = self.is_in_function(line_num, &lines, func_name, parsed);
             }
             
             // Specific class filter
-            if let Some(ref class_name) = self.cli.in_class {
-                include_line = self.is_in_class(line_num, &lines, class_name, path);
+            if self.cli.in_class.is_some() {
+                include_line = class_scopes.iter().any(|scope| scope.contains(&(line_num + 1)));
             }
             
-            if include_line {
-                filtered_lines.push(*line);
+            // Attribute filter
+            if self.cli.in_attribute.is_some() {
+                include_line = attribute_scopes.iter().any(|scope| scope.contains(&(line_num + 1)));
             }
+            
+            filtered_lines.push(if include_line { *line } else { "" });
+            kept.push(include_line);
         }
         
-        Ok(filtered_lines.join("\n"))
-    }
-    
-    fn is_comment_line(&self, line: &str, path: &Path) -> bool {
-        let trimmed = line.trim();
-        
-        match path.extension().and_then(|e| e.to_str()) {
-            Some("rs") | Some("js") | Some("ts") | Some("go") | Some("java") | Some("c") | Some("cpp") => {
-                trimmed.starts_with("//") || trimmed.starts_with("/*") || trimmed.ends_with("*/")
-            }
-            Some("py") => trimmed.starts_with("#"),
-            Some("rb") => trimmed.starts_with("#"),
-            Some("sh") => trimmed.starts_with("#"),
-            _ => trimmed.starts_with("#") || trimmed.starts_with("//"),
-        }
+        Ok((filtered_lines.join("\n"), kept))
     }
     
-    fn is_import_line(&self, line: &str, path: &Path) -> bool {
-        let trimmed = line.trim();
-        
-        match path.extension().and_then(|e| e.to_str()) {
-            Some("rs") => trimmed.starts_with("use ") || trimmed.starts_with("extern crate"),
-            Some("go") => trimmed.starts_with("import "),
-            Some("js") | Some("ts") => {
-                trimmed.starts_with("import ") || trimmed.starts_with("const ") && trimmed.contains("require(")
-            }
-            Some("py") => trimmed.starts_with("import ") || trimmed.starts_with("from "),
-            Some("java") => trimmed.starts_with("import "),
-            _ => trimmed.contains("import") || trimmed.contains("require"),
+    /// Parse the file on first use; later calls reuse the cached result
+    fn parsed_code<'a>(
+        &self,
+        parsed: &'a OnceCell<ParsedCode>,
+        content: &str,
+        path: &Path,
+    ) -> CodeGrepResult<&'a ParsedCode> {
+        if let Some(parsed_code) = parsed.get() {
+            return Ok(parsed_code);
         }
+        let parsed_code = self.parser.parse(content, path)?;
+        Ok(parsed.get_or_init(|| parsed_code))
     }
     
     fn is_function_line(&self, line: &str, path: &Path) -> bool {
@@ -242,45 +751,149 @@ impl SearchEngine {
         }
     }
     
-    fn is_in_function(&self, line_num: usize, lines: &[&str], func_name: &str, path: &Path) -> bool {
-        // Basic implementation: check if we're inside a function with the given name
-        // This is a simplified version - a full implementation would need proper parsing
+    fn is_in_function(&self, line_num: usize, lines: &[&str], func_name: &str, parsed: &ParsedCode) -> bool {
+        // Nearest preceding function definition whose signature mentions the name
+        let definition = parsed
+            .functions
+            .iter()
+            .filter(|func| func.start_line <= line_num + 1 && func.signature.contains(func_name))
+            .map(|func| func.start_line - 1)
+            .max();
         
-        // Look backwards for function definition
-        for i in (0..=line_num).rev() {
-            let line = lines[i].trim();
-            if self.is_function_line(line, path) && line.contains(func_name) {
-                // Found the function, now check if we're still inside it
-                let mut brace_count = 0;
-                for j in i..=line_num {
-                    let check_line = lines[j];
-                    brace_count += check_line.matches('{').count() as i32;
-                    brace_count -= check_line.matches('}').count() as i32;
+        // Found the function, now check if we're still inside it
+        definition.is_some_and(|start| brace_balance(&lines[start..=line_num]) > 0)
+    }
+}
+
+/// Runs the search of `path`, turning a panic into a warning and no result. Panics only unwind
+/// (and can be caught) because the release profile keeps `panic = "unwind"`.
+fn isolate_panic(path: &Path, search: impl FnOnce() -> CodeGrepResult<FileMatch>) -> Option<FileMatch> {
+    match panic::catch_unwind(AssertUnwindSafe(search)) {
+        Ok(result) => result.ok(),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            eprintln!("Warning: {}: search aborted: {}; skipping file", path.display(), message);
+            None
+        }
+    }
+}
+
+/// Line ranges (1-based, inclusive) of the classes named exactly `class_name`, from the
+/// definition to the brace (or dedent) that closes it as matched by the parser, so a later class
+/// is never mistaken for part of an earlier one. With `top_level`, nested classes are left out.
+fn class_scopes(
+    parsed: &ParsedCode,
+    lines: &[&str],
+    path: &Path,
+    class_name: &str,
+    top_level: bool,
+) -> Vec<RangeInclusive<usize>> {
+    let depths = if top_level { nesting_depths(lines, path) } else { Vec::new() };
+    parsed
+        .classes
+        .iter()
+        .filter(|class| class_identifier(&class.name) == class_name)
+        .filter(|class| !top_level || depths.get(class.start_line - 1) == Some(&0))
+        .map(|class| class.start_line..=class.end_line.max(class.start_line))
+        .collect()
+}
+
+/// Line ranges (1-based, inclusive) of the functions and types annotated with `attribute`. The
+/// attribute may be anywhere in the run of attributes and comments directly above the definition.
+pub(crate) fn attribute_scopes(parsed: &ParsedCode, attribute: &str) -> Vec<RangeInclusive<usize>> {
+    let annotated = |start_line: usize| {
+        let mut line = start_line - 1;
+        loop {
+            let mut on_line = parsed.attributes.iter().filter(|attr| attr.line == line).peekable();
+            if on_line.peek().is_some() {
+                if on_line.any(|attr| attr.name == attribute) {
+                    return true;
                 }
-                return brace_count > 0;
+            } else if !parsed.comments.iter().any(|comment| comment.line == line) {
+                return false;
             }
+            line -= 1;
         }
-        false
+    };
+    
+    let functions = parsed.functions.iter().map(|func| (func.start_line, func.end_line));
+    let classes = parsed.classes.iter().map(|class| (class.start_line, class.end_line));
+    functions
+        .chain(classes)
+        .filter(|&(start, _)| annotated(start))
+        .map(|(start, end)| start..=end.max(start))
+        .collect()
+}
+
+/// The name of a class as the parser took it from the definition, without what followed it
+/// there (`A{`, `Foo<T>`, `Point:`)
+fn class_identifier(name: &str) -> &str {
+    let end = name.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$')).unwrap_or(name.len());
+    &name[..end]
+}
+
+fn is_comment_line(line: &str, path: &Path) -> bool {
+    let trimmed = line.trim();
+    
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("rs") | Some("js") | Some("ts") | Some("go") | Some("java") | Some("c") | Some("cpp") => {
+            trimmed.starts_with("//") || trimmed.starts_with("/*") || trimmed.ends_with("*/")
+        }
+        Some("py") => trimmed.starts_with("#"),
+        Some("rb") => trimmed.starts_with("#"),
+        Some("sh") => trimmed.starts_with("#"),
+        _ => trimmed.starts_with("#") || trimmed.starts_with("//"),
     }
+}
+
+fn is_import_line(line: &str, path: &Path) -> bool {
+    let trimmed = line.trim();
     
-    fn is_in_class(&self, line_num: usize, lines: &[&str], class_name: &str, _path: &Path) -> bool {
-        // Similar to is_in_function but for classes
-        for i in (0..=line_num).rev() {
-            let line = lines[i].trim();
-            if line.starts_with("class ") && line.contains(class_name) {
-                let mut brace_count = 0;
-                for j in i..=line_num {
-                    let check_line = lines[j];
-                    brace_count += check_line.matches('{').count() as i32;
-                    brace_count -= check_line.matches('}').count() as i32;
-                }
-                return brace_count > 0;
-            }
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("rs") => trimmed.starts_with("use ") || trimmed.starts_with("extern crate"),
+        Some("go") => trimmed.starts_with("import "),
+        Some("js") | Some("ts") => {
+            trimmed.starts_with("import ") || trimmed.starts_with("const ") && trimmed.contains("require(")
         }
-        false
+        Some("py") => trimmed.starts_with("import ") || trimmed.starts_with("from "),
+        Some("java") => trimmed.starts_with("import "),
+        _ => trimmed.contains("import") || trimmed.contains("require"),
     }
 }
 
+/// Net number of opened braces across the given lines
+fn brace_balance(lines: &[&str]) -> i32 {
+    lines
+        .iter()
+        .map(|line| line.matches('{').count() as i32 - line.matches('}').count() as i32)
+        .sum()
+}
+
+/// Nesting depth at the start of each line: indentation for Python, brace depth elsewhere
+fn nesting_depths(lines: &[&str], path: &Path) -> Vec<usize> {
+    if path.extension().and_then(|e| e.to_str()) == Some("py") {
+        return lines
+            .iter()
+            .map(|line| line.len() - line.trim_start().len())
+            .collect();
+    }
+    
+    let mut depth = 0i32;
+    lines
+        .iter()
+        .map(|line| {
+            let current = depth.max(0) as usize;
+            depth += line.matches('{').count() as i32;
+            depth -= line.matches('}').count() as i32;
+            current
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -313,4 +926,483 @@ mod tests {
         assert_eq!(results[0].total_matches, 1);
         assert!(stats.files_searched > 0);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_search_spooled_keeps_order() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..6 {
+            create_test_file(temp_dir.path(), &format!("file{}.txt", i), "needle\nhay\nneedle");
+        }
+        
+        let cli = Cli {
+            pattern: Some("needle".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        
+        let engine = SearchEngine::new(cli).unwrap();
+        let (spool, stats) = engine.search_spooled(2).unwrap();
+        assert!(spool.spilled() > 0);
+        assert_eq!(stats.files_with_matches, 6);
+        
+        let results = spool.into_vec().unwrap();
+        let names: Vec<_> = results
+            .iter()
+            .map(|fm| fm.path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        let expected: Vec<_> = (0..6).map(|i| format!("file{}.txt", i)).collect();
+        assert_eq!(names, expected);
+        assert!(results.iter().all(|fm| fm.total_matches == 2));
+    }
+    
+    #[test]
+    fn test_search_git_tracked_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let init = std::process::Command::new("git")
+            .arg("-C")
+            .arg(temp_dir.path())
+            .args(["init", "-q"])
+            .status()
+            .unwrap();
+        assert!(init.success());
+        create_test_file(temp_dir.path(), "tracked.txt", "needle");
+        create_test_file(temp_dir.path(), "untracked.txt", "needle");
+        let add = std::process::Command::new("git")
+            .arg("-C")
+            .arg(temp_dir.path())
+            .args(["add", "tracked.txt"])
+            .status()
+            .unwrap();
+        assert!(add.success());
+        
+        let cli = Cli {
+            pattern: Some("needle".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            git_tracked: true,
+            ..Default::default()
+        };
+        let (results, stats) = SearchEngine::new(cli).unwrap().search().unwrap();
+        
+        assert_eq!(stats.files_searched, 1);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("tracked.txt"));
+    }
+    
+    #[test]
+    fn test_search_keeps_source_for_context() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(temp_dir.path(), "test.txt", "one\ntwo\nthree");
+        
+        let cli = Cli {
+            pattern: Some("two".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        let (results, _) = SearchEngine::new(cli.clone()).unwrap().search().unwrap();
+        assert!(results[0].source.is_none());
+        
+        let cli = Cli { context: 1, ..cli };
+        let (results, _) = SearchEngine::new(cli).unwrap().search().unwrap();
+        assert_eq!(
+            results[0].source_records(None),
+            Some(vec!["one", "two", "three"])
+        );
+    }
+    
+    #[test]
+    fn test_top_level_functions_only() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(
+            temp_dir.path(),
+            "app.js",
+            "function outer() {\n    function inner() {\n        return 1;\n    }\n}\nfunction other() {}",
+        );
+        create_test_file(
+            temp_dir.path(),
+            "app.py",
+            "def outer():\n    def inner():\n        pass\n\ndef other():\n    pass",
+        );
+        
+        let cli = Cli {
+            pattern: Some("inner|outer|other".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            functions: true,
+            top_level: true,
+            ..Default::default()
+        };
+        let (results, _) = SearchEngine::new(cli).unwrap().search().unwrap();
+        
+        assert_eq!(results.len(), 2);
+        for file_match in &results {
+            let texts: Vec<_> = file_match.line_matches.iter().map(|lm| lm.line_text.trim()).collect();
+            assert_eq!(texts.len(), 2, "{:?}", texts);
+            assert!(texts.iter().all(|text| !text.contains("inner")));
+        }
+    }
+    
+    #[test]
+    fn test_in_function_uses_parsed_definitions() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(
+            temp_dir.path(),
+            "lib.rs",
+            "fn main() {\n    helper();\n}\n\nfn helper() {\n    let x = 1;\n}\nlet y = 2;",
+        );
+        
+        let cli = Cli {
+            pattern: Some("let|helper".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            in_function: Some("helper".to_string()),
+            ..Default::default()
+        };
+        let (results, _) = SearchEngine::new(cli).unwrap().search().unwrap();
+        
+        assert_eq!(results.len(), 1);
+        let lines: Vec<_> = results[0].line_matches.iter().map(|lm| (lm.line_number, lm.line_text.trim())).collect();
+        assert_eq!(lines, vec![(5, "fn helper() {"), (6, "let x = 1;")]);
+    }
+    
+    #[test]
+    fn test_in_attribute() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(
+            temp_dir.path(),
+            "lib.rs",
+            "fn helper() {\n    let x = 1;\n}\n\n#[test]\n/// Checks the helper\nfn check() {\n    let y = 2;\n}\n\n#[cfg(test)]\nfn setup() {\n    let z = 3;\n}",
+        );
+        
+        let cli = Cli {
+            pattern: Some("let".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            in_attribute: Some("test".to_string()),
+            ..Default::default()
+        };
+        let (results, _) = SearchEngine::new(cli.clone()).unwrap().search().unwrap();
+        
+        assert_eq!(results.len(), 1);
+        let lines: Vec<_> = results[0].line_matches.iter().map(|lm| (lm.line_number, lm.line_text.trim())).collect();
+        assert_eq!(lines, vec![(8, "let y = 2;")]);
+        
+        // Replacement edits the reported line of the file itself
+        let replacer = crate::Replacer::new(PatternMatcher::Literal("let".to_string()), "LET".to_string(), cli);
+        let result = replacer.replace_in_file(&results[0]).unwrap().unwrap();
+        assert_eq!(result.lines_affected, vec![8]);
+    }
+    
+    #[test]
+    fn test_search_paths_explicit_list() {
+        let temp_dir = TempDir::new().unwrap();
+        let listed = create_test_file(temp_dir.path(), "b.txt", "needle");
+        let hidden = create_test_file(temp_dir.path(), ".hidden.txt", "needle");
+        create_test_file(temp_dir.path(), "a.txt", "needle");
+        
+        let cli = Cli {
+            pattern: Some("needle".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        let engine = SearchEngine::new(cli).unwrap();
+        
+        // Only the listed files are searched, in the given order, even ones a walk would skip
+        let (results, stats) = engine.search_paths(&[listed.clone(), hidden.clone()]).unwrap();
+        let paths: Vec<_> = results.iter().map(|fm| fm.path.clone()).collect();
+        assert_eq!(paths, vec![listed, hidden]);
+        assert_eq!(stats.files_searched, 2);
+        
+        let (results, _) = engine.search().unwrap();
+        assert_eq!(results.len(), 2);
+    }
+    
+    #[test]
+    fn test_search_strips_bom() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(temp_dir.path(), "bom.rs", "\u{feff}fn main() {}\nfn other() {}");
+        
+        let cli = Cli {
+            pattern: Some("^fn".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            regex: true,
+            ..Default::default()
+        };
+        let (results, _) = SearchEngine::new(cli).unwrap().search().unwrap();
+        
+        let line_matches = &results[0].line_matches;
+        assert_eq!(line_matches.len(), 2);
+        assert_eq!(line_matches[0].line_text, "fn main() {}");
+        assert_eq!(line_matches[0].matches[0].start, 0);
+    }
+    
+    #[test]
+    fn test_stats_count_bytes_and_lines_searched() {
+        let temp_dir = TempDir::new().unwrap();
+        let files = [
+            create_test_file(temp_dir.path(), "a.txt", "needle\nhay\n"),
+            create_test_file(temp_dir.path(), "b.txt", "hay\nhay\nhay"),
+            create_test_file(temp_dir.path(), "c.txt", "\u{feff}needle"),
+        ];
+        
+        let cli = Cli {
+            pattern: Some("needle".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        
+        let engine = SearchEngine::new(cli).unwrap();
+        let (_results, stats) = engine.search().unwrap();
+        
+        let total_size: u64 = files.iter().map(|path| fs::metadata(path).unwrap().len()).sum();
+        assert_eq!(stats.files_searched, 3);
+        assert_eq!(stats.bytes_searched, total_size);
+        assert_eq!(stats.lines_searched, 6);
+    }
+    
+    #[test]
+    fn test_search_continues_after_panic_in_one_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let good = create_test_file(temp_dir.path(), "good.txt", "needle");
+        let bad = create_test_file(temp_dir.path(), "bad.txt", "needle");
+        
+        let cli = Cli {
+            pattern: Some("needle".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        let engine = SearchEngine::new(cli).unwrap();
+        let record_throughput = |_: &str| {};
+        
+        let results: Vec<FileMatch> = [&bad, &good]
+            .par_iter()
+            .filter_map(|path| {
+                isolate_panic(path, || {
+                    if path.ends_with("bad.txt") {
+                        panic!("deliberate failure");
+                    }
+                    engine.search_file(path, &record_throughput)
+                })
+            })
+            .collect();
+        
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("good.txt") && results[0].total_matches == 1);
+    }
+    
+    #[test]
+    fn test_case_folding_keeps_offsets_of_the_file() {
+        let temp_dir = TempDir::new().unwrap();
+        // Lowercasing 'İ' grows it by a byte, so offsets in the folded text would be off by one
+        create_test_file(temp_dir.path(), "a.txt", "\u{130}needle \u{130}NEEDLE");
+        
+        let cli = Cli {
+            pattern: Some("needle".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        let (results, _) = SearchEngine::new(cli).unwrap().search().unwrap();
+        
+        let matches: Vec<_> = results[0].line_matches[0].matches.iter().map(|m| (m.start, m.text.as_str())).collect();
+        assert_eq!(matches, vec![(2, "needle"), (11, "NEEDLE")]);
+    }
+    
+    #[test]
+    fn test_search_line_range() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(temp_dir.path(), "test.txt", "needle\nhay\nneedle\nhay\nneedle");
+        
+        let cli = Cli {
+            pattern: Some("needle".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            range: Some("2:4".to_string()),
+            ..Default::default()
+        };
+        
+        let engine = SearchEngine::new(cli).unwrap();
+        let (results, _stats) = engine.search().unwrap();
+        let lines: Vec<usize> = results[0].line_matches.iter().map(|lm| lm.line_number).collect();
+        assert_eq!(lines, vec![3]);
+        assert_eq!(results[0].total_matches, 1);
+    }
+    
+    #[test]
+    fn test_count_only_matches_full_search() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(temp_dir.path(), "a.rs", "foo foo\nbar\nfoofoo foo\n");
+        create_test_file(temp_dir.path(), "b.rs", "nothing\nfoo\n");
+        
+        let cli = Cli {
+            pattern: Some("foo".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        let (_full_results, full_stats) = SearchEngine::new(cli.clone()).unwrap().search().unwrap();
+        
+        let count_cli = Cli { count_only: true, ..cli };
+        assert!(count_cli.counts_only());
+        let (count_results, count_stats) = SearchEngine::new(count_cli).unwrap().search().unwrap();
+        
+        assert!(count_results.iter().all(|fm| fm.line_matches.is_empty()));
+        assert_eq!(count_stats.total_matches, full_stats.total_matches);
+        assert_eq!(count_stats.total_lines, full_stats.total_lines);
+        assert_eq!(count_stats.total_matches, 6);
+    }
+    
+    #[test]
+    fn test_search_json_and_yaml_key_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(
+            temp_dir.path(),
+            "config.json",
+            "{\n  \"database\": {\n    \"host\": \"db.internal\",\n    \"user\": \"db.internal\"\n  }\n}\n",
+        );
+        create_test_file(temp_dir.path(), "config.yaml", "cache:\n  host: db.internal\ndatabase:\n  host: db.internal\n");
+        create_test_file(temp_dir.path(), "notes.txt", "database.host = db.internal\n");
+        
+        let cli = Cli {
+            pattern: Some("internal".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            json_path: Some("database.host".to_string()),
+            yaml_path: Some("database.host".to_string()),
+            ..Default::default()
+        };
+        let engine = SearchEngine::new(cli.clone()).unwrap();
+        let (results, _stats) = engine.search().unwrap();
+        
+        let mut found: Vec<(String, usize, usize)> = results
+            .iter()
+            .map(|fm| {
+                let name = fm.path.file_name().unwrap().to_string_lossy().into_owned();
+                let line_match = &fm.line_matches[0];
+                (name, line_match.line_number, line_match.matches[0].start)
+            })
+            .collect();
+        found.sort();
+        assert_eq!(found, vec![("config.json".to_string(), 3, 16), ("config.yaml".to_string(), 4, 11)]);
+        assert!(results.iter().all(|fm| fm.total_matches == 1));
+        
+        // --line-regex still decides which lines the values are matched on
+        let indented = Cli { line_regex: Some(r"^\s{4}".to_string()), ..cli };
+        let (results, _stats) = SearchEngine::new(indented).unwrap().search().unwrap();
+        let names: Vec<_> = results.iter().map(|fm| fm.path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(names, ["config.json"]);
+    }
+    
+    #[test]
+    fn test_file_count_estimate_settles_on_walked_count() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(temp_dir.path(), "a.rs", "fn a() {}");
+        create_test_file(temp_dir.path(), "b.rs", "fn b() {}");
+        fs::create_dir(temp_dir.path().join("src")).unwrap();
+        create_test_file(&temp_dir.path().join("src"), "c.rs", "fn c() {}");
+        
+        let cli = Cli {
+            pattern: Some("fn".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        
+        // Nothing is estimated unless someone asks for the count
+        let engine = SearchEngine::new(cli.clone()).unwrap();
+        engine.search().unwrap();
+        assert!(engine.file_count_estimate.get().is_none());
+        
+        // 2 top-level files + 1 directory holding 1 entry, over the assumed depth
+        let engine = SearchEngine::new(cli).unwrap();
+        let estimate = engine.file_count_estimate();
+        engine.store_file_count_estimate(0);
+        assert_eq!(estimate.load(Ordering::Relaxed), 2 + 3);
+        engine.search().unwrap();
+        assert_eq!(estimate.load(Ordering::Relaxed), 3);
+    }
+    
+    #[test]
+    fn test_limit_files() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..5 {
+            create_test_file(temp_dir.path(), &format!("file{}.txt", i), "needle");
+        }
+        
+        let cli = Cli {
+            pattern: Some("needle".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            limit_files: Some(2),
+            ..Default::default()
+        };
+        let (results, stats) = SearchEngine::new(cli).unwrap().search().unwrap();
+        assert_eq!(stats.files_searched, 2);
+        let names: Vec<_> = results.iter().map(|fm| fm.path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(names, ["file0.txt", "file1.txt"]);
+    }
+    
+    #[test]
+    fn test_search_head_and_tail() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(temp_dir.path(), "test.txt", "#!/bin/sh\n# needle\nhay\nneedle\nhay\nneedle");
+        
+        let lines = |cli: Cli| -> Vec<usize> {
+            let cli = Cli {
+                pattern: Some("needle".to_string()),
+                paths: vec![temp_dir.path().to_path_buf()],
+                ..cli
+            };
+            let (results, _) = SearchEngine::new(cli).unwrap().search().unwrap();
+            results.iter().flat_map(|fm| fm.line_matches.iter().map(|lm| lm.line_number)).collect()
+        };
+        
+        assert_eq!(lines(Cli { head: Some(3), ..Default::default() }), vec![2]);
+        assert_eq!(lines(Cli { head: Some(1), ..Default::default() }), Vec::<usize>::new());
+        assert_eq!(lines(Cli { tail: Some(2), ..Default::default() }), vec![6]);
+        assert_eq!(lines(Cli { tail: Some(3), range: Some("1:5".to_string()), ..Default::default() }), vec![4]);
+        
+        let counted = Cli {
+            pattern: Some("needle".to_string()),
+            paths: vec![temp_dir.path().to_path_buf()],
+            head: Some(4),
+            count_only: true,
+            ..Default::default()
+        };
+        let (results, _) = SearchEngine::new(counted).unwrap().search().unwrap();
+        assert_eq!(results[0].total_matches, 2);
+    }
+    
+    #[test]
+    fn test_structure_comes_from_the_unfiltered_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = create_test_file(temp_dir.path(), "lib.rs", "impl Widget {\n    fn alpha() {\n        // needle\n    }\n}\n");
+        let cli = Cli {
+            pattern: Some("needle".to_string()),
+            paths: vec![path],
+            comments_only: true,
+            show_function_boundaries: true,
+            print_ast_path: cfg!(feature = "tree-sitter"),
+            ..Default::default()
+        };
+        
+        let (results, _) = SearchEngine::new(cli).unwrap().search().unwrap();
+        let function = results[0].enclosing_function(3).unwrap();
+        assert_eq!((function.name.as_str(), function.start_line, function.end_line), ("alpha", 2, 4));
+        #[cfg(feature = "tree-sitter")]
+        assert!(results[0].ast_path(3).unwrap().starts_with("source_file/impl_item/declaration_list/function_item/"));
+    }
+    
+    #[test]
+    fn test_tail_counts_lines_of_the_file_with_structured_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        // Trailing blank lines are dropped from the filtered text, but still count for --tail
+        let path = create_test_file(temp_dir.path(), "test.sh", "# needle\n# needle\n\n\n");
+        let cli = Cli {
+            pattern: Some("needle".to_string()),
+            replace: Some("pin".to_string()),
+            paths: vec![path],
+            comments_only: true,
+            tail: Some(3),
+            ..Default::default()
+        };
+        
+        let (results, _) = SearchEngine::new(cli.clone()).unwrap().search().unwrap();
+        let lines: Vec<usize> = results[0].line_matches.iter().map(|lm| lm.line_number).collect();
+        assert_eq!(lines, vec![2]);
+        
+        let replacer = crate::Replacer::new(PatternMatcher::new(&cli).unwrap(), "pin".to_string(), cli);
+        let result = replacer.replace_in_file(&results[0]).unwrap().unwrap();
+        assert_eq!(result.lines_affected, lines);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{Cli, CodeGrepResult, FileMatch};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// State file `--sparse` keeps in the current directory
+pub const SPARSE_STATE_FILE: &str = ".cg_state.json";
+
+/// `--sparse` の検索状態
+///
+/// パターン（と検索オプション）とファイルの組ごとに、前回検索したときの更新時刻と結果を保持する。
+/// 更新時刻が変わっていないファイルはファイルを読まずに前回の結果を返す。
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SparseState {
+    /// Keyed by `pattern_hash:path_hash`; files are searched in parallel, so lookups and
+    /// records only hold the lock while touching the map
+    entries: Mutex<HashMap<String, SparseEntry>>,
+    /// Hash of the pattern and every option that can change a file's result
+    #[serde(skip)]
+    pattern_hash: String,
+    #[serde(skip)]
+    file: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SparseEntry {
+    path: PathBuf,
+    /// Modification time since the Unix epoch when the file was searched
+    mtime: Duration,
+    /// SHA-256 of `result` as JSON, so a damaged entry is searched again instead of trusted
+    result_hash: String,
+    /// Stored without `source`; the file is unchanged, so it can be read again when context needs it
+    result: FileMatch,
+}
+
+/// The options that decide what a search finds in one file. Paths, output formatting and the
+/// like are left out, so runs that differ only in those share results.
+#[derive(Serialize)]
+struct ResultOptions<'a> {
+    pattern: &'a Option<String>,
+    saved: &'a Option<String>,
+    regex: bool,
+    fancy_regex: bool,
+    case_sensitive: bool,
+    ignore_case_pattern: bool,
+    word_boundary: bool,
+    literal: bool,
+    glob_pattern: bool,
+    infix_pattern: bool,
+    ignore_whitespace: bool,
+    match_newlines_literal: bool,
+    multiline: bool,
+    zero_length_matches: bool,
+    split_on: &'a Option<String>,
+    range: &'a Option<String>,
+    head: Option<usize>,
+    tail: Option<usize>,
+    line_regex: &'a Option<String>,
+    json_path: &'a Option<String>,
+    yaml_path: &'a Option<String>,
+    trim_path_prefix: &'a Option<PathBuf>,
+    counts_only: bool,
+    todos: bool,
+    approx: Option<usize>,
+    fast: bool,
+    thorough: bool,
+    functions: bool,
+    top_level: bool,
+    show_function_boundaries: bool,
+    print_ast_path: bool,
+    in_function: &'a Option<String>,
+    in_class: &'a Option<String>,
+    in_attribute: &'a Option<String>,
+    in_scope: &'a [String],
+    imports_only: bool,
+    comments_only: bool,
+    and: &'a [String],
+    or: &'a [String],
+}
+
+impl<'a> ResultOptions<'a> {
+    fn new(cli: &'a Cli) -> Self {
+        Self {
+            pattern: &cli.pattern,
+            saved: &cli.saved,
+            regex: cli.regex,
+            fancy_regex: cli.fancy_regex,
+            case_sensitive: cli.case_sensitive,
+            ignore_case_pattern: cli.ignore_case_pattern,
+            word_boundary: cli.word_boundary,
+            literal: cli.literal,
+            glob_pattern: cli.glob_pattern,
+            infix_pattern: cli.infix_pattern,
+            ignore_whitespace: cli.ignore_whitespace,
+            match_newlines_literal: cli.match_newlines_literal,
+            multiline: cli.multiline,
+            zero_length_matches: cli.zero_length_matches,
+            split_on: &cli.split_on,
+            range: &cli.range,
+            head: cli.head,
+            tail: cli.tail,
+            line_regex: &cli.line_regex,
+            json_path: &cli.json_path,
+            yaml_path: &cli.yaml_path,
+            trim_path_prefix: &cli.trim_path_prefix,
+            counts_only: cli.counts_only(),
+            todos: cli.todos,
+            approx: cli.approx,
+            fast: cli.fast,
+            thorough: cli.thorough,
+            functions: cli.functions,
+            top_level: cli.top_level,
+            show_function_boundaries: cli.show_function_boundaries,
+            print_ast_path: cli.print_ast_path,
+            in_function: &cli.in_function,
+            in_class: &cli.in_class,
+            in_attribute: &cli.in_attribute,
+            in_scope: &cli.in_scope,
+            imports_only: cli.imports_only,
+            comments_only: cli.comments_only,
+            and: &cli.and,
+            or: &cli.or,
+        }
+    }
+}
+
+impl SparseState {
+    /// Loads the state kept in `file` for the search `cli` describes. A missing or unreadable
+    /// state file starts an empty state, so the first `--sparse` run searches everything.
+    pub fn load(file: &Path, cli: &Cli) -> Self {
+        let mut state: SparseState = fs::read_to_string(file)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        let options = serde_json::to_vec(&ResultOptions::new(cli)).unwrap_or_default();
+        state.pattern_hash = sha256_hex(&options);
+        state.file = file.to_path_buf();
+        state
+    }
+
+    /// The result stored for `path` if the file has not been modified since it was searched
+    pub fn lookup(&self, path: &Path, modified: SystemTime) -> Option<FileMatch> {
+        let key = self.key(path);
+        let entry = self.entries.lock().unwrap().get(&key)?.clone();
+        if entry.mtime != since_epoch(modified) {
+            return None;
+        }
+        let result = serde_json::to_vec(&entry.result).ok()?;
+        (sha256_hex(&result) == entry.result_hash).then_some(entry.result)
+    }
+
+    /// Remember the result of searching `path` as it was at `modified`
+    pub fn record(&self, path: &Path, modified: SystemTime, file_match: &FileMatch) {
+        let result = FileMatch { source: None, ..file_match.clone() };
+        let Ok(json) = serde_json::to_vec(&result) else {
+            return;
+        };
+        let entry = SparseEntry {
+            path: path.to_path_buf(),
+            mtime: since_epoch(modified),
+            result_hash: sha256_hex(&json),
+            result,
+        };
+        let key = self.key(path);
+        self.entries.lock().unwrap().insert(key, entry);
+    }
+
+    /// Write the state back, dropping entries for files that no longer exist
+    pub fn save(&self) -> CodeGrepResult<()> {
+        self.entries.lock().unwrap().retain(|_, entry| entry.path.is_file());
+        fs::write(&self.file, serde_json::to_string(self).map_err(std::io::Error::other)?)?;
+        Ok(())
+    }
+
+    fn key(&self, path: &Path) -> String {
+        format!("{}:{}", self.pattern_hash, sha256_hex(path.to_string_lossy().as_bytes()))
+    }
+}
+
+fn since_epoch(time: SystemTime) -> Duration {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default()
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LineMatch, Match, OutputFormat};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_sparse_state_reuses_unchanged_results() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state_file = temp_dir.path().join(SPARSE_STATE_FILE);
+        let source = temp_dir.path().join("a.rs");
+        fs::write(&source, "fn main() {}").unwrap();
+        let modified = fs::metadata(&source).unwrap().modified().unwrap();
+
+        let cli = Cli { pattern: Some("main".to_string()), sparse: true, ..Default::default() };
+        let file_match = FileMatch::new(
+            source.clone(),
+            vec![LineMatch::new(1, "fn main() {}".to_string(), vec![Match { start: 3, end: 7, text: "main".to_string(), captures: Vec::new() }])],
+        );
+        let state = SparseState::load(&state_file, &cli);
+        assert!(state.lookup(&source, modified).is_none());
+        state.record(&source, modified, &file_match);
+        state.save().unwrap();
+
+        let state = SparseState::load(&state_file, &cli);
+        assert_eq!(state.lookup(&source, modified).unwrap().total_matches, 1);
+        assert!(state.lookup(&source, modified + Duration::from_secs(1)).is_none());
+
+        // Another pattern has its own results
+        let other = Cli { pattern: Some("fn".to_string()), ..cli.clone() };
+        assert!(SparseState::load(&state_file, &other).lookup(&source, modified).is_none());
+
+        // Options that only change how results are shown share them
+        let shown_differently = Cli { output: OutputFormat::Json, context: 2, verbose: 1, ..cli.clone() };
+        assert!(SparseState::load(&state_file, &shown_differently).lookup(&source, modified).is_some());
+
+        // The file's text is not stored along with the result
+        let state = SparseState::load(&state_file, &cli);
+        state.record(&source, modified, &file_match.clone().with_source(Arc::from("fn main() {}")));
+        assert!(state.lookup(&source, modified).unwrap().source.is_none());
+
+        // A result that does not match its hash is not trusted
+        let json = fs::read_to_string(&state_file).unwrap();
+        fs::write(&state_file, json.replace("\"total_matches\":1", "\"total_matches\":9")).unwrap();
+        assert!(SparseState::load(&state_file, &cli).lookup(&source, modified).is_none());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{CodeGrepError, CodeGrepResult, FileMatch};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+
+/// 検索結果の一時保管領域
+///
+/// メモリ上の結果数が閾値を超えると、古い結果から一時ファイルへ退避する。
+/// 結果は追加された順序のまま読み出される。
+pub struct ResultSpool {
+    threshold: usize,
+    buffer: Vec<FileMatch>,
+    spill_file: Option<BufWriter<File>>,
+    spilled: usize,
+}
+
+impl ResultSpool {
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            buffer: Vec::new(),
+            spill_file: None,
+            spilled: 0,
+        }
+    }
+
+    pub fn push(&mut self, file_match: FileMatch) -> CodeGrepResult<()> {
+        self.buffer.push(file_match);
+
+        if self.buffer.len() > self.threshold {
+            self.spill()?;
+        }
+
+        Ok(())
+    }
+
+    /// Total number of results held, both in memory and on disk
+    pub fn len(&self) -> usize {
+        self.spilled + self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of results that have been written to the spill file
+    pub fn spilled(&self) -> usize {
+        self.spilled
+    }
+
+    fn spill(&mut self) -> CodeGrepResult<()> {
+        if self.spill_file.is_none() {
+            self.spill_file = Some(BufWriter::new(tempfile::tempfile()?));
+        }
+        let writer = self.spill_file.as_mut().unwrap();
+
+        for file_match in self.buffer.drain(..) {
+            serde_json::to_writer(&mut *writer, &file_match)
+                .map_err(|e| CodeGrepError::Search(format!("Failed to spill results: {}", e)))?;
+            writer.write_all(b"\n")?;
+            self.spilled += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Stream all results back in insertion order, spilled results first
+    pub fn stream(self) -> CodeGrepResult<impl Iterator<Item = CodeGrepResult<FileMatch>>> {
+        let spilled_lines = match self.spill_file {
+            Some(writer) => {
+                let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+                file.seek(SeekFrom::Start(0))?;
+                Some(BufReader::new(file).lines())
+            }
+            None => None,
+        };
+
+        let spilled = spilled_lines.into_iter().flatten().map(|line| {
+            let line = line?;
+            serde_json::from_str::<FileMatch>(&line)
+                .map_err(|e| CodeGrepError::Search(format!("Failed to read spilled results: {}", e)))
+        });
+
+        Ok(spilled.chain(self.buffer.into_iter().map(Ok)))
+    }
+
+    /// Load every result back into memory
+    pub fn into_vec(self) -> CodeGrepResult<Vec<FileMatch>> {
+        self.stream()?.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_spool_preserves_order() {
+        let mut spool = ResultSpool::new(2);
+        for i in 0..5 {
+            spool.push(FileMatch::new(PathBuf::from(format!("file{}.txt", i)), vec![])).unwrap();
+        }
+
+        assert_eq!(spool.len(), 5);
+        assert!(spool.spilled() > 0);
+
+        let paths: Vec<_> = spool.into_vec().unwrap().into_iter().map(|fm| fm.path).collect();
+        let expected: Vec<_> = (0..5).map(|i| PathBuf::from(format!("file{}.txt", i))).collect();
+        assert_eq!(paths, expected);
+    }
+}
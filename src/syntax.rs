@@ -0,0 +1,98 @@
+use std::ops::Range;
+use std::path::Path;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, Style, Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use termcolor::{Color, ColorSpec};
+
+/// Bundled syntect theme used for `--color-syntax`
+const SYNTAX_THEME: &str = "base16-ocean.dark";
+
+/// `--color-syntax` のシンタックスハイライト（`syntax-highlight` feature が必要）
+///
+/// 出力する行はファイル内で連続しないため、各行を単独でハイライトする。
+/// 複数行にまたがるコメントや文字列の途中の行は、通常のコードとして色付けされる。
+pub struct SyntaxHighlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    /// Syntax of the file being printed; `None` when its language is unknown
+    current: Option<SyntaxReference>,
+}
+
+impl Default for SyntaxHighlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyntaxHighlighter {
+    pub fn new() -> Self {
+        let mut themes = ThemeSet::load_defaults().themes;
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes.remove(SYNTAX_THEME).unwrap_or_default(),
+            current: None,
+        }
+    }
+
+    /// Pick the syntax for the lines of `path` printed next, by extension
+    pub fn set_file(&mut self, path: &Path) {
+        self.current = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.syntaxes.find_syntax_by_extension(ext))
+            .cloned();
+    }
+
+    /// Byte ranges of `line` with the color each is drawn in, or `None` when the current file's
+    /// language is unknown and the line is printed as usual
+    pub fn regions(&self, line: &str) -> Option<Vec<(ColorSpec, Range<usize>)>> {
+        let syntax = self.current.as_ref()?;
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let styled = highlighter.highlight_line(line, &self.syntaxes).ok()?;
+
+        let mut offset = 0;
+        let regions = styled
+            .into_iter()
+            .map(|(style, text)| {
+                let range = offset..offset + text.len();
+                offset = range.end;
+                (color_spec(style), range)
+            })
+            .collect();
+        Some(regions)
+    }
+}
+
+fn color_spec(style: Style) -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    let fg = style.foreground;
+    spec.set_fg(Some(Color::Rgb(fg.r, fg.g, fg.b)))
+        .set_bold(style.font_style.contains(FontStyle::BOLD))
+        .set_italic(style.font_style.contains(FontStyle::ITALIC))
+        .set_underline(style.font_style.contains(FontStyle::UNDERLINE));
+    spec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regions_cover_line() {
+        let mut highlighter = SyntaxHighlighter::new();
+        let line = "fn main() { let x = \"text\"; }";
+
+        highlighter.set_file(Path::new("notes.unknown-ext"));
+        assert!(highlighter.regions(line).is_none());
+
+        highlighter.set_file(Path::new("main.rs"));
+        let regions = highlighter.regions(line).unwrap();
+        assert_eq!(regions.first().unwrap().1.start, 0);
+        assert_eq!(regions.last().unwrap().1.end, line.len());
+        assert!(regions.windows(2).all(|pair| pair[0].1.end == pair[1].1.start));
+        // Keywords and strings are not drawn in the same color
+        let color_at = |index: usize| regions.iter().find(|(_, range)| range.contains(&index)).unwrap().0.fg().cloned();
+        assert_ne!(color_at(0), color_at(line.find("text").unwrap()));
+    }
+}
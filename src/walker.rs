@@ -1,17 +1,95 @@
-use crate::{Cli, CodeGrepError, CodeGrepResult};
-use ignore::{Walk, WalkBuilder};
+use crate::{is_url, Cli, CodeGrepError, CodeGrepResult, Config, GitAttributes, WalkOrder};
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::WalkBuilder;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Assumed average depth of the tree below each top-level directory, for `estimate_file_count`,
+/// unless the config sets `performance.estimated_depth`
+const ESTIMATED_AVERAGE_DEPTH: usize = 3;
+
+/// Top-level directories read per root by `estimate_file_count`; the rest are extrapolated
+const ESTIMATE_SAMPLE_DIRS: usize = 64;
 
 pub struct FileWalker {
     builder: WalkBuilder,
+    /// Local roots of the walk, kept for `estimate_file_count`
+    roots: Vec<PathBuf>,
+    hidden: bool,
+    max_depth: Option<usize>,
+    /// Average depth `estimate_file_count` assumes below each top-level directory
+    estimated_depth: usize,
+    walk_order: WalkOrder,
+    /// `--verbose` level; at 2 and above every filtered-out file is reported with its reason
+    verbosity: u8,
+    gitattributes: GitAttributes,
+    /// `--glob` patterns; `true` marks `!` exclusions
+    globs: Vec<(bool, PathGlob)>,
+    /// `--type-add` definitions as (type name, glob)
+    type_globs: Vec<(String, PathGlob)>,
+    /// What `--tests-only` / `--no-tests` consider a test file; empty unless one of them is set
+    test_globs: Vec<PathGlob>,
+}
+
+/// Why `FileWalker` left a file out of the search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    Extension,
+    Glob,
+    Type,
+    Test,
+    Size,
+    ModifiedTime,
+    Binary,
+    Ignore,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            SkipReason::Extension => "extension not in --ext",
+            SkipReason::Glob => "excluded by --glob",
+            SkipReason::Type => "not a requested --type",
+            SkipReason::Test => "excluded by --tests-only/--no-tests",
+            SkipReason::Size => "outside --max-filesize/--min-filesize",
+            SkipReason::ModifiedTime => "outside the modification time filters",
+            SkipReason::Binary => "binary",
+            SkipReason::Ignore => "hidden or excluded by an ignore file",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// A `--glob` / `--type-add` glob; patterns without a slash match the file name
+pub(crate) struct PathGlob {
+    glob: GlobMatcher,
+    match_file_name: bool,
 }
 
 impl FileWalker {
     pub fn new(cli: &Cli) -> Self {
-        let mut builder = WalkBuilder::new(".");
-        
-        // Add additional paths
-        for path in &cli.paths {
+        Self::with_config(cli, &Config::default())
+    }
+    
+    /// Like `new`, taking the settings the config file adds to the command line
+    pub fn with_config(cli: &Cli, config: &Config) -> Self {
+        // Start from the first path (or the current directory) and add the rest;
+        // URLs given with --search-network are fetched by the search engine instead
+        let mut roots: Vec<PathBuf> = cli
+            .paths
+            .iter()
+            .filter(|p| !(cli.search_network && is_url(p)))
+            .cloned()
+            .collect();
+        if roots.is_empty() {
+            roots.push(PathBuf::from("."));
+        }
+        let mut builder = WalkBuilder::new(&roots[0]);
+        for path in &roots[1..] {
             builder.add(path);
         }
         
@@ -20,11 +98,21 @@ impl FileWalker {
             .hidden(!cli.hidden)
             .git_ignore(cli.respect_gitignore)
             .git_exclude(cli.respect_gitignore)
+            .git_global(!cli.no_ignore_global)
             .threads(cli.effective_threads())
             .follow_links(false);
         
-        // Set max depth if specified
-        if let Some(depth) = cli.max_depth {
+        // Symlinks given on the command line are always searched. Symlinks to regular files found
+        // while walking are searched too unless --skip-symlinks is set; symlinked directories are
+        // never entered, so link cycles cannot occur.
+        let skip_symlinks = cli.skip_symlinks;
+        builder.filter_entry(move |entry| {
+            entry.depth() == 0 || !entry.path_is_symlink() || (!skip_symlinks && entry.path().is_file())
+        });
+        
+        // Set max depth if specified; --no-recursive is the same as --max-depth 1
+        let max_depth = if cli.no_recursive { Some(1) } else { cli.max_depth };
+        if let Some(depth) = max_depth {
             builder.max_depth(Some(depth));
         }
         
@@ -34,13 +122,55 @@ impl FileWalker {
             // TODO: Implement custom ignore patterns
         }
         
-        Self { builder }
+        // Invalid globs are rejected up front by `Cli::validate`
+        let globs = cli
+            .glob
+            .iter()
+            .filter_map(|glob| match glob.strip_prefix('!') {
+                Some(exclude) => PathGlob::new(exclude).ok().map(|glob| (true, glob)),
+                None => PathGlob::new(glob).ok().map(|glob| (false, glob)),
+            })
+            .collect();
+        let type_globs = cli
+            .type_add
+            .iter()
+            .filter_map(|definition| parse_type_add(definition).ok())
+            .collect();
+        
+        // Test file patterns come from the config file the caller loaded
+        let test_globs = if cli.tests_only || cli.no_tests {
+            config.default.test_patterns.iter().filter_map(|pattern| PathGlob::new(pattern).ok()).collect()
+        } else {
+            Vec::new()
+        };
+        
+        Self {
+            builder,
+            roots,
+            hidden: cli.hidden,
+            max_depth,
+            estimated_depth: config.default.performance.estimated_depth.unwrap_or(ESTIMATED_AVERAGE_DEPTH),
+            walk_order: cli.walk_order,
+            verbosity: cli.verbose,
+            gitattributes: GitAttributes::new(),
+            globs,
+            type_globs,
+            test_globs,
+        }
     }
     
+    /// Files the walk finds, in `--walk-order`
     pub fn walk(&self) -> Vec<CodeGrepResult<PathBuf>> {
         let mut results = Vec::new();
+        // With --walk-order bfs, the entries found directly inside each directory
+        let mut children: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
         for entry in self.builder.build() {
             match entry {
+                Ok(entry) if self.walk_order == WalkOrder::Bfs && entry.depth() > 0 => {
+                    if let Some(parent) = entry.path().parent() {
+                        children.entry(parent.to_path_buf()).or_default().push(entry.into_path());
+                    }
+                }
                 Ok(entry) => {
                     let path = entry.path();
                     if path.is_file() {
@@ -50,31 +180,241 @@ impl FileWalker {
                 }
                 Err(e) => {
                     // Convert ignore::Error to std::io::Error
-                    let io_error = std::io::Error::new(std::io::ErrorKind::Other, e);
+                    let io_error = std::io::Error::other(e);
                     results.push(Err(CodeGrepError::Io(io_error)));
                 }
             }
         }
+        if self.walk_order == WalkOrder::Bfs {
+            results.extend(self.breadth_first(children).into_iter().map(Ok));
+        }
         results
     }
     
+    /// The files in `children` level by level, starting from the roots: each directory's files in
+    /// path order, with its subdirectories queued behind the directories already waiting.
+    /// The ignore crate only walks depth first, so its entries are collected and reordered here.
+    fn breadth_first(&self, mut children: HashMap<PathBuf, Vec<PathBuf>>) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        let mut queue: VecDeque<PathBuf> = self.roots.iter().filter(|root| root.is_dir()).cloned().collect();
+        while let Some(dir) = queue.pop_front() {
+            let mut entries = children.remove(&dir).unwrap_or_default();
+            entries.sort();
+            for entry in entries {
+                if entry.is_dir() {
+                    queue.push_back(entry);
+                } else if entry.is_file() {
+                    files.push(entry);
+                }
+            }
+        }
+        files
+    }
+    
+    /// Rough number of files the walk will visit, cheap enough to compute before walking.
+    /// Only each root and its top-level directories are listed (sampling at most
+    /// `ESTIMATE_SAMPLE_DIRS` of them); deeper levels are assumed to hold as many entries as the
+    /// top-level directories do, `ESTIMATED_AVERAGE_DEPTH` times over. Ignore files are not read.
+    pub fn estimate_file_count(&self) -> usize {
+        let max_depth = self.max_depth.unwrap_or(usize::MAX);
+        self.roots
+            .iter()
+            .map(|root| {
+                if !root.is_dir() {
+                    return usize::from(root.exists());
+                }
+                if max_depth == 0 {
+                    return 0;
+                }
+                
+                let (files, dirs) = self.list_dir(root);
+                if max_depth == 1 || dirs.is_empty() {
+                    return files;
+                }
+                
+                // Entries per top-level directory, from an evenly spaced sample of them
+                let step = dirs.len().div_ceil(ESTIMATE_SAMPLE_DIRS);
+                let sampled: Vec<usize> = dirs
+                    .iter()
+                    .step_by(step)
+                    .map(|dir| {
+                        let (files, subdirs) = self.list_dir(dir);
+                        files + subdirs.len()
+                    })
+                    .collect();
+                let average_entries = sampled.iter().sum::<usize>() as f64 / sampled.len() as f64;
+                let depth = self.estimated_depth.min(max_depth - 1);
+                
+                files + (dirs.len() as f64 * average_entries * depth as f64).round() as usize
+            })
+            .sum()
+    }
+    
+    /// Number of files and the subdirectories directly inside `dir`, skipping hidden entries like the walk does
+    fn list_dir(&self, dir: &Path) -> (usize, Vec<PathBuf>) {
+        let mut files = 0;
+        let mut dirs = Vec::new();
+        let Ok(entries) = fs::read_dir(dir) else {
+            return (0, dirs);
+        };
+        for entry in entries.flatten() {
+            if !self.hidden && entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => dirs.push(entry.path()),
+                Ok(file_type) if file_type.is_file() => files += 1,
+                _ => {}
+            }
+        }
+        (files, dirs)
+    }
+    
+    /// Local files a search would read, after all filters, in path order
+    pub fn included_files(&self, cli: &Cli) -> CodeGrepResult<Vec<PathBuf>> {
+        self.included_files_logged(cli, &mut io::stderr())
+    }
+    
+    /// `included_files`, writing each skipped file and its reason to `log` at `-VV`
+    fn included_files_logged(&self, cli: &Cli, log: &mut dyn Write) -> CodeGrepResult<Vec<PathBuf>> {
+        if self.verbosity >= 2 && !cli.git_tracked {
+            for path in self.ignored_entries() {
+                writeln!(log, "Skipping {}: {}", path.display(), SkipReason::Ignore)?;
+            }
+        }
+        
+        let candidates: Vec<PathBuf> = if cli.git_tracked {
+            Self::git_tracked_files(cli)?
+        } else {
+            self.walk()
+                .into_iter()
+                .filter_map(|path_result| path_result.ok())
+                .collect()
+        };
+        
+        let mut files = Vec::new();
+        for path in candidates {
+            match self.skip_reason(&path, cli) {
+                None => files.push(path),
+                Some(reason) if self.verbosity >= 2 => writeln!(log, "Skipping {}: {}", path.display(), reason)?,
+                Some(_) => {}
+            }
+        }
+        match self.walk_order {
+            WalkOrder::Dfs => files.sort(),
+            // git ls-files output has not been through the breadth-first walk
+            WalkOrder::Bfs if cli.git_tracked => files.sort_by_key(|path| (path.components().count(), path.clone())),
+            WalkOrder::Bfs => {}
+        }
+        Ok(files)
+    }
+    
+    /// Entries the walk never yields because they are hidden or match an ignore file, found by
+    /// walking again without those filters. Below an ignored directory only the directory is listed.
+    fn ignored_entries(&self) -> Vec<PathBuf> {
+        let walked: HashSet<PathBuf> = self.builder.build().flatten().map(|entry| entry.into_path()).collect();
+        let mut unfiltered = self.builder.clone();
+        unfiltered.standard_filters(false);
+        let mut ignored: Vec<PathBuf> = unfiltered
+            .build()
+            .flatten()
+            .map(|entry| entry.into_path())
+            .filter(|path| !walked.contains(path))
+            .collect();
+        ignored.sort();
+        ignored.dedup_by(|path, dir| path.starts_with(dir));
+        ignored
+    }
+    
+    /// List the files tracked by git under each search path, bypassing the normal walk
+    pub fn git_tracked_files(cli: &Cli) -> CodeGrepResult<Vec<PathBuf>> {
+        let default_paths = [PathBuf::from(".")];
+        let roots = if cli.paths.is_empty() { &default_paths[..] } else { &cli.paths[..] };
+        
+        let mut files = Vec::new();
+        for root in roots {
+            // `git ls-files` needs a directory to run in; a file root lists just that file
+            let (dir, pathspec) = if root.is_file() {
+                let parent = root.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+                (parent.to_path_buf(), root.file_name().map(PathBuf::from))
+            } else {
+                (root.clone(), None)
+            };
+            
+            let mut command = std::process::Command::new("git");
+            command.arg("-C").arg(&dir).args(["ls-files", "-z", "--"]);
+            if let Some(ref pathspec) = pathspec {
+                command.arg(pathspec);
+            }
+            
+            let output = command.output().map_err(|e| {
+                CodeGrepError::Search(format!("--git-tracked: failed to run git: {}", e))
+            })?;
+            if !output.status.success() {
+                return Err(CodeGrepError::Search(format!(
+                    "--git-tracked: {} is not inside a git repository: {}",
+                    root.display(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )));
+            }
+            
+            for name in output.stdout.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+                let path = dir.join(String::from_utf8_lossy(name).as_ref());
+                // Tracked files may have been deleted from the working tree
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+        }
+        
+        Ok(files)
+    }
+    
+    /// Whether `path` matches a test file pattern. Directory patterns such as `**/tests/**` only
+    /// look below the search root, so a checkout inside a `test` directory is not all tests.
+    pub fn is_test_file(&self, path: &Path) -> bool {
+        let relative = self
+            .roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .filter(|relative| !relative.as_os_str().is_empty())
+            .unwrap_or(path);
+        self.test_globs.iter().any(|glob| glob.is_match(relative))
+    }
+    
     pub fn should_include_file(&self, path: &Path, cli: &Cli) -> bool {
+        self.skip_reason(path, cli).is_none()
+    }
+    
+    /// Which filter, if any, excludes `path` from the search
+    pub fn skip_reason(&self, path: &Path, cli: &Cli) -> Option<SkipReason> {
         // Check file extensions
         if !cli.ext.is_empty() {
             if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
                 if !cli.ext.iter().any(|e| e == ext) {
-                    return false;
+                    return Some(SkipReason::Extension);
                 }
             } else {
-                return false;
+                return Some(SkipReason::Extension);
+            }
+        }
+        
+        // Check --glob: exclusions always win, and any inclusion turns the globs into a whitelist
+        if !self.globs.is_empty() {
+            if self.globs.iter().any(|(exclude, glob)| *exclude && glob.is_match(path)) {
+                return Some(SkipReason::Glob);
+            }
+            let mut includes = self.globs.iter().filter(|(exclude, _)| !exclude).peekable();
+            if includes.peek().is_some() && !includes.any(|(_, glob)| glob.is_match(path)) {
+                return Some(SkipReason::Glob);
             }
         }
         
-        // Check file types (basic mapping)
+        // Check file types (basic mapping plus --type-add definitions)
         if !cli.r#type.is_empty() {
             let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
             let matches_type = cli.r#type.iter().any(|t| {
-                match t.as_str() {
+                let builtin = match t.as_str() {
                     "rust" | "rs" => ext == "rs",
                     "go" => ext == "go",
                     "js" | "javascript" => ext == "js",
@@ -92,19 +432,34 @@ impl FileWalker {
                     "md" | "markdown" => matches!(ext, "md" | "markdown"),
                     "txt" | "text" => ext == "txt",
                     _ => false,
-                }
+                };
+                builtin || self.type_globs.iter().any(|(name, glob)| name == t && glob.is_match(path))
             });
             if !matches_type {
-                return false;
+                return Some(SkipReason::Type);
             }
         }
         
+        // Check --tests-only / --no-tests
+        if (cli.tests_only || cli.no_tests) && self.is_test_file(path) != cli.tests_only {
+            return Some(SkipReason::Test);
+        }
+        
         // Check file size
         if let Some(max_size_str) = &cli.max_filesize {
             if let Ok(metadata) = std::fs::metadata(path) {
                 let max_size = parse_size(max_size_str).unwrap_or(u64::MAX);
                 if metadata.len() > max_size {
-                    return false;
+                    return Some(SkipReason::Size);
+                }
+            }
+        }
+        
+        if let Some(min_size_str) = &cli.min_filesize {
+            if let Ok(metadata) = std::fs::metadata(path) {
+                let min_size = parse_size(min_size_str).unwrap_or(0);
+                if metadata.len() < min_size {
+                    return Some(SkipReason::Size);
                 }
             }
         }
@@ -116,21 +471,70 @@ impl FileWalker {
                     let duration = parse_duration(within_str).unwrap_or(std::time::Duration::MAX);
                     if let Ok(elapsed) = modified.elapsed() {
                         if elapsed > duration {
-                            return false;
+                            return Some(SkipReason::ModifiedTime);
                         }
                     }
                 }
             }
         }
         
-        // Check if binary (basic heuristic)
+        // Absolute modification dates (validated up front by `Cli::validate`)
+        if cli.modified_after.is_some() || cli.modified_before.is_some() {
+            if let Ok(modified) = std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+                let after = cli.modified_after.as_deref().and_then(|date| parse_datetime(date).ok());
+                let before = cli.modified_before.as_deref().and_then(|date| parse_datetime(date).ok());
+                if after.is_some_and(|after| modified <= after) || before.is_some_and(|before| modified >= before) {
+                    return Some(SkipReason::ModifiedTime);
+                }
+            }
+        }
+        
+        // Check if binary: `.gitattributes` first, then the null-byte heuristic
         if !cli.binary {
-            if is_binary_file(path) {
-                return false;
+            let binary = self
+                .gitattributes
+                .is_binary(path)
+                .unwrap_or_else(|| is_binary_file(path));
+            if binary {
+                return Some(SkipReason::Binary);
             }
         }
         
-        true
+        None
+    }
+}
+
+impl PathGlob {
+    pub(crate) fn new(pattern: &str) -> CodeGrepResult<Self> {
+        let glob = GlobBuilder::new(pattern.trim_start_matches("./"))
+            .literal_separator(true)
+            .build()
+            .map_err(|e| CodeGrepError::Config(format!("Invalid glob '{}': {}", pattern, e)))?
+            .compile_matcher();
+        
+        Ok(Self {
+            glob,
+            match_file_name: !pattern.contains('/'),
+        })
+    }
+    
+    fn is_match(&self, path: &Path) -> bool {
+        if self.match_file_name {
+            path.file_name().is_some_and(|name| self.glob.is_match(name))
+        } else {
+            self.glob.is_match(path.strip_prefix(".").unwrap_or(path))
+        }
+    }
+}
+
+/// Parse a `--type-add NAME:GLOB` definition
+pub(crate) fn parse_type_add(definition: &str) -> CodeGrepResult<(String, PathGlob)> {
+    match definition.split_once(':') {
+        Some((name, glob)) if !name.is_empty() && !glob.is_empty() => Ok((name.to_string(), PathGlob::new(glob)?)),
+        _ => Err(CodeGrepError::Config(format!(
+            "Invalid --type-add '{}' (expected NAME:GLOB)",
+            definition
+        ))),
     }
 }
 
@@ -164,6 +568,25 @@ fn parse_duration(duration_str: &str) -> Option<std::time::Duration> {
     }
 }
 
+/// Parse an RFC3339 timestamp (`2024-05-01T12:00:00+09:00`) or a plain date taken as UTC midnight
+pub(crate) fn parse_datetime(date_str: &str) -> CodeGrepResult<SystemTime> {
+    let date_str = date_str.trim();
+    
+    let datetime = match chrono::DateTime::parse_from_rfc3339(date_str) {
+        Ok(datetime) => datetime.with_timezone(&chrono::Utc),
+        Err(rfc3339_error) => chrono::NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+            .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc())
+            .map_err(|_| {
+                CodeGrepError::Config(format!(
+                    "Invalid date '{}': {} (expected RFC3339 or YYYY-MM-DD)",
+                    date_str, rfc3339_error
+                ))
+            })?,
+    };
+    
+    Ok(datetime.into())
+}
+
 fn is_binary_file(path: &Path) -> bool {
     // Basic binary file detection
     if let Ok(mut file) = std::fs::File::open(path) {
@@ -196,4 +619,308 @@ mod tests {
         assert_eq!(parse_duration("1h"), Some(std::time::Duration::from_secs(3600)));
         assert_eq!(parse_duration("1d"), Some(std::time::Duration::from_secs(86400)));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_git_tracked_outside_repo() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cli = Cli {
+            git_tracked: true,
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        assert!(FileWalker::git_tracked_files(&cli).is_err());
+    }
+    
+    #[test]
+    fn test_parse_datetime() {
+        let utc = parse_datetime("2024-05-01T00:00:00Z").unwrap();
+        assert_eq!(parse_datetime("2024-05-01").unwrap(), utc);
+        assert_eq!(parse_datetime("2024-05-01T09:00:00+09:00").unwrap(), utc);
+        assert!(parse_datetime("2024-13-01").is_err());
+        assert!(parse_datetime("yesterday").is_err());
+    }
+    
+    #[test]
+    fn test_filter_by_modification_date() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let old_file = temp_dir.path().join("old.txt");
+        let new_file = temp_dir.path().join("new.txt");
+        for (path, date) in [(&old_file, "2020-01-01T00:00:00Z"), (&new_file, "2024-01-01T00:00:00Z")] {
+            let file = std::fs::File::create(path).unwrap();
+            file.set_modified(parse_datetime(date).unwrap()).unwrap();
+        }
+        
+        let cli = Cli {
+            modified_after: Some("2022-06-01".to_string()),
+            ..Default::default()
+        };
+        let walker = FileWalker::new(&cli);
+        assert!(walker.should_include_file(&new_file, &cli));
+        assert!(!walker.should_include_file(&old_file, &cli));
+        
+        let cli = Cli {
+            modified_before: Some("2022-06-01T00:00:00+09:00".to_string()),
+            ..Default::default()
+        };
+        assert!(walker.should_include_file(&old_file, &cli));
+        assert!(!walker.should_include_file(&new_file, &cli));
+        
+        let cli = Cli {
+            modified_after: Some("2024-01-01".to_string()),
+            modified_before: Some("2020-01-01".to_string()),
+            ..Default::default()
+        };
+        assert!(cli.validate().is_err());
+    }
+    
+    #[test]
+    fn test_gitattributes_binary_takes_precedence() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join(".gitattributes"), "*.dat binary\n*.utf16 text\n").unwrap();
+        std::fs::write(root.join("data.dat"), "plain text without null bytes").unwrap();
+        std::fs::write(root.join("notes.utf16"), b"h\0i\0").unwrap();
+        std::fs::write(root.join("notes.txt"), "plain text").unwrap();
+        
+        let cli = Cli::default();
+        let walker = FileWalker::new(&cli);
+        assert!(!walker.should_include_file(&root.join("data.dat"), &cli));
+        assert!(walker.should_include_file(&root.join("notes.utf16"), &cli));
+        assert!(walker.should_include_file(&root.join("notes.txt"), &cli));
+        
+        let cli = Cli { binary: true, ..Default::default() };
+        assert!(walker.should_include_file(&root.join("data.dat"), &cli));
+    }
+    
+    #[test]
+    fn test_no_recursive() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        std::fs::write(temp_dir.path().join("top.txt"), "top").unwrap();
+        std::fs::write(temp_dir.path().join("sub/nested.txt"), "nested").unwrap();
+        
+        let walked = |cli: Cli| -> Vec<PathBuf> {
+            FileWalker::new(&cli).walk().into_iter().filter_map(|path| path.ok()).collect()
+        };
+        let base = Cli {
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        
+        assert_eq!(walked(base.clone()).len(), 2);
+        assert_eq!(
+            walked(Cli { no_recursive: true, ..base.clone() }),
+            vec![temp_dir.path().join("top.txt")]
+        );
+        assert_eq!(
+            walked(Cli { max_depth: Some(1), ..base.clone() }),
+            walked(Cli { no_recursive: true, ..base.clone() })
+        );
+        assert!(walked(Cli { max_depth: Some(0), ..base }).is_empty());
+    }
+    
+    #[test]
+    fn test_estimate_file_count() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for dir in ["a", "b", "c"] {
+            std::fs::create_dir_all(temp_dir.path().join(dir).join("deep")).unwrap();
+            for i in 0..4 {
+                std::fs::write(temp_dir.path().join(dir).join(format!("{}.rs", i)), "").unwrap();
+                std::fs::write(temp_dir.path().join(dir).join("deep").join(format!("{}.rs", i)), "").unwrap();
+            }
+        }
+        std::fs::write(temp_dir.path().join("top.rs"), "").unwrap();
+        std::fs::write(temp_dir.path().join(".hidden"), "").unwrap();
+        
+        let base = Cli {
+            paths: vec![temp_dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        let estimate = |cli: Cli| FileWalker::new(&cli).estimate_file_count();
+        
+        // 1 top-level file + 3 directories of 5 entries each, over the assumed depth
+        assert_eq!(estimate(base.clone()), 1 + 3 * 5 * ESTIMATED_AVERAGE_DEPTH);
+        assert_eq!(estimate(Cli { max_depth: Some(1), ..base.clone() }), 1);
+        assert_eq!(estimate(Cli { max_depth: Some(2), ..base.clone() }), 1 + 3 * 5);
+        assert_eq!(estimate(Cli { paths: vec![temp_dir.path().join("top.rs")], ..base.clone() }), 1);
+        
+        let mut config = Config::default();
+        config.default.performance.estimated_depth = Some(1);
+        assert_eq!(FileWalker::with_config(&base, &config).estimate_file_count(), 1 + 3 * 5);
+    }
+    
+    #[test]
+    fn test_verbose_reports_skip_reasons() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("small.txt"), "small").unwrap();
+        std::fs::write(temp_dir.path().join("large.txt"), "x".repeat(4096)).unwrap();
+        
+        let cli = Cli {
+            paths: vec![temp_dir.path().to_path_buf()],
+            max_filesize: Some("1k".to_string()),
+            verbose: 2,
+            ..Default::default()
+        };
+        let mut log = Vec::new();
+        let files = FileWalker::new(&cli).included_files_logged(&cli, &mut log).unwrap();
+        
+        assert_eq!(files, vec![temp_dir.path().join("small.txt")]);
+        let log = String::from_utf8(log).unwrap();
+        assert_eq!(
+            log,
+            format!("Skipping {}: {}\n", temp_dir.path().join("large.txt").display(), SkipReason::Size)
+        );
+        
+        // Level 1 does not list skipped files
+        let quiet = Cli { verbose: 1, ..cli };
+        let mut log = Vec::new();
+        FileWalker::new(&quiet).included_files_logged(&quiet, &mut log).unwrap();
+        assert!(log.is_empty());
+    }
+    
+    #[test]
+    fn test_verbose_reports_ignored_entries() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir(root.join("build")).unwrap();
+        std::fs::write(root.join("build/out.txt"), "out").unwrap();
+        std::fs::write(root.join("build/more.txt"), "more").unwrap();
+        std::fs::write(root.join("kept.txt"), "kept").unwrap();
+        std::fs::write(root.join(".ignore"), "build/\n").unwrap();
+        
+        let cli = Cli { paths: vec![root.to_path_buf()], verbose: 2, ..Default::default() };
+        let mut log = Vec::new();
+        let files = FileWalker::new(&cli).included_files_logged(&cli, &mut log).unwrap();
+        
+        assert_eq!(files, vec![root.join("kept.txt")]);
+        // The hidden .ignore file and the directory it excludes, but not the files inside it
+        let expected: String = [root.join(".ignore"), root.join("build")]
+            .iter()
+            .map(|path| format!("Skipping {}: {}\n", path.display(), SkipReason::Ignore))
+            .collect();
+        assert_eq!(String::from_utf8(log).unwrap(), expected);
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_include_symlinks_as_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir(root.join("real")).unwrap();
+        std::fs::write(root.join("real/a.txt"), "a").unwrap();
+        std::os::unix::fs::symlink(root.join("real/a.txt"), root.join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(root.join("real"), root.join("linkdir")).unwrap();
+        
+        let walked = |skip_symlinks| -> Vec<PathBuf> {
+            let cli = Cli {
+                paths: vec![root.to_path_buf()],
+                skip_symlinks,
+                ..Default::default()
+            };
+            let mut paths: Vec<_> = FileWalker::new(&cli).walk().into_iter().filter_map(|path| path.ok()).collect();
+            paths.sort();
+            paths
+        };
+        
+        assert_eq!(walked(false), vec![root.join("link.txt"), root.join("real/a.txt")]);
+        assert_eq!(walked(true), vec![root.join("real/a.txt")]);
+    }
+    
+    #[test]
+    fn test_included_files_respects_type() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for name in ["b.rs", "a.rs", "c.py", "d.txt"] {
+            std::fs::write(temp_dir.path().join(name), "content").unwrap();
+        }
+        
+        let cli = Cli {
+            paths: vec![temp_dir.path().to_path_buf()],
+            r#type: vec!["rust".to_string()],
+            list_files: true,
+            ..Default::default()
+        };
+        let files = FileWalker::new(&cli).included_files(&cli).unwrap();
+        assert_eq!(files, vec![temp_dir.path().join("a.rs"), temp_dir.path().join("b.rs")]);
+    }
+    
+    #[test]
+    fn test_glob_and_type_add_filters() {
+        let cli = Cli {
+            glob: vec!["*.rs".to_string(), "!src/generated/**".to_string()],
+            ..Default::default()
+        };
+        let walker = FileWalker::new(&cli);
+        assert!(walker.should_include_file(Path::new("./src/main.rs"), &cli));
+        assert!(!walker.should_include_file(Path::new("./src/generated/types.rs"), &cli));
+        assert!(!walker.should_include_file(Path::new("README.md"), &cli));
+        
+        let cli = Cli {
+            r#type: vec!["web".to_string()],
+            type_add: vec!["web:*.html".to_string()],
+            ..Default::default()
+        };
+        let walker = FileWalker::new(&cli);
+        assert!(walker.should_include_file(Path::new("index.html"), &cli));
+        assert!(!walker.should_include_file(Path::new("main.rs"), &cli));
+        assert!(parse_type_add("web").is_err());
+    }
+    
+    #[test]
+    fn test_walk_order_bfs() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("a/deep")).unwrap();
+        std::fs::create_dir(root.join("b")).unwrap();
+        for name in ["a/deep/1.txt", "a/2.txt", "b/3.txt", "z.txt"] {
+            std::fs::write(root.join(name), "content").unwrap();
+        }
+        
+        let included = |walk_order| {
+            let cli = Cli { paths: vec![root.to_path_buf()], walk_order, ..Default::default() };
+            FileWalker::new(&cli).included_files(&cli).unwrap()
+        };
+        let paths = |names: &[&str]| names.iter().map(|name| root.join(name)).collect::<Vec<_>>();
+        
+        assert_eq!(included(WalkOrder::Dfs), paths(&["a/2.txt", "a/deep/1.txt", "b/3.txt", "z.txt"]));
+        assert_eq!(included(WalkOrder::Bfs), paths(&["z.txt", "a/2.txt", "b/3.txt", "a/deep/1.txt"]));
+    }
+    
+    #[test]
+    fn test_tests_only_and_no_tests() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("tests")).unwrap();
+        for name in ["foo_test.go", "test_foo.py", "app.spec.ts", "tests/integration.rs", "foo.go", "foo.py"] {
+            std::fs::write(root.join(name), "content").unwrap();
+        }
+        
+        let cli = Cli { paths: vec![root.to_path_buf()], tests_only: true, ..Default::default() };
+        let walker = FileWalker::new(&cli);
+        assert!(walker.is_test_file(&root.join("foo_test.go")));
+        assert!(walker.is_test_file(&root.join("test_foo.py")));
+        assert!(!walker.is_test_file(&root.join("foo.go")));
+        
+        let included = |cli: Cli| {
+            let names: Vec<String> = FileWalker::new(&cli)
+                .included_files(&cli)
+                .unwrap()
+                .iter()
+                .map(|path| path.strip_prefix(root).unwrap().to_string_lossy().into_owned())
+                .collect();
+            names
+        };
+        assert_eq!(included(cli.clone()), ["app.spec.ts", "foo_test.go", "test_foo.py", "tests/integration.rs"]);
+        assert_eq!(included(Cli { tests_only: false, no_tests: true, ..cli.clone() }), ["foo.go", "foo.py"]);
+        
+        // The patterns come from the config the caller passes in
+        let mut config = Config::default();
+        config.default.test_patterns = vec!["*.spec.ts".to_string()];
+        let spec_only: Vec<_> = FileWalker::with_config(&cli, &config)
+            .included_files(&cli)
+            .unwrap()
+            .into_iter()
+            .map(|path| path.strip_prefix(root).unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(spec_only, ["app.spec.ts"]);
+    }
+}
@@ -0,0 +1,102 @@
+//! Allocation checks, in their own test binary so the counting allocator only wraps these tests
+
+use code_grep::{count_in_text, find_in_text, Cli, FileMatch, LineMatch, Match, PatternMatcher, Replacer};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fs;
+
+/// Global allocator that tracks what the current thread allocates
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static LIVE_BYTES: Cell<usize> = const { Cell::new(0) };
+    static PEAK_BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        let live = LIVE_BYTES.with(|live| {
+            live.set(live.get() + layout.size());
+            live.get()
+        });
+        PEAK_BYTES.with(|peak| peak.set(peak.get().max(live)));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Memory freed by another thread than the one that allocated it may underflow
+        LIVE_BYTES.with(|live| live.set(live.get().saturating_sub(layout.size())));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Number of allocations the current thread makes while running `f`
+fn allocations_during<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+/// Most bytes the current thread held at once while running `f`, beyond what it held before
+fn peak_bytes_during<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = LIVE_BYTES.with(Cell::get);
+    PEAK_BYTES.with(|peak| peak.set(before));
+    let result = f();
+    (result, PEAK_BYTES.with(Cell::get) - before)
+}
+
+#[test]
+fn test_count_in_text_matches_find_in_text_without_copying() {
+    let cli = Cli {
+        pattern: Some("ab".to_string()),
+        ..Default::default()
+    };
+    let matcher = PatternMatcher::new(&cli).unwrap();
+    let text = format!("{}\nnone\n{}", "ab ".repeat(200), "AB ab");
+
+    let (line_matches, full_allocations) = allocations_during(|| find_in_text(&text, &matcher, None));
+    let (line_counts, count_allocations) = allocations_during(|| count_in_text(&text, &matcher, None, false));
+
+    let expected: Vec<(usize, usize)> = line_matches.iter().map(|lm| (lm.line_number, lm.matches.len())).collect();
+    assert_eq!(line_counts, expected);
+    assert_eq!(line_counts, vec![(1, 200), (3, 2)]);
+
+    // One String per match on the full path; counting allocates per line only
+    assert!(full_allocations >= 202);
+    assert!(count_allocations < 20, "count path made {} allocations", count_allocations);
+}
+
+#[test]
+fn test_stream_replace_large_file() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("large.txt");
+    let line_count = 100_000;
+    let line_for = |n: usize, word: &str| {
+        if n.is_multiple_of(10_000) { format!("{} at line {}\r\n", word, n) } else { format!("filler line {}\n", n) }
+    };
+    let original: String = (1..=line_count).map(|n| line_for(n, "needle")).collect();
+    fs::write(&path, &original).unwrap();
+
+    let needle = || vec![Match { start: 0, end: 6, text: "needle".to_string(), captures: Vec::new() }];
+    let file_match = FileMatch::new(
+        path.clone(),
+        (1..=line_count)
+            .filter(|n| n.is_multiple_of(10_000))
+            .map(|n| LineMatch::new(n, format!("needle at line {}", n), needle()))
+            .collect(),
+    );
+    let cli = Cli { write: true, ..Default::default() };
+    let replacer = Replacer::new(PatternMatcher::Literal("needle".to_string()), "thread".to_string(), cli);
+
+    let (replacements, peak_bytes) = peak_bytes_during(|| replacer.stream_replace_file(&file_match));
+    assert_eq!(replacements.unwrap(), 10);
+    let expected: String = (1..=line_count).map(|n| line_for(n, "thread")).collect();
+    assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+    assert!(original.len() > 1024 * 1024);
+    assert!(peak_bytes < 128 * 1024, "streaming held {} bytes", peak_bytes);
+}